@@ -0,0 +1,91 @@
+//! Compares the stores of a `FlowArena` on a tree of 8 children per node.
+//!
+//! Run with `cargo bench -p flow_arena`; add `--features persistent` to
+//! compare against `im::HashMap` instead of `HashMap`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use flow_arena::{
+    Flow, FlowArena, FlowNode, SlabArena, TraverseMode, TraverseOrder,
+};
+
+type Map = FlowArena<u64, FlowNode<u64, ()>>;
+type Slab = SlabArena<u64, ()>;
+
+const SIZES: [u64; 2] = [1_000, 10_000];
+
+/// node i is owned by node (i - 1) / 8.
+fn build<F>(n: u64) -> F
+where
+    F: Flow<Id = u64, Node = FlowNode<u64, ()>> + Default,
+{
+    let mut flow = F::default();
+    for i in 0..n {
+        flow.grow(FlowNode::from_id(i, ())).expect("grow");
+        if i > 0 {
+            flow.devote_push(&i, &((i - 1) / 8)).expect("devote");
+        }
+    }
+    flow
+}
+
+fn bench<F>(c: &mut Criterion, name: &str)
+where
+    F: Flow<Id = u64, Node = FlowNode<u64, ()>> + Default + Clone,
+{
+    for n in SIZES {
+        let flow: F = build(n);
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/build", name), n),
+            &n,
+            |b, &n| b.iter(|| build::<F>(n)),
+        );
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/traverse", name), n),
+            &flow,
+            |b, flow| {
+                b.iter(|| {
+                    flow.traverse(
+                        &0,
+                        TraverseOrder::PreOrder,
+                        TraverseMode::Owned,
+                    )
+                    .count()
+                })
+            },
+        );
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/ownership", name), n),
+            &flow,
+            |b, flow| b.iter(|| flow.node_ownership_set(&0).len()),
+        );
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/check", name), n),
+            &flow,
+            |b, flow| b.iter(|| flow.check().expect("check")),
+        );
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/clone", name), n),
+            &flow,
+            |b, flow| b.iter(|| flow.clone()),
+        );
+        c.bench_with_input(
+            BenchmarkId::new(format!("{}/erase", name), n),
+            &flow,
+            |b, flow| {
+                b.iter_batched(
+                    || flow.clone(),
+                    |mut flow| flow.erase(&1).expect("erase"),
+                    criterion::BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+}
+
+fn stores(c: &mut Criterion) {
+    bench::<Map>(c, "map");
+    bench::<Slab>(c, "slab");
+}
+
+criterion_group!(benches, stores);
+criterion_main!(benches);
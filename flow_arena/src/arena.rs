@@ -9,6 +9,19 @@ use std::{
 
 use super::*;
 
+#[cfg(not(feature = "persistent"))]
+use std::collections::hash_map::{IterMut, Values};
+/// the storage of all nodes; a plain `HashMap` by default.
+#[cfg(not(feature = "persistent"))]
+pub(crate) type NodeMap<Id, FlowNode> = HashMap<Id, FlowNode>;
+
+#[cfg(feature = "persistent")]
+use im::hashmap::{IterMut, Values};
+/// the storage of all nodes; a structurally shared `im::HashMap` under
+/// feature `persistent`, which clones in O(1).
+#[cfg(feature = "persistent")]
+pub(crate) type NodeMap<Id, FlowNode> = im::HashMap<Id, FlowNode>;
+
 #[derive(Clone, Default, PartialEq)]
 #[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
 pub struct FlowNode<Id, Entity> {
@@ -17,6 +30,15 @@ pub struct FlowNode<Id, Entity> {
     /// indicates ownership.
     parent: Option<Id>,
     children: Vec<Id>,
+    /// the position keys of the children, if any are kept.
+    #[cfg_attr(
+        feature = "serde_impl",
+        serde(
+            default = "Positions::default",
+            skip_serializing_if = "Positions::is_empty"
+        )
+    )]
+    positions: Positions<Id>,
 }
 
 impl<Id, Entity> FlowNode<Id, Entity> {
@@ -26,6 +48,7 @@ impl<Id, Entity> FlowNode<Id, Entity> {
             entity,
             parent: None,
             children: Vec::new(),
+            positions: Positions::default(),
         }
     }
 }
@@ -70,45 +93,243 @@ where
         self.parent = None
     }
 
-    fn children(&self) -> Vec<Id> {
-        self.children.clone()
+    fn children(&self) -> &[Id] {
+        &self.children
     }
 
     fn children_ref_mut(&mut self) -> &mut Vec<Id> {
         &mut self.children
     }
+
+    fn positions(&self) -> Option<&Positions<Id>> {
+        Some(&self.positions)
+    }
+
+    fn positions_mut(&mut self) -> Option<&mut Positions<Id>> {
+        Some(&mut self.positions)
+    }
+}
+
+/// A flow of nodes kept in `Store`; a `NodeMap` by default, see
+/// `NodeStore` for the others.
+#[derive(Clone)]
+pub struct FlowArena<
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store = NodeMap<Id, FlowNode>,
+> {
+    pub(crate) node_map: Store,
+    /// the previous states of the nodes touched in the current transaction;
+    /// None if not in a transaction.
+    pub(crate) journal: Option<HashMap<Id, Option<FlowNode>>>,
+    /// the previous states of the nodes touched since `record_begin`;
+    /// None if not recording.
+    pub(crate) record: Option<HashMap<Id, Option<FlowNode>>>,
+    /// the nodes to be checked by the next `check_touched`.
+    pub(crate) touched: HashSet<Id>,
+    pub(crate) check_mode: CheckMode,
+    /// reversed children lists: the linked node -> all its linkers;
+    /// the nodes in `unindexed` are left out.
+    pub(crate) backlinks: NodeMap<Id, HashSet<Id>>,
+    /// the nodes whose children may be altered and not yet in `backlinks`.
+    pub(crate) unindexed: HashSet<Id>,
+}
+
+impl<Id, FlowNode, Store> PartialEq for FlowArena<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone + PartialEq,
+    Store: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.node_map == other.node_map
+    }
+}
+
+impl<Id, FlowNode, Store> Debug for FlowArena<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Clone + Debug,
+    FlowNode: Node<Id> + Clone + Debug,
+    Store: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlowArena")
+            .field("node_map", &self.node_map)
+            .finish()
+    }
+}
+
+/// Takes the nodes as they are, e.g. read one by one; the flow isn't
+/// checked, so the nodes had better be whole together.
+impl<Id, FlowNode, Store> FromIterator<FlowNode>
+    for FlowArena<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    fn from_iter<I: IntoIterator<Item = FlowNode>>(iter: I) -> Self {
+        let node_map = iter
+            .into_iter()
+            .map(|node| (node.id().clone(), node))
+            .collect();
+        FlowArena::from_node_map(node_map)
+    }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-pub struct FlowArena<Id: Hash + Eq + Clone, FlowNode: Node<Id> + Clone> {
-    pub(crate) node_map: HashMap<Id, FlowNode>,
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    pub(crate) fn from_node_map(node_map: Store) -> Self {
+        let unindexed = node_map.keys().cloned().collect();
+        let mut flow = FlowArena {
+            node_map,
+            journal: None,
+            record: None,
+            touched: HashSet::new(),
+            check_mode: CheckMode::default(),
+            backlinks: NodeMap::new(),
+            unindexed,
+        };
+        flow.index_sync();
+        flow
+    }
+
+    /// puts the children of all unindexed nodes into `backlinks`.
+    fn index_sync(&mut self) {
+        for id in std::mem::take(&mut self.unindexed) {
+            if let Some(node) = self.node_map.get(&id) {
+                for child in node.children() {
+                    self.backlinks
+                        .entry(child.clone())
+                        .or_default()
+                        .insert(id.clone());
+                }
+            }
+        }
+    }
+
+    /// takes the children of a node out of `backlinks` before it's altered.
+    fn index_drop(&mut self, obj: &Id) {
+        if !self.unindexed.insert(obj.clone()) {
+            return;
+        }
+        let children = self.node_map.get(obj).map(|node| node.children());
+        for child in children.unwrap_or_default().to_vec() {
+            if let Some(set) = self.backlinks.get_mut(&child) {
+                set.remove(obj);
+                if set.is_empty() {
+                    self.backlinks.remove(&child);
+                }
+            }
+        }
+    }
 }
 
-impl<Id, FlowNode> Default for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> Default for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<Id, FlowNode> FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     pub fn new() -> Self {
-        let node_map = HashMap::new();
-        FlowArena { node_map }
+        Self::from_node_map(Store::default())
+    }
+
+    /// the nodes as stored, e.g. to take `SlabKey`s from a `Slab`; read
+    /// only, since all changes go through the flow.
+    pub fn store(&self) -> &Store {
+        &self.node_map
+    }
+
+    pub fn check_mode(&self) -> CheckMode {
+        self.check_mode
+    }
+
+    /// sets how the flow checks itself after each operation.
+    pub fn set_check_mode(&mut self, check_mode: CheckMode) {
+        self.check_mode = check_mode
+    }
+
+    /// starts recording the previous states of all nodes altered hereafter.
+    pub fn record_begin(&mut self) {
+        self.record = Some(HashMap::new())
+    }
+
+    /// stops recording; returns the previous states of all nodes altered
+    /// since `record_begin`, None for those didn't exist.
+    ///
+    /// Nodes altered and then restored by a failed transaction are included.
+    pub fn record_end(&mut self) -> HashMap<Id, Option<FlowNode>> {
+        self.record.take().unwrap_or_default()
+    }
+
+    /// puts back the given states in a transaction, None for removal;
+    /// usually those returned by `record_end`.
+    pub fn restore(
+        &mut self,
+        nodes: HashMap<Id, Option<FlowNode>>,
+    ) -> Result<(), FlowError<Id>> {
+        self.transact(|flow| {
+            for (id, node) in nodes {
+                flow.touch(&id);
+                match node {
+                    Some(node) => flow.node_map.insert(id, node),
+                    None => flow.node_map.remove(&id),
+                };
+            }
+            Ok(())
+        })
+    }
+
+    /// marks a node and its current neighbors to be checked; also records
+    /// the state of it before it's first altered in a transaction or a recording
+    fn touch(&mut self, obj: &Id) {
+        self.index_drop(obj);
+        if let Some(node) = self.node_map.get(obj) {
+            self.touched.extend(node.parent());
+            self.touched.extend(node.children().iter().cloned());
+        }
+        self.touched.insert(obj.clone());
+        let logs = [self.journal.as_mut(), self.record.as_mut()];
+        for log in logs.into_iter().flatten() {
+            if !log.contains_key(obj) {
+                log.insert(obj.clone(), self.node_map.get(obj).cloned());
+            }
+        }
+    }
+
+    /// restores all the nodes recorded in the journal
+    fn rollback(&mut self, journal: HashMap<Id, Option<FlowNode>>) {
+        for (id, node) in journal {
+            self.index_drop(&id);
+            match node {
+                Some(node) => self.node_map.insert(id, node),
+                None => self.node_map.remove(&id),
+            };
+        }
     }
 }
 
-impl<Id, FlowNode> FlowBase for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowBase for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     type Id = Id;
     type Node = FlowNode;
@@ -133,191 +354,393 @@ where
         self.node_map.get(obj)
     }
 
+    fn linked_from(&self, obj: &Self::Id) -> Vec<Self::Id> {
+        let indexed = self.backlinks.get(obj).into_iter().flatten().cloned();
+        let unindexed = self.unindexed.iter().filter(|id| {
+            self.node_map
+                .get(id)
+                .is_some_and(|node| node.children().contains(obj))
+        });
+        indexed.chain(unindexed.cloned()).collect()
+    }
     fn node_mut(&mut self, obj: &Self::Id) -> Option<&mut Self::Node> {
+        self.touch(obj);
         self.node_map.get_mut(obj)
     }
 }
 
-impl<Id, FlowNode> FlowCheck for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowCheck for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
-    fn check(&self) -> Result<(), (FlowError, String)> {
+    fn check(&self) -> Result<(), FlowError<Self::Id>> {
         for (id, node) in self.node_map.iter() {
-            let current_str =
-                format!(", current: \nid: {:?}, \nnode: {:#?}", id, node);
-            if id != node.id() {
-                return Err((FlowError::NodeIdNotMatch, current_str));
-            }
-            // children exist
-            for id in node.children().iter() {
-                if self.node_map.get(id).is_none() {
-                    return Err((FlowError::NotExistChild, current_str));
+            self.check_node(id, node)?
+        }
+        // every parent chain ends up in an orphan
+        if let Some(cycle) = self.ownership_cycle(self.node_map.keys()) {
+            return Err(FlowError::OwnershipCycle { cycle });
+        }
+        Ok(())
+    }
+
+    /// checks the nodes touched since the last check according to
+    /// the check mode; deferred till commit in a transaction.
+    fn check_touched(&mut self) -> Result<(), FlowError<Self::Id>> {
+        self.index_sync();
+        if self.journal.is_some() {
+            return Ok(());
+        }
+        let touched = std::mem::take(&mut self.touched);
+        match self.check_mode {
+            CheckMode::Assert => {
+                if cfg!(debug_assertions) {
+                    if let Err(err) = self.check_nodes(&touched) {
+                        panic!("{}", err)
+                    }
                 }
+                Ok(())
             }
-            // parent exist
-            if let Some(parent_id) = node.parent().clone() {
-                let maybe = self.node_map.get(&parent_id);
-                if maybe.is_none() {
-                    return Err((FlowError::NotExistParent, current_str));
+            CheckMode::Touched => self.check_nodes(&touched),
+            CheckMode::Full => self.check(),
+            CheckMode::Off => Ok(()),
+        }
+    }
+}
+
+impl<Id, FlowNode, Store> FlowTransact for FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    fn transact<T, F>(&mut self, f: F) -> Result<T, FlowError<Self::Id>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, FlowError<Self::Id>>,
+    {
+        let outer = self.journal.replace(HashMap::new());
+        let res = f(self);
+        // only the outermost transaction checks
+        let res = match outer {
+            Some(_) => res,
+            None => {
+                let touched = std::mem::take(&mut self.touched);
+                let check = match self.check_mode {
+                    CheckMode::Full => self.check(),
+                    CheckMode::Off => Ok(()),
+                    _ => self.check_nodes(&touched),
+                };
+                res.and_then(|t| check.map(|_| t))
+            }
+        };
+        let journal = self.journal.take().unwrap_or_default();
+        match (res.is_ok(), outer) {
+            (false, outer) => {
+                self.rollback(journal);
+                self.journal = outer;
+            }
+            (true, Some(mut outer)) => {
+                // the earlier records kept by the outer journal win
+                for (id, node) in journal {
+                    outer.entry(id).or_insert(node);
                 }
-                if let Some(node) = maybe {
-                    if node
-                        .children()
-                        .iter()
-                        .find(|x| x.clone() == id)
-                        .is_none()
-                    {
-                        return Err((FlowError::AbandonedChild, current_str));
-                    }
+                self.journal = Some(outer);
+            }
+            (true, None) => (),
+        }
+        self.index_sync();
+        res
+    }
+}
+
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// checks the properties around the given nodes only, i.e. the nodes
+    /// themselves and their parent chains; missing nodes are skipped.
+    ///
+    /// Enough to validate an operation if all the nodes it touched and
+    /// their former parents and children are given.
+    pub fn check_nodes<'a>(
+        &self,
+        objs: impl IntoIterator<Item = &'a Id>,
+    ) -> Result<(), FlowError<Id>>
+    where
+        Id: 'a,
+    {
+        let objs: Vec<&Id> = objs
+            .into_iter()
+            .filter(|id| self.node_map.contains_key(id))
+            .collect();
+        for &id in objs.iter() {
+            let node = self.node_map.get(id).expect("filtered");
+            self.check_node(id, node)?
+        }
+        if let Some(cycle) = self.ownership_cycle(objs) {
+            return Err(FlowError::OwnershipCycle { cycle });
+        }
+        Ok(())
+    }
+
+    /// checks a single node and its links to the neighbors.
+    fn check_node(
+        &self,
+        id: &Id,
+        node: &FlowNode,
+    ) -> Result<(), FlowError<Id>> {
+        if id != node.id() {
+            return Err(FlowError::NodeIdNotMatch {
+                obj: id.clone(),
+                found: node.id().clone(),
+            });
+        }
+        // children exist, distinct and not self
+        let mut children_set = HashSet::new();
+        for child in node.children().iter() {
+            if self.node_map.get(child).is_none() {
+                return Err(FlowError::NotExistChild {
+                    obj: child.clone(),
+                    owner: id.clone(),
+                });
+            }
+            if child == id {
+                return Err(FlowError::SelfLink { obj: id.clone() });
+            }
+            if !children_set.insert(child) {
+                return Err(FlowError::DuplicateChild {
+                    obj: child.clone(),
+                    owner: id.clone(),
+                });
+            }
+        }
+        // parent exist
+        if let Some(parent_id) = node.parent().clone() {
+            let maybe = self.node_map.get(&parent_id);
+            if maybe.is_none() {
+                return Err(FlowError::NotExistParent {
+                    obj: id.clone(),
+                    owner: parent_id,
+                });
+            }
+            if let Some(node) = maybe {
+                if !node.children().contains(id) {
+                    return Err(FlowError::AbandonedChild {
+                        obj: id.clone(),
+                        owner: parent_id,
+                    });
                 }
             }
         }
         Ok(())
     }
+
+    /// finds a group of nodes owning each other in a loop, which can't be reached from any orphan;
+    /// only the parent chains starting from `objs` are searched.
+    fn ownership_cycle<'a>(
+        &self,
+        objs: impl IntoIterator<Item = &'a Id>,
+    ) -> Option<Vec<Id>>
+    where
+        Id: 'a,
+    {
+        // nodes whose parent chain is known to end up in an orphan
+        let mut rooted: HashSet<Id> = HashSet::new();
+        for id in objs {
+            let mut path = Vec::new();
+            let mut path_set = HashSet::new();
+            let mut current = Some(id.clone());
+            while let Some(id) = current {
+                if rooted.contains(&id) {
+                    break;
+                }
+                if !path_set.insert(id.clone()) {
+                    let start =
+                        path.iter().position(|x| x == &id).unwrap_or_default();
+                    return Some(path.split_off(start));
+                }
+                current = self.node_map.get(&id).and_then(|x| x.parent());
+                path.push(id);
+            }
+            rooted.extend(path);
+        }
+        None
+    }
 }
 
-impl<Id, FlowNode> FlowMap for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowMap for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
-    fn grow(&mut self, obj: Self::Node) -> Result<Self::Id, FlowError> {
+    fn grow(
+        &mut self,
+        obj: Self::Node,
+    ) -> Result<Self::Id, FlowError<Self::Id>> {
         let res = if self.contains_node(obj.id()) {
-            Err(FlowError::ExistGrow)
+            Err(FlowError::ExistGrow {
+                obj: obj.id().clone(),
+            })
         } else {
             let id = obj.id().clone();
+            self.touch(&id);
             self.node_map.insert(obj.id().clone(), obj);
             Ok(id)
         };
-        self.check_assert();
+        self.check_touched()?;
         res
     }
 
-    fn erase(&mut self, obj: &Self::Id) -> Result<Self::Node, FlowError> {
-        let node = self.node(obj).cloned().ok_or(FlowError::NotExistObj)?;
+    fn erase(
+        &mut self,
+        obj: &Self::Id,
+    ) -> Result<Self::Node, FlowError<Self::Id>> {
+        let node = self
+            .node(obj)
+            .cloned()
+            .ok_or(FlowError::NotExistObj { obj: obj.clone() })?;
         let kill_set = self.node_ownership_set(obj);
-        self.node_map.retain(|id, _| !kill_set.contains(id));
-        let () = self
-            .node_map
-            .values_mut()
-            .map(|obj| {
-                obj.children_ref_mut().retain(|id| !kill_set.contains(id))
-            })
+        let linkers: HashSet<Self::Id> = kill_set
+            .iter()
+            .flat_map(|id| self.linked_from(id))
+            .filter(|id| !kill_set.contains(id))
             .collect();
-        self.check_assert();
+        for id in kill_set.iter().chain(linkers.iter()) {
+            self.touch(id)
+        }
+        self.node_map.retain(|id, _| !kill_set.contains(id));
+        for id in linkers {
+            if let Some(node) = self.node_map.get_mut(&id) {
+                node.children_ref_mut().retain(|id| !kill_set.contains(id))
+            }
+        }
+        self.check_touched()?;
         Ok(node)
     }
 }
 
-impl<Id, FlowNode> FlowLink for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowLink for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
 }
 
-impl<Id, FlowNode> FlowDevote for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowDevote for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
 }
 
-impl<Id, FlowNode> FlowDock for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowDock for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     fn dock(
         &mut self,
         owner: &Self::Id,
         mut vec: Vec<Self::Id>,
         mut flow: Self,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         // check vec contains all flow.orphan()
         if vec.is_empty() {
             vec = flow.orphan()
         } else {
             let set: HashSet<Self::Id> = vec.iter().cloned().collect();
-            if !flow
-                .orphan()
-                .iter()
-                .fold(true, |is_in, id| is_in && set.contains(id))
+            if let Some(id) =
+                flow.orphan().into_iter().find(|id| !set.contains(id))
             {
-                return Err(FlowError::AbandonedChild);
+                return Err(FlowError::AbandonedChild {
+                    obj: id,
+                    owner: owner.clone(),
+                });
             }
         }
-        if flow
-            .node_map
-            .keys()
-            .fold(false, |is, id| is || self.contains_node(id))
-        {
-            Err(FlowError::ExistDock)
+        let exist = flow.node_map.keys().find(|id| self.contains_node(id));
+        if let Some(id) = exist.cloned() {
+            Err(FlowError::ExistDock { obj: id })
         } else if !self.contains_node(owner) {
-            Err(FlowError::NotExistOwner)
+            Err(FlowError::NotExistOwner {
+                owner: owner.clone(),
+            })
         } else {
             self.node_mut(owner)
                 .map(|node| node.children_ref_mut().extend(vec));
-            let _: Vec<()> = flow
-                .node_map
-                .iter_mut()
-                .map(|(_, node)| node.parent_set(owner.clone()))
-                .collect();
+            for id in flow.node_map.keys() {
+                self.touch(id)
+            }
+            // only the orphans are owned by owner
+            for (_, node) in flow.node_map.iter_mut() {
+                if node.parent().is_none() {
+                    node.parent_set(owner.clone())
+                }
+            }
             self.node_map.extend(flow.node_map);
-            self.check_assert();
+            self.check_touched()?;
             Ok(())
         }
     }
 
-    fn undock_impl(
-        &mut self,
-        obj: &Self::Id,
-        owned: bool,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError> {
+    fn undock_impl(&mut self, obj: &Self::Id, owned: bool) -> DockResult<Self> {
         let (flow, vec) = if owned {
             self.snap_owned(obj)?
         } else {
             self.snap(obj)?
         };
         let set: HashSet<Self::Id> = flow.node_map.keys().cloned().collect();
+        // any linker inside, back to obj
+        if let Some(linker) = self
+            .linked_from(obj)
+            .into_iter()
+            .find(|id| set.contains(id))
+        {
+            return Err(FlowError::LinkedUndock {
+                obj: obj.clone(),
+                owner: linker,
+            });
+        }
         for id in set.iter() {
-            let filter: Vec<()> = self
-                .node_map
-                .values()
-                .filter(|node| !set.contains(node.id()) && !(node.id() == obj))
-                // node means current nodes excluding set_to_remove hereafter
-                .filter_map(|node| {
-                    // println!("set_id:{:?}, node_children:{:?}", id, node.children());
-                    let ch_ft =
-                        node.children().iter().find(|&x| x == id).map(|_| ());
-                    let pa_ft = node
-                        .parent()
-                        .map(|x| if &x == id { Some(()) } else { None })
-                        .flatten();
-                    ch_ft.or(pa_ft)
-                })
-                .collect();
-            if !filter.is_empty() {
-                return Err(FlowError::LinkedUndock);
+            // any linker outside, except obj
+            let linker = self
+                .linked_from(id)
+                .into_iter()
+                .find(|linker| !set.contains(linker) && linker != obj);
+            if let Some(linker) = linker {
+                return Err(FlowError::LinkedUndock {
+                    obj: id.clone(),
+                    owner: linker,
+                });
             }
         }
+        for id in set.iter() {
+            self.touch(id)
+        }
         self.node_map.retain(|id, _| !set.contains(id));
         self.node_mut(obj)
             .map(|node| node.children_ref_mut().clear());
-        self.check_assert();
+        self.check_touched()?;
         Ok((flow, vec))
     }
 
-    fn snap(&self, obj: &Self::Id) -> Result<(Self, Vec<Self::Id>), FlowError> {
+    fn snap(&self, obj: &Self::Id) -> DockResult<Self> {
         if !self.contains_node(obj) {
-            return Err(FlowError::NotExistObj);
+            return Err(FlowError::NotExistObj { obj: obj.clone() });
         }
         let vec = self.children(obj);
-        let set = self.node_offspring_set(obj);
-        let mut flow = FlowArena::new();
-        let node_map: HashMap<Self::Id, Self::Node> = set
+        let mut set = self.node_offspring_set(obj);
+        // only reachable through a link loop
+        set.remove(obj);
+        let node_map: Store = set
             .iter()
             .cloned()
             .filter_map(|id| self.node_map.get(&id).cloned())
@@ -333,24 +756,18 @@ where
                 (node.id().clone(), node)
             })
             .collect();
-        flow.node_map.extend(node_map);
-        self.check_assert();
-        Ok((flow, vec))
+        Ok((FlowArena::from_node_map(node_map), vec))
     }
 
-    fn snap_owned(
-        &self,
-        obj: &Self::Id,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError> {
+    fn snap_owned(&self, obj: &Self::Id) -> DockResult<Self> {
         if !self.contains_node(obj) {
-            return Err(FlowError::NotExistObj);
+            return Err(FlowError::NotExistObj { obj: obj.clone() });
         }
         let vec = self.children(obj);
         let mut set = self.node_ownership_set(obj);
         set.remove(obj);
         let set = set;
-        let mut flow = FlowArena::new();
-        let node_map: HashMap<Self::Id, Self::Node> = set
+        let node_map: Store = set
             .iter()
             .cloned()
             .filter_map(|id| self.node_map.get(&id).cloned())
@@ -366,29 +783,172 @@ where
                 (node.id().clone(), node)
             })
             .collect();
-        flow.node_map.extend(node_map);
-        self.check_assert();
-        Ok((flow, vec))
+        Ok((FlowArena::from_node_map(node_map), vec))
+    }
+}
+
+/// Decides what becomes of the links leaving a copied subtree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OuterLinks {
+    /// the copy still links to the nodes outside
+    Keep,
+    Drop,
+}
+
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// copies obj and all the nodes it owns under fresh ids; returns the
+    /// copy, whose only orphan is the new obj, along with the new obj.
+    ///
+    /// `fresh` makes a copy of a node with a new id; its parent and
+    /// children are then rewritten, so that the links within the subtree
+    /// point to the copies. The links leaving the subtree are kept or
+    /// dropped as `outer` says; kept ones refer to nodes not in the copy,
+    /// which is fine as long as it's docked back into this flow.
+    pub fn snap_fresh(
+        &self,
+        obj: &Id,
+        mut fresh: impl FnMut(&FlowNode) -> FlowNode,
+        outer: OuterLinks,
+    ) -> Result<(Self, Id), FlowError<Id>> {
+        if !self.contains_node(obj) {
+            return Err(FlowError::NotExistObj { obj: obj.clone() });
+        }
+        let set = self.node_ownership_set(obj);
+        let mut id_map: HashMap<Id, Id> = HashMap::new();
+        let mut new_set: HashSet<Id> = HashSet::new();
+        let mut nodes = Vec::new();
+        for id in set.iter() {
+            let node = self.node_map.get(id).expect("owned node exists");
+            let copy = fresh(node);
+            let new = copy.id().clone();
+            if self.contains_node(&new) || !new_set.insert(new.clone()) {
+                return Err(FlowError::ExistGrow { obj: new });
+            }
+            id_map.insert(id.clone(), new);
+            nodes.push((node, copy));
+        }
+        let node_map = nodes
+            .into_iter()
+            .map(|(node, mut copy)| {
+                match node.parent().and_then(|id| id_map.get(&id)) {
+                    Some(parent) if node.id() != obj => {
+                        copy.parent_set(parent.clone())
+                    }
+                    _ => copy.parent_set_none(),
+                }
+                let map = |id: &Id| match id_map.get(id) {
+                    Some(new) => Some(new.clone()),
+                    None => (outer == OuterLinks::Keep).then(|| id.clone()),
+                };
+                *copy.children_ref_mut() =
+                    node.children().iter().filter_map(map).collect();
+                if let (Some(keys), Some(copied)) =
+                    (node.positions(), copy.positions_mut())
+                {
+                    *copied = keys.remap(map)
+                }
+                (copy.id().clone(), copy)
+            })
+            .collect();
+        let new = id_map.get(obj).expect("obj is owned by itself").clone();
+        Ok((FlowArena::from_node_map(node_map), new))
     }
 }
 
-impl<Id, FlowNode> FlowShift for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> FlowShift for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
 }
 
-impl<Id, FlowNode> Flow for FlowArena<Id, FlowNode>
+impl<Id, FlowNode, Store> Flow for FlowArena<Id, FlowNode, Store>
 where
     Id: Clone + Hash + Eq + Default + Debug,
     FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
 }
 
-#[derive(Clone)]
+/// A single operation staged in a `FlowArena::commit`.
+#[derive(Clone, Debug)]
+pub enum FlowStep<
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store = NodeMap<Id, FlowNode>,
+> {
+    Grow(FlowNode),
+    Erase(Id),
+    Link {
+        obj: Id,
+        owner: Id,
+        nth: usize,
+    },
+    Detach {
+        obj: Id,
+        owner: Id,
+    },
+    Devote {
+        obj: Id,
+        owner: Id,
+        nth: usize,
+    },
+    Decay(Id),
+    /// the flow is boxed, being far larger than the other steps.
+    Dock {
+        owner: Id,
+        vec: Vec<Id>,
+        flow: Box<FlowArena<Id, FlowNode, Store>>,
+    },
+}
+
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// applies the steps in order within a transaction; all or nothing.
+    pub fn commit(
+        &mut self,
+        steps: Vec<FlowStep<Id, FlowNode, Store>>,
+    ) -> Result<(), FlowError<Id>> {
+        self.transact(|flow| {
+            for step in steps {
+                match step {
+                    FlowStep::Grow(node) => flow.grow(node).map(|_| ())?,
+                    FlowStep::Erase(obj) => flow.erase(&obj).map(|_| ())?,
+                    FlowStep::Link { obj, owner, nth } => {
+                        flow.link(&obj, &owner, nth)?
+                    }
+                    FlowStep::Detach { obj, owner } => {
+                        flow.detach(&obj, &owner)?
+                    }
+                    FlowStep::Devote { obj, owner, nth } => {
+                        flow.devote(&obj, &owner, nth)?
+                    }
+                    FlowStep::Decay(obj) => flow.decay(&obj)?,
+                    FlowStep::Dock {
+                        owner,
+                        vec,
+                        flow: other,
+                    } => flow.dock(&owner, vec, *other)?,
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg_attr(not(feature = "persistent"), derive(Clone))]
 pub struct Entities<'a, Id: 'a, Entity: 'a> {
-    iter: std::collections::hash_map::Values<'a, Id, FlowNode<Id, Entity>>,
+    iter: Values<'a, Id, FlowNode<Id, Entity>>,
 }
 
 impl<'a, Id, Entity> Iterator for Entities<'a, Id, Entity> {
@@ -399,16 +959,16 @@ impl<'a, Id, Entity> Iterator for Entities<'a, Id, Entity> {
         x.map(|node| &node.entity)
     }
 }
-pub struct EntitiesMut<'a, Id: 'a, Entity: 'a> {
-    iter: std::collections::hash_map::ValuesMut<'a, Id, FlowNode<Id, Entity>>,
+pub struct EntitiesMut<'a, Id: Clone + 'a, Entity: Clone + 'a> {
+    iter: IterMut<'a, Id, FlowNode<Id, Entity>>,
 }
 
-impl<'a, Id, Entity> Iterator for EntitiesMut<'a, Id, Entity> {
+impl<'a, Id: Clone, Entity: Clone> Iterator for EntitiesMut<'a, Id, Entity> {
     type Item = &'a Entity;
 
     fn next(&mut self) -> Option<Self::Item> {
         let x = self.iter.next();
-        x.map(|node| &node.entity)
+        x.map(|(_, node)| &node.entity)
     }
 }
 
@@ -426,7 +986,7 @@ where
     /// returns an iterator over all entities.
     pub fn entities_mut(&mut self) -> EntitiesMut<Id, Entity> {
         EntitiesMut {
-            iter: self.node_map.values_mut(),
+            iter: self.node_map.iter_mut(),
         }
     }
 }
@@ -436,7 +996,7 @@ mod tests {
     use super::*;
     type FlowEntity = FlowPure<EntityId>;
     type NodeEntity = FlowNode<EntityId, ()>;
-    #[derive(Clone, Copy, Default, Hash, PartialEq, Eq)]
+    #[derive(Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
     #[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
     struct EntityId {
         idx: u64,
@@ -454,7 +1014,7 @@ mod tests {
 
     fn wrapper<T>(
         name: &str,
-        res: Result<T, FlowError>,
+        res: Result<T, FlowError<EntityId>>,
         flow: &FlowEntity,
         aloud: bool,
     ) {
@@ -693,6 +1253,437 @@ mod tests {
         assert_eq!(flow, flow_);
     }
 
+    #[test]
+    fn snap_fresh() {
+        let (mut flow, obj_vec) = make_flow(false);
+        let fresh = |node: &NodeEntity| {
+            FlowNode::from_id((node.id().idx + 100).into(), ())
+        };
+        let new = |x: EntityId| EntityId::from(x.idx + 100);
+        let (copy, obj) = flow
+            .snap_fresh(&obj_vec[6], fresh, OuterLinks::Keep)
+            .expect("snap error");
+        assert_eq!(obj, new(obj_vec[6]));
+        assert_eq!(copy.orphan(), vec![obj]);
+        assert_eq!(copy.node_map.len(), 4);
+        assert_eq!(
+            copy.children(&new(obj_vec[11])),
+            flow.children(&obj_vec[11])
+                .into_iter()
+                .map(|id| if id == obj_vec[13] { id } else { new(id) })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(copy.parent(&new(obj_vec[12])), Some(new(obj_vec[11])));
+        // pasted next to the original
+        flow.dock(&obj_vec[0], vec![obj], copy).expect("dock error");
+        flow.check().expect("check error");
+        assert_eq!(flow.parent(&obj), Some(obj_vec[0]));
+        assert_eq!(flow.parent(&new(obj_vec[12])), Some(new(obj_vec[11])));
+        assert!(flow.linked_from(&obj_vec[14]).contains(&new(obj_vec[12])));
+        // once more, dropping the outer links
+        let fresh = |node: &NodeEntity| {
+            FlowNode::from_id((node.id().idx + 200).into(), ())
+        };
+        let (copy, _) = flow
+            .snap_fresh(&obj_vec[6], fresh, OuterLinks::Drop)
+            .expect("snap error");
+        assert!(copy.check().is_ok());
+        // ids taken
+        let same = |node: &NodeEntity| node.clone();
+        assert!(matches!(
+            flow.snap_fresh(&obj_vec[6], same, OuterLinks::Drop),
+            Err(FlowError::ExistGrow { .. })
+        ));
+    }
+
+    #[test]
+    fn backlinks() {
+        let (mut flow, obj_vec) = make_flow(false);
+        // the index always agrees with a full scan
+        let assert_index = |flow: &FlowEntity| {
+            for id in flow.node_map.keys() {
+                let mut indexed: HashSet<EntityId> =
+                    flow.linked_from(id).into_iter().collect();
+                for node in flow.node_map.values() {
+                    if node.children().contains(id) {
+                        assert!(indexed.remove(node.id()), "{:?}", id);
+                    }
+                }
+                assert!(indexed.is_empty(), "{:?}", id);
+            }
+        };
+        assert_index(&flow);
+        let mut linkers = flow.linked_from(&obj_vec[12]);
+        linkers.sort_by_key(|id| id.idx);
+        assert_eq!(linkers, vec![obj_vec[6], obj_vec[11]]);
+        assert_eq!(
+            flow.linked_from_outside(&obj_vec[11]),
+            vec![(obj_vec[6], obj_vec[12])]
+        );
+        assert!(flow.linked_from_outside(&obj_vec[6]).is_empty());
+        // altered behind the index
+        flow.node_mut(&obj_vec[0])
+            .map(|node| node.children_ref_mut().push(obj_vec[13]));
+        assert!(flow.linked_from(&obj_vec[13]).contains(&obj_vec[0]));
+        flow.node_mut(&obj_vec[0])
+            .map(|node| node.children_ref_mut().pop());
+        assert_index(&flow);
+        // failed transactions
+        let res = flow.transact(|flow| {
+            flow.link_push(&obj_vec[13], &obj_vec[2])?;
+            flow.erase(&obj_vec[11])?;
+            flow.erase(&obj_vec[11])
+        });
+        assert!(res.is_err());
+        assert_index(&flow);
+        // erase cleans up the links
+        flow.erase(&obj_vec[11]).expect("erase error");
+        assert!(flow.linked_from(&obj_vec[14]).is_empty());
+        assert!(!flow.children(&obj_vec[6]).contains(&obj_vec[12]));
+        assert_index(&flow);
+        let (sub, vec) = flow.undock(&obj_vec[6]).expect("undock error");
+        assert_index(&sub);
+        flow.dock(&obj_vec[0], vec, sub).expect("dock error");
+        assert_index(&flow);
+    }
+
+    #[test]
+    fn check_invalid() {
+        let (flow, obj_vec) = make_flow(false);
+        assert!(flow.check().is_ok());
+
+        // 6 -> 11 -> 12 -> 6
+        let mut cyclic = flow.clone();
+        cyclic
+            .node_map
+            .get_mut(&obj_vec[12])
+            .unwrap()
+            .children
+            .push(obj_vec[6]);
+        cyclic.node_map.get_mut(&obj_vec[6]).unwrap().parent =
+            Some(obj_vec[12]);
+        match cyclic.check() {
+            Err(err @ FlowError::OwnershipCycle { .. }) => {
+                let msg = err.to_string();
+                assert!(msg.starts_with("ownership forms a cycle: "));
+                for i in [6, 11, 12] {
+                    assert!(msg.contains(&format!("{:?}", obj_vec[i])));
+                }
+            }
+            res => panic!("cycle undetected: {:?}", res),
+        }
+
+        let mut duplicated = flow.clone();
+        duplicated
+            .node_map
+            .get_mut(&obj_vec[6])
+            .unwrap()
+            .children
+            .push(obj_vec[10]);
+        match duplicated.check() {
+            Err(FlowError::DuplicateChild { obj, owner }) => {
+                assert_eq!((obj, owner), (obj_vec[10], obj_vec[6]))
+            }
+            res => panic!("duplicate undetected: {:?}", res),
+        }
+
+        let mut self_linked = flow.clone();
+        self_linked
+            .node_map
+            .get_mut(&obj_vec[2])
+            .unwrap()
+            .children
+            .push(obj_vec[2]);
+        match self_linked.check() {
+            Err(FlowError::SelfLink { obj }) => assert_eq!(obj, obj_vec[2]),
+            res => panic!("self link undetected: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn devote_cycle() {
+        let (mut flow, obj_vec) = make_flow(false);
+        let flow_ = flow.clone();
+        assert_eq!(
+            flow.devote(&obj_vec[6], &obj_vec[12], 0),
+            Err(FlowError::OwnershipCycle {
+                cycle: vec![obj_vec[12], obj_vec[11], obj_vec[6]]
+            })
+        );
+        assert_eq!(
+            flow.devote_push(&obj_vec[6], &obj_vec[6]),
+            Err(FlowError::OwnershipCycle {
+                cycle: vec![obj_vec[6]]
+            })
+        );
+        assert_eq!(
+            flow.link_push(&obj_vec[6], &obj_vec[6]),
+            Err(FlowError::SelfLink { obj: obj_vec[6] })
+        );
+        assert_eq!(flow, flow_);
+        // pure links may still form loops
+        flow.link_push(&obj_vec[6], &obj_vec[14])
+            .expect("link error");
+        assert_eq!(flow.node_offspring_set(&obj_vec[6]).len(), 6);
+    }
+
+    #[test]
+    fn transact() {
+        let (mut flow, obj_vec) = make_flow(false);
+        let node =
+            |i: usize| -> NodeEntity { FlowNode::from_id(obj_vec[i], ()) };
+        let flow_ = flow.clone();
+        // fails at the last step; nothing happens
+        let res = flow.commit(vec![
+            FlowStep::Grow(node(15)),
+            FlowStep::Devote {
+                obj: obj_vec[15],
+                owner: obj_vec[0],
+                nth: 0,
+            },
+            FlowStep::Erase(obj_vec[11]),
+            FlowStep::Devote {
+                obj: obj_vec[0],
+                owner: obj_vec[15],
+                nth: 0,
+            },
+        ]);
+        assert_eq!(
+            res,
+            Err(FlowError::OwnershipCycle {
+                cycle: vec![obj_vec[15], obj_vec[0]]
+            })
+        );
+        assert_eq!(flow, flow_);
+        // fails at the final check; nothing happens
+        let res = flow.transact(|flow| {
+            flow.node_mut(&obj_vec[2])
+                .map(|node| node.parent_set(obj_vec[4]));
+            Ok(())
+        });
+        assert_eq!(
+            res,
+            Err(FlowError::AbandonedChild {
+                obj: obj_vec[2],
+                owner: obj_vec[4]
+            })
+        );
+        assert_eq!(flow, flow_);
+        // a failing nested transaction only rolls back itself
+        flow.transact(|flow| {
+            flow.grow(node(15))?;
+            flow.devote_push(&obj_vec[15], &obj_vec[6])?;
+            let res = flow.transact(|flow| {
+                flow.erase(&obj_vec[6])?;
+                flow.erase(&obj_vec[6])
+            });
+            assert!(res.is_err());
+            Ok(())
+        })
+        .expect("transact error");
+        assert_eq!(flow.parent(&obj_vec[15]), Some(obj_vec[6]));
+        assert_eq!(flow.node_map.len(), flow_.node_map.len() + 1);
+        // all steps applied
+        flow.commit(vec![
+            FlowStep::Grow(node(16)),
+            FlowStep::Link {
+                obj: obj_vec[16],
+                owner: obj_vec[0],
+                nth: 0,
+            },
+            FlowStep::Detach {
+                obj: obj_vec[16],
+                owner: obj_vec[0],
+            },
+            FlowStep::Devote {
+                obj: obj_vec[16],
+                owner: obj_vec[15],
+                nth: 0,
+            },
+            FlowStep::Decay(obj_vec[15]),
+        ])
+        .expect("commit error");
+        assert_eq!(flow.children(&obj_vec[15]), vec![obj_vec[16]]);
+        assert_eq!(flow.parent(&obj_vec[15]), None);
+        assert!(flow.journal.is_none());
+    }
+
+    #[test]
+    fn record() {
+        let (mut flow, obj_vec) = make_flow(false);
+        let flow_ = flow.clone();
+        flow.record_begin();
+        flow.decay(&obj_vec[11]).expect("decay error");
+        flow.erase(&obj_vec[6]).expect("erase error");
+        let record = flow.record_end();
+        assert!(record.contains_key(&obj_vec[10]));
+        let flow_erased = flow.clone();
+        flow.restore(record).expect("restore error");
+        assert_eq!(flow, flow_);
+        // a restore which breaks the flow does nothing
+        let mut bad = HashMap::new();
+        bad.insert(obj_vec[11], None);
+        assert!(flow.restore(bad).is_err());
+        assert_eq!(flow, flow_);
+        assert_ne!(flow, flow_erased);
+    }
+
+    #[cfg(feature = "persistent")]
+    #[test]
+    fn persistent() {
+        let (flow, obj_vec) = make_flow(false);
+        let mut snapshot = flow.clone();
+        assert!(snapshot.node_map.ptr_eq(&flow.node_map));
+        snapshot.erase(&obj_vec[6]).expect("erase error");
+        assert!(flow.contains_node(&obj_vec[12]));
+        assert!(!snapshot.contains_node(&obj_vec[12]));
+    }
+
+    #[test]
+    fn check_mode() {
+        let (mut flow, obj_vec) = make_flow(false);
+        let node =
+            |i: usize| -> NodeEntity { FlowNode::from_id(obj_vec[i], ()) };
+        flow.set_check_mode(CheckMode::Touched);
+        // broken by a touch; found by the next operation
+        flow.node_mut(&obj_vec[2])
+            .map(|node| node.parent_set(obj_vec[4]));
+        assert_eq!(
+            flow.grow(node(15)),
+            Err(FlowError::AbandonedChild {
+                obj: obj_vec[2],
+                owner: obj_vec[4]
+            })
+        );
+        assert!(flow.check_nodes(&[obj_vec[15]]).is_ok());
+        assert!(flow.check_nodes(&[obj_vec[2]]).is_err());
+        flow.node_mut(&obj_vec[2])
+            .map(|node| node.parent_set(obj_vec[0]));
+        flow.grow(node(16)).expect("grow error");
+        // broken behind the back; only found by a full check
+        flow.node_map
+            .get_mut(&obj_vec[12])
+            .map(|node| node.parent_set(obj_vec[10]));
+        flow.grow(node(17)).expect("grow error");
+        flow.set_check_mode(CheckMode::Full);
+        assert_eq!(
+            flow.grow(node(18)),
+            Err(FlowError::AbandonedChild {
+                obj: obj_vec[12],
+                owner: obj_vec[10]
+            })
+        );
+    }
+
+    #[test]
+    fn traverse() {
+        let (flow, obj_vec) = make_flow(false);
+        let ids = |order, mode| -> Vec<(EntityId, usize)> {
+            flow.traverse(&obj_vec[6], order, mode)
+                .map(|visit| (visit.id, visit.depth))
+                .collect()
+        };
+        let expect = |vec: &[(usize, usize)]| -> Vec<(EntityId, usize)> {
+            vec.iter().map(|&(i, depth)| (obj_vec[i], depth)).collect()
+        };
+        use TraverseMode::*;
+        use TraverseOrder::*;
+        assert_eq!(
+            ids(PreOrder, Owned),
+            expect(&[(6, 0), (10, 1), (11, 1), (12, 2)])
+        );
+        assert_eq!(
+            ids(PostOrder, Owned),
+            expect(&[(10, 1), (12, 2), (11, 1), (6, 0)])
+        );
+        assert_eq!(
+            ids(PreOrder, Linked),
+            expect(&[(6, 0), (10, 1), (11, 1), (12, 2), (14, 3), (13, 2)])
+        );
+        assert_eq!(
+            ids(PostOrder, Linked),
+            expect(&[(10, 1), (14, 3), (12, 2), (13, 2), (11, 1), (6, 0)])
+        );
+        assert_eq!(
+            ids(BreadthFirst, Linked),
+            expect(&[(6, 0), (10, 1), (11, 1), (12, 1), (13, 2), (14, 2)])
+        );
+        let parent = |order| {
+            flow.traverse(&obj_vec[6], order, Linked)
+                .find(|visit| visit.id == obj_vec[12])
+                .and_then(|visit| visit.parent)
+        };
+        assert_eq!(parent(PreOrder), Some(obj_vec[11]));
+        assert_eq!(parent(BreadthFirst), Some(obj_vec[6]));
+
+        // every node is met exactly once, even with loops
+        let mut flow = flow;
+        flow.link_push(&obj_vec[6], &obj_vec[14])
+            .expect("link error");
+        for order in [PreOrder, PostOrder, BreadthFirst] {
+            let set: HashSet<EntityId> = flow
+                .traverse_orphan(order, Linked)
+                .map(|visit| visit.id)
+                .collect();
+            assert_eq!(set.len(), flow.traverse_orphan(order, Linked).count());
+            assert_eq!(set.len(), flow.node_map.len());
+        }
+    }
+
+    #[test]
+    fn ancestors() {
+        let (flow, obj_vec) = make_flow(false);
+        let ancestors: Vec<EntityId> = flow.ancestors(&obj_vec[12]).collect();
+        assert_eq!(ancestors, vec![obj_vec[11], obj_vec[6]]);
+        assert_eq!(flow.root(&obj_vec[12]), Some(obj_vec[6]));
+        assert_eq!(flow.root(&obj_vec[6]), Some(obj_vec[6]));
+        assert_eq!(flow.root(&obj_vec[1]), None);
+        assert_eq!(flow.depth(&obj_vec[12]), Some(2));
+        assert_eq!(flow.depth(&obj_vec[13]), Some(0));
+        assert_eq!(
+            flow.lowest_common_owner(&obj_vec[10], &obj_vec[12]),
+            Some(obj_vec[6])
+        );
+        assert_eq!(
+            flow.lowest_common_owner(&obj_vec[12], &obj_vec[11]),
+            Some(obj_vec[11])
+        );
+        // linked, but not owned
+        assert_eq!(flow.lowest_common_owner(&obj_vec[13], &obj_vec[11]), None);
+        assert_eq!(
+            flow.ownership_path(&obj_vec[10], &obj_vec[12]),
+            Some(vec![obj_vec[10], obj_vec[6], obj_vec[11], obj_vec[12]])
+        );
+        assert_eq!(
+            flow.ownership_path(&obj_vec[12], &obj_vec[6]),
+            Some(vec![obj_vec[12], obj_vec[11], obj_vec[6]])
+        );
+        assert_eq!(flow.ownership_path(&obj_vec[2], &obj_vec[12]), None);
+    }
+
+    #[test]
+    fn migrate() {
+        let (mut flow, obj_vec) = make_flow(false);
+        flow.migrate(&obj_vec[2], Direction::Forward)
+            .expect("migrate error");
+        assert_eq!(flow.children(&obj_vec[0]), vec![obj_vec[4], obj_vec[2]]);
+        assert!(matches!(
+            flow.migrate(&obj_vec[2], Direction::Forward),
+            Err(FlowError::InvalidLen { nth: 1, len: 2, .. })
+        ));
+        flow.migrate(&obj_vec[12], Direction::Ascend)
+            .expect("migrate error");
+        assert_eq!(
+            flow.children(&obj_vec[6]),
+            vec![obj_vec[10], obj_vec[11], obj_vec[12]]
+        );
+        assert_eq!(flow.parent(&obj_vec[12]), Some(obj_vec[6]));
+        assert_eq!(
+            flow.migrate(&obj_vec[12], Direction::Ascend),
+            Err(FlowError::IsOrphaned { obj: obj_vec[6] })
+        );
+    }
+
     #[test]
     fn iter() {
         let (flow, _) = make_flow(false);
@@ -723,6 +1714,10 @@ mod tests {
         let str = serde_json::to_string(&flow).unwrap();
         print_wrapper(&str, true);
         let _flow: FlowEntity = serde_json::from_str(&str).unwrap();
-        assert_eq!(flow, _flow)
+        assert_eq!(flow, _flow);
+        // canonical, whatever the map order is
+        assert_eq!(serde_json::to_string(&_flow).unwrap(), str);
+        let (flow, _) = make_flow(false);
+        assert_eq!(serde_json::to_string(&flow).unwrap(), str);
     }
 }
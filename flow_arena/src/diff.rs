@@ -0,0 +1,581 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{
+    arena::NodeMap, FlowArena, FlowCheck, FlowError, Node, NodeStore, Position,
+};
+
+/// The structural difference from an old flow to a new one; all in no
+/// specific order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowDiff<Id> {
+    /// nodes only in the new flow
+    pub added: Vec<Id>,
+    /// nodes only in the old flow
+    pub removed: Vec<Id>,
+    /// nodes whose entity changed; parent and children aside
+    pub changed: Vec<Id>,
+    /// nodes owned by another parent, as (obj, old parent, new parent)
+    pub moved: Vec<(Id, Option<Id>, Option<Id>)>,
+    /// nodes with the same children in a different order
+    pub reordered: Vec<Id>,
+    /// nodes with children added or removed
+    pub relinked: Vec<Id>,
+}
+
+impl<Id> Default for FlowDiff<Id> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            moved: Vec::new(),
+            reordered: Vec::new(),
+            relinked: Vec::new(),
+        }
+    }
+}
+
+impl<Id> FlowDiff<Id> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.moved.is_empty()
+            && self.reordered.is_empty()
+            && self.relinked.is_empty()
+    }
+}
+
+/// A part of a node changed by both sides of a merge in different ways.
+///
+/// The merge never picks a side silently; it takes ours and reports.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict<Id> {
+    /// both added the node, differently
+    Added { obj: Id },
+    /// one removed the node while the other changed it; it's kept
+    Removed { obj: Id },
+    /// both changed the entity
+    Entity { obj: Id },
+    /// both moved the node, to different parents
+    Parent { obj: Id },
+    /// both reordered the children, in different ways
+    Children { obj: Id },
+    /// the merged flow is broken, e.g. by moves making an ownership cycle;
+    /// ours is taken as a whole
+    Invalid { error: FlowError<Id> },
+}
+
+impl<Id: Debug> fmt::Display for Conflict<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Conflict::*;
+        match self {
+            Added { obj } => write!(f, "{:?} added on both sides", obj),
+            Removed { obj } => {
+                write!(f, "{:?} removed on one side, changed on the other", obj)
+            }
+            Entity { obj } => write!(f, "{:?} changed on both sides", obj),
+            Parent { obj } => write!(f, "{:?} moved on both sides", obj),
+            Children { obj } => {
+                write!(f, "children of {:?} reordered on both sides", obj)
+            }
+            Invalid { error } => write!(f, "merge broke the flow: {}", error),
+        }
+    }
+}
+
+/// The result of a three-way merge; clean if no conflicts.
+#[derive(Debug, Clone)]
+pub struct FlowMerge<
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store = NodeMap<Id, FlowNode>,
+> {
+    pub flow: FlowArena<Id, FlowNode, Store>,
+    pub conflicts: Vec<Conflict<Id>>,
+}
+
+impl<Id, FlowNode, Store> FlowMerge<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Clone,
+    FlowNode: Node<Id> + Clone,
+{
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// the node without parent and children, i.e. only the entity.
+fn bare<Id, FlowNode: Node<Id> + Clone>(node: &FlowNode) -> FlowNode {
+    let mut node = node.clone();
+    node.parent_set_none();
+    node.children_ref_mut().clear();
+    if let Some(positions) = node.positions_mut() {
+        positions.clear()
+    }
+    node
+}
+
+/// takes the side which changed; ours if both did, with a conflict.
+fn pick<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> (T, bool) {
+    if ours == theirs || theirs == base {
+        (ours.clone(), false)
+    } else if ours == base {
+        (theirs.clone(), false)
+    } else {
+        (ours.clone(), true)
+    }
+}
+
+/// merges children lists; removals and additions of both sides are kept,
+/// and a conflict only arises if both reordered.
+fn pick_children<Id: Clone + Hash + Eq>(
+    base: &[Id],
+    ours: &[Id],
+    theirs: &[Id],
+) -> (Vec<Id>, bool) {
+    let (picked, conflict) = pick(&base, &ours, &theirs);
+    if !conflict {
+        return (picked.to_vec(), false);
+    }
+    let base_set: HashSet<&Id> = base.iter().collect();
+    let ours_set: HashSet<&Id> = ours.iter().collect();
+    let theirs_set: HashSet<&Id> = theirs.iter().collect();
+    let kept = |list: &[Id]| -> Vec<Id> {
+        list.iter()
+            .filter(|id| ours_set.contains(id) && theirs_set.contains(id))
+            .filter(|id| base_set.contains(id))
+            .cloned()
+            .collect()
+    };
+    let (order_base, order_ours, order_theirs) =
+        (kept(base), kept(ours), kept(theirs));
+    let reordered = order_ours != order_base && order_theirs != order_base;
+    let conflict = reordered && order_ours != order_theirs;
+    // start from the side which reordered, and add in the other side
+    let (first, second) = if order_ours == order_base {
+        (theirs, ours)
+    } else {
+        (ours, theirs)
+    };
+    let second_set: HashSet<&Id> = second.iter().collect();
+    let mut children: Vec<Id> = first
+        .iter()
+        .filter(|id| !base_set.contains(id) || second_set.contains(id))
+        .cloned()
+        .collect();
+    for (i, id) in second.iter().enumerate() {
+        if base_set.contains(id) || children.contains(id) {
+            continue;
+        }
+        // right after the nearest one before it, if any
+        let pos = second[..i]
+            .iter()
+            .rev()
+            .find_map(|prev| children.iter().position(|x| x == prev))
+            .map_or(0, |pos| pos + 1);
+        children.insert(pos, id.clone());
+    }
+    (children, conflict)
+}
+
+/// merges the position keys of the children picked into node; if all of
+/// them are keyed, the keys decide the order, and only a key changed by
+/// both sides conflicts. Children with equal keys, e.g. inserted at the
+/// same place on both sides, keep the order `pick_children` gave.
+fn pick_positions<Id: Clone + PartialEq, FlowNode: Node<Id>>(
+    node: &mut FlowNode,
+    base: &FlowNode,
+    ours: &FlowNode,
+    theirs: &FlowNode,
+    conflict: bool,
+) -> bool {
+    let children = node.children().to_vec();
+    let key = |node: &FlowNode, id: &Id| -> Option<Position> {
+        node.positions().and_then(|keys| keys.get(id)).cloned()
+    };
+    let mut keyed = Vec::new();
+    let mut key_conflict = false;
+    for id in children.iter() {
+        let (picked, conflict) =
+            pick(&key(base, id), &key(ours, id), &key(theirs, id));
+        key_conflict |= conflict;
+        if let Some(picked) = picked {
+            keyed.push((id.clone(), picked))
+        }
+    }
+    let positions = match node.positions_mut() {
+        Some(positions) => positions,
+        None => return conflict,
+    };
+    positions.clear();
+    for (id, key) in keyed.iter().cloned() {
+        positions.set(id, key)
+    }
+    if keyed.is_empty() || keyed.len() < children.len() {
+        return conflict || key_conflict;
+    }
+    keyed.sort_by(|(_, a), (_, b)| a.cmp(b));
+    *node.children_ref_mut() = keyed.into_iter().map(|(id, _)| id).collect();
+    key_conflict
+}
+
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone + PartialEq,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// compares the flow with a newer one.
+    pub fn diff(&self, other: &Self) -> FlowDiff<Id> {
+        let mut diff = FlowDiff::default();
+        for (id, old) in self.node_map.iter() {
+            let new = match other.node_map.get(id) {
+                Some(new) => new,
+                None => {
+                    diff.removed.push(id.clone());
+                    continue;
+                }
+            };
+            if bare(old) != bare(new) {
+                diff.changed.push(id.clone())
+            }
+            if old.parent() != new.parent() {
+                diff.moved.push((id.clone(), old.parent(), new.parent()))
+            }
+            let (old, new) = (old.children(), new.children());
+            if old != new {
+                let old_set: HashSet<&Id> = old.iter().collect();
+                let new_set: HashSet<&Id> = new.iter().collect();
+                if old_set == new_set {
+                    diff.reordered.push(id.clone())
+                } else {
+                    diff.relinked.push(id.clone())
+                }
+            }
+        }
+        diff.added = other
+            .node_map
+            .keys()
+            .filter(|id| !self.node_map.contains_key(id))
+            .cloned()
+            .collect();
+        diff
+    }
+
+    /// merges the changes made by ours and theirs since their common base.
+    ///
+    /// Changes to different parts of a node, i.e. entity, parent and
+    /// children, merge without conflict; so do the links added or removed
+    /// by either side. Where both sides changed the same part, ours is
+    /// taken and a `Conflict` reported. A node changed on one side is kept,
+    /// along with its owners, even if the other side removed it; moves
+    /// that only form an ownership cycle together are undone node by node,
+    /// each reported as a `Conflict::Parent`.
+    pub fn merge(
+        base: &Self,
+        ours: &Self,
+        theirs: &Self,
+    ) -> FlowMerge<Id, FlowNode, Store> {
+        let mut conflicts = Vec::new();
+        let ids: HashSet<&Id> = base
+            .node_map
+            .keys()
+            .chain(ours.node_map.keys())
+            .chain(theirs.node_map.keys())
+            .collect();
+        let mut node_map = Store::default();
+        for id in ids {
+            let node = match (
+                base.node_map.get(id),
+                ours.node_map.get(id),
+                theirs.node_map.get(id),
+            ) {
+                (Some(b), Some(o), Some(t)) => {
+                    let (mut node, conflict) =
+                        pick(&bare(b), &bare(o), &bare(t));
+                    if conflict {
+                        conflicts.push(Conflict::Entity { obj: id.clone() })
+                    }
+                    let (parent, conflict) =
+                        pick(&b.parent(), &o.parent(), &t.parent());
+                    match parent {
+                        Some(parent) => node.parent_set(parent),
+                        None => node.parent_set_none(),
+                    }
+                    if conflict {
+                        conflicts.push(Conflict::Parent { obj: id.clone() })
+                    }
+                    let (children, conflict) =
+                        pick_children(b.children(), o.children(), t.children());
+                    *node.children_ref_mut() = children;
+                    let conflict = pick_positions(&mut node, b, o, t, conflict);
+                    if conflict {
+                        conflicts.push(Conflict::Children { obj: id.clone() })
+                    }
+                    node
+                }
+                (None, Some(o), Some(t)) => {
+                    if o != t {
+                        conflicts.push(Conflict::Added { obj: id.clone() })
+                    }
+                    o.clone()
+                }
+                (Some(b), Some(x), None) | (Some(b), None, Some(x)) => {
+                    if x == b {
+                        continue;
+                    }
+                    conflicts.push(Conflict::Removed { obj: id.clone() });
+                    x.clone()
+                }
+                (None, Some(x), None) | (None, None, Some(x)) => x.clone(),
+                (_, None, None) => continue,
+            };
+            node_map.insert(id.clone(), node);
+        }
+        Self::merge_uncycle(&mut node_map, ours, &mut conflicts);
+        Self::merge_repair(&mut node_map, ours, theirs);
+        let flow = FlowArena::from_node_map(node_map);
+        match flow.check() {
+            Ok(()) => FlowMerge { flow, conflicts },
+            Err(error) => {
+                conflicts.push(Conflict::Invalid { error });
+                FlowMerge {
+                    flow: ours.clone(),
+                    conflicts,
+                }
+            }
+        }
+    }
+
+    /// breaks the ownership cycles made by moves merged from both sides,
+    /// each by moving a node of the cycle back under its owner in ours,
+    /// or none if it's not in ours; a `Conflict::Parent` for each.
+    fn merge_uncycle(
+        node_map: &mut Store,
+        ours: &Self,
+        conflicts: &mut Vec<Conflict<Id>>,
+    ) {
+        while let Some(cycle) = Self::merge_cycle(node_map) {
+            // ours alone has no cycle, so some node differs from ours
+            let ours_parent =
+                |id: &Id| ours.node_map.get(id).map(|x| x.parent());
+            let obj = cycle
+                .iter()
+                .find(|id| {
+                    let parent = node_map.get(id).and_then(|x| x.parent());
+                    ours_parent(id).is_some_and(|x| x != parent)
+                })
+                .or_else(|| cycle.iter().find(|id| ours_parent(id).is_none()))
+                .expect("a node apart from ours")
+                .clone();
+            let node = node_map.get_mut(&obj).expect("in cycle");
+            let parent = node.parent().expect("in cycle");
+            match ours_parent(&obj).flatten() {
+                Some(owner) => node.parent_set(owner),
+                None => node.parent_set_none(),
+            }
+            let linked = ours
+                .node_map
+                .get(&parent)
+                .is_some_and(|x| x.children().contains(&obj));
+            if !linked {
+                let owner = node_map.get_mut(&parent).expect("in cycle");
+                owner.children_ref_mut().retain(|id| id != &obj);
+            }
+            conflicts.push(Conflict::Parent { obj });
+        }
+    }
+
+    /// an ownership cycle, if any.
+    fn merge_cycle(node_map: &Store) -> Option<Vec<Id>> {
+        let mut done: HashSet<Id> = HashSet::new();
+        for id in node_map.keys() {
+            let mut path: Vec<Id> = Vec::new();
+            let mut current = Some(id.clone());
+            while let Some(id) = current {
+                if done.contains(&id) {
+                    break;
+                }
+                if let Some(pos) = path.iter().position(|x| x == &id) {
+                    return Some(path.split_off(pos));
+                }
+                current = node_map.get(&id).and_then(|x| x.parent());
+                path.push(id);
+            }
+            done.extend(path);
+        }
+        None
+    }
+
+    /// brings back the missing owners of the kept nodes, drops the links
+    /// to the removed ones and lets the owners link to what they own.
+    fn merge_repair(node_map: &mut Store, ours: &Self, theirs: &Self) {
+        let mut wait: Vec<Id> = node_map.keys().cloned().collect();
+        while let Some(id) = wait.pop() {
+            let parent = match node_map.get(&id).and_then(|x| x.parent()) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            if node_map.contains_key(&parent) {
+                continue;
+            }
+            let owner = ours
+                .node_map
+                .get(&parent)
+                .or_else(|| theirs.node_map.get(&parent));
+            match owner {
+                Some(owner) => {
+                    node_map.insert(parent.clone(), owner.clone());
+                    wait.push(parent);
+                }
+                None => {
+                    if let Some(node) = node_map.get_mut(&id) {
+                        node.parent_set_none()
+                    }
+                }
+            }
+        }
+        let ids: HashSet<Id> = node_map.keys().cloned().collect();
+        for id in ids.iter() {
+            if let Some(node) = node_map.get_mut(id) {
+                node.children_ref_mut().retain(|id| ids.contains(id))
+            }
+        }
+        for id in ids.iter() {
+            let parent = node_map.get(id).and_then(|x| x.parent());
+            if let Some(owner) = parent.and_then(|id| node_map.get_mut(&id)) {
+                if !owner.children().contains(id) {
+                    owner.children_ref_mut().push(id.clone())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FlowBase, FlowDevote, FlowLink, FlowMap, FlowNode};
+    type Flow = FlowArena<u64, FlowNode<u64, &'static str>>;
+
+    /// 0 -> {1 -> {3, 4}, 2 -> {5}}
+    fn make_flow() -> Flow {
+        let mut flow = Flow::new();
+        for i in 0..6 {
+            flow.grow(FlowNode::from_id(i, "")).expect("grow error");
+        }
+        for (obj, owner) in [(1, 0), (2, 0), (3, 1), (4, 1), (5, 2)] {
+            flow.devote_push(&obj, &owner).expect("devote error");
+        }
+        flow
+    }
+
+    fn sorted<T: Ord>(mut vec: Vec<T>) -> Vec<T> {
+        vec.sort();
+        vec
+    }
+
+    #[test]
+    fn diff() {
+        let old = make_flow();
+        assert!(old.diff(&old).is_empty());
+        let mut new = old.clone();
+        new.erase(&5).expect("erase error");
+        new.grow(FlowNode::from_id(6, "six")).expect("grow error");
+        new.devote(&6, &1, 0).expect("devote error");
+        new.node_mut(&3).unwrap().entity = "three";
+        new.devote_push(&4, &2).expect("devote error");
+        new.link_push(&3, &2).expect("link error");
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec![6]);
+        assert_eq!(diff.removed, vec![5]);
+        assert_eq!(diff.changed, vec![3]);
+        assert_eq!(diff.moved, vec![(4, Some(1), Some(2))]);
+        assert_eq!(sorted(diff.relinked), vec![1, 2]);
+        let mut new = old.clone();
+        new.node_mut(&0).unwrap().children_ref_mut().reverse();
+        assert_eq!(old.diff(&new).reordered, vec![0]);
+    }
+
+    #[test]
+    fn merge_clean() {
+        let base = make_flow();
+        let mut ours = base.clone();
+        ours.node_mut(&3).unwrap().entity = "ours";
+        ours.grow(FlowNode::from_id(6, "")).expect("grow error");
+        ours.devote(&6, &1, 1).expect("devote error");
+        ours.node_mut(&0).unwrap().children_ref_mut().reverse();
+        let mut theirs = base.clone();
+        theirs.node_mut(&4).unwrap().entity = "theirs";
+        theirs.grow(FlowNode::from_id(7, "")).expect("grow error");
+        theirs.devote_push(&7, &1).expect("devote error");
+        theirs.erase(&5).expect("erase error");
+        theirs.devote_push(&3, &2).expect("devote error");
+        let merge = Flow::merge(&base, &ours, &theirs);
+        assert!(merge.is_clean(), "{:?}", merge.conflicts);
+        let flow = merge.flow;
+        assert_eq!(flow.node(&3).unwrap().entity, "ours");
+        assert_eq!(flow.node(&4).unwrap().entity, "theirs");
+        assert_eq!(flow.children(&0), vec![2, 1]);
+        // 3 is still linked from 1
+        assert_eq!(flow.children(&1), vec![3, 6, 4, 7]);
+        assert_eq!(flow.children(&2), vec![3]);
+        assert_eq!(flow.parent(&3), Some(2));
+        assert!(!flow.contains_node(&5));
+        // either way round
+        let merge = Flow::merge(&base, &theirs, &ours);
+        assert!(merge.is_clean());
+        assert_eq!(merge.flow, flow);
+    }
+
+    #[test]
+    fn merge_conflict() {
+        let base = make_flow();
+        let mut ours = base.clone();
+        ours.node_mut(&3).unwrap().entity = "ours";
+        ours.devote_push(&4, &2).expect("devote error");
+        ours.node_mut(&5).unwrap().entity = "ours";
+        let mut theirs = base.clone();
+        theirs.node_mut(&3).unwrap().entity = "theirs";
+        theirs.devote_push(&4, &3).expect("devote error");
+        theirs.erase(&2).expect("erase error");
+        let merge = Flow::merge(&base, &ours, &theirs);
+        let mut conflicts = merge.conflicts.clone();
+        conflicts.sort_by_key(|c| format!("{}", c));
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::Removed { obj: 2 },
+                Conflict::Entity { obj: 3 },
+                Conflict::Parent { obj: 4 },
+                Conflict::Removed { obj: 5 },
+            ]
+        );
+        let flow = merge.flow;
+        assert_eq!(flow.node(&3).unwrap().entity, "ours");
+        assert_eq!(flow.parent(&4), Some(2));
+        // 2 and 5 are kept
+        assert_eq!(flow.parent(&5), Some(2));
+        assert_eq!(flow.children(&2), vec![5, 4]);
+        assert_eq!(flow.children(&0), vec![1, 2]);
+        // moves making a cycle together; theirs is moved back
+        let mut ours = base.clone();
+        ours.devote_push(&1, &5).expect("devote error");
+        let mut theirs = base.clone();
+        theirs.devote_push(&2, &3).expect("devote error");
+        theirs.node_mut(&4).unwrap().entity = "theirs";
+        let merge = Flow::merge(&base, &ours, &theirs);
+        assert_eq!(merge.conflicts, vec![Conflict::Parent { obj: 2 }]);
+        let flow = merge.flow;
+        flow.check().expect("check error");
+        assert_eq!(flow.parent(&1), Some(5));
+        assert_eq!(flow.parent(&2), Some(0));
+        assert!(flow.children(&3).is_empty());
+        assert_eq!(flow.node(&4).unwrap().entity, "theirs");
+    }
+}
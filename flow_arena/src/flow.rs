@@ -1,17 +1,32 @@
-use std::{collections::HashSet, hash::Hash};
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{Ancestors, Positions, Traverse, TraverseMode, TraverseOrder};
 
 pub trait Node<Id> {
     fn id(&self) -> &Id;
     fn parent(&self) -> Option<Id>;
     fn parent_set(&mut self, id: Id);
     fn parent_set_none(&mut self);
-    fn children(&self) -> Vec<Id>;
+    /// borrows the children, so visiting them allocates nothing.
+    fn children(&self) -> &[Id];
     fn children_ref_mut(&mut self) -> &mut Vec<Id>;
+    /// the position keys of the children, for the nodes keeping them.
+    fn positions(&self) -> Option<&Positions<Id>> {
+        None
+    }
+    fn positions_mut(&mut self) -> Option<&mut Positions<Id>> {
+        None
+    }
 }
 
 /// provides basic node-reflection abiliy; no check
 pub trait FlowBase {
-    type Id: Default + Clone + Hash + Eq;
+    type Id: Default + Clone + Hash + Eq + Debug;
     type Node: Default + Clone + Node<Self::Id>;
     /// ensures root and returns it
     fn orphan(&self) -> Vec<Self::Id>;
@@ -24,7 +39,24 @@ pub trait FlowBase {
         self.node(obj).map_or(None, |node| node.parent())
     }
     fn children(&self, obj: &Self::Id) -> Vec<Self::Id> {
-        self.node(obj).map_or(Vec::new(), |node| node.children())
+        self.node(obj)
+            .map_or(Vec::new(), |node| node.children().to_vec())
+    }
+    /// returns all the nodes linking to obj, its owner included; in no specific order.
+    fn linked_from(&self, obj: &Self::Id) -> Vec<Self::Id>;
+    /// returns all the links into the nodes owned by obj recursively from the nodes
+    /// outside, as (linker, linked); links into obj itself are not counted.
+    fn linked_from_outside(&self, obj: &Self::Id) -> Vec<(Self::Id, Self::Id)> {
+        let set = self.node_ownership_set(obj);
+        set.iter()
+            .filter(|&id| id != obj)
+            .flat_map(|id| {
+                self.linked_from(id)
+                    .into_iter()
+                    .filter(|linker| !set.contains(linker))
+                    .map(move |linker| (linker, id.clone()))
+            })
+            .collect()
     }
 
     /// returns parent's children
@@ -72,9 +104,14 @@ pub trait FlowBase {
         while !visit_set.is_empty() {
             let mut wait_set = HashSet::new();
             for obj in visit_set.iter() {
-                let children =
-                    self.node(&obj).map(|x| x.children()).unwrap_or_default();
-                wait_set.extend(children);
+                let children = self.node(obj).map_or(&[][..], |x| x.children());
+                // guards against pure link loops
+                wait_set.extend(
+                    children
+                        .iter()
+                        .filter(|id| !final_set.contains(*id))
+                        .cloned(),
+                );
             }
             final_set.extend(wait_set.iter().cloned());
             visit_set.clear();
@@ -94,15 +131,16 @@ pub trait FlowBase {
         while !visit_set.is_empty() {
             let mut wait_set = HashSet::new();
             for obj in visit_set.iter() {
-                let children =
-                    self.node(&obj).map(|x| x.children()).unwrap_or_default();
+                let children = self.node(obj).map_or(&[][..], |x| x.children());
                 let set: Vec<Self::Id> = children
-                    .into_iter()
+                    .iter()
                     .filter_map(|id| {
-                        self.node(&id)
+                        self.node(id)
                             .map(|node| {
-                                if node.parent() == Some(obj.clone()) {
-                                    Some(id)
+                                if node.parent() == Some(obj.clone())
+                                    && !final_set.contains(id)
+                                {
+                                    Some(id.clone())
                                 } else {
                                     None
                                 }
@@ -118,28 +156,169 @@ pub trait FlowBase {
         }
         final_set
     }
+
+    /// judge whether the obj is owned by the owner recursively, or is the owner itself
+    fn is_owned_recursively(&self, obj: &Self::Id, owner: &Self::Id) -> bool {
+        self.ownership_chain(obj, owner).is_some()
+    }
+
+    /// returns the chain of owners from obj up to the owner, both included;
+    /// None if the obj is not owned by the owner recursively
+    fn ownership_chain(
+        &self,
+        obj: &Self::Id,
+        owner: &Self::Id,
+    ) -> Option<Vec<Self::Id>> {
+        if obj == owner {
+            return Some(vec![obj.clone()]);
+        }
+        let mut chain = vec![obj.clone()];
+        for id in self.ancestors(obj) {
+            chain.push(id.clone());
+            if &id == owner {
+                return Some(chain);
+            }
+        }
+        None
+    }
+
+    /// lazily iterates over the owners of obj, from its parent up to its orphan
+    fn ancestors(&self, obj: &Self::Id) -> Ancestors<'_, Self> {
+        Ancestors::new(self, obj)
+    }
+
+    /// returns the orphan owning obj recursively, or obj itself if orphaned;
+    /// None if obj not found
+    fn root(&self, obj: &Self::Id) -> Option<Self::Id> {
+        if !self.contains_node(obj) {
+            return None;
+        }
+        Some(self.ancestors(obj).last().unwrap_or(obj.clone()))
+    }
+
+    /// returns the number of owners above obj; None if obj not found
+    fn depth(&self, obj: &Self::Id) -> Option<usize> {
+        if !self.contains_node(obj) {
+            return None;
+        }
+        Some(self.ancestors(obj).count())
+    }
+
+    /// returns the nearest node owning both, recursively; a node counts as owning itself
+    fn lowest_common_owner(
+        &self,
+        obj: &Self::Id,
+        dude: &Self::Id,
+    ) -> Option<Self::Id> {
+        if !self.contains_node(obj) || !self.contains_node(dude) {
+            return None;
+        }
+        let owner_set: HashSet<Self::Id> = Some(obj.clone())
+            .into_iter()
+            .chain(self.ancestors(obj))
+            .collect();
+        Some(dude.clone())
+            .into_iter()
+            .chain(self.ancestors(dude))
+            .find(|id| owner_set.contains(id))
+    }
+
+    /// returns the path from obj to dude through ownership, both included;
+    /// it goes up from obj to their lowest common owner, then down to dude.
+    ///
+    /// None if they don't share any owner.
+    fn ownership_path(
+        &self,
+        obj: &Self::Id,
+        dude: &Self::Id,
+    ) -> Option<Vec<Self::Id>> {
+        let owner = self.lowest_common_owner(obj, dude)?;
+        let mut path = self.ownership_chain(obj, &owner)?;
+        let mut down = self.ownership_chain(dude, &owner)?;
+        down.pop();
+        path.extend(down.into_iter().rev());
+        Some(path)
+    }
+
+    /// lazily traverses the nodes reachable from obj, including itself
+    fn traverse(
+        &self,
+        obj: &Self::Id,
+        order: TraverseOrder,
+        mode: TraverseMode,
+    ) -> Traverse<'_, Self> {
+        Traverse::new(self, vec![obj.clone()], order, mode)
+    }
+
+    /// lazily traverses the whole flow, starting from the orphans
+    fn traverse_orphan(
+        &self,
+        order: TraverseOrder,
+        mode: TraverseMode,
+    ) -> Traverse<'_, Self> {
+        Traverse::new(self, self.orphan(), order, mode)
+    }
+}
+
+/// How a flow checks itself after each operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckMode {
+    /// checks the touched nodes and panics on failure; iff in debug state
+    #[default]
+    Assert,
+    /// checks the touched nodes and returns the error; in all builds
+    Touched,
+    /// checks the whole flow and returns the error; in all builds, paranoid
+    Full,
+    /// never checks
+    Off,
 }
 
 /// checks the Flow's properties and see whether they hold
-pub trait FlowCheck {
-    fn check(&self) -> Result<(), (FlowError, String)>;
+pub trait FlowCheck: FlowBase {
+    /// checks the whole flow
+    fn check(&self) -> Result<(), FlowError<Self::Id>>;
+    /// checks after an operation; only the nodes touched if possible.
+    ///
+    /// Defaults to `check_assert`.
+    fn check_touched(&mut self) -> Result<(), FlowError<Self::Id>> {
+        self.check_assert();
+        Ok(())
+    }
     /// panics if anything went wrong. Iff in debug state.
     fn check_assert(&self) {
         if cfg!(debug_assertions) {
-            if let Err((err, current)) = self.check() {
-                panic!("{:?}{}", err, current)
+            if let Err(err) = self.check() {
+                panic!("{}", err)
             }
         }
     }
 }
 
+/// provides ability to apply a group of operations as a whole; all or nothing
+pub trait FlowTransact: FlowCheck + Sized {
+    /// runs `f` on the flow and checks the invariants once it's done;
+    /// restores the previous state if `f` or the check fails.
+    ///
+    /// Checks are deferred till the outermost transaction commits.
+    fn transact<T, F>(&mut self, f: F) -> Result<T, FlowError<Self::Id>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, FlowError<Self::Id>>;
+}
+
 /// provides hashmap functionality
 pub trait FlowMap: FlowBase + FlowCheck {
     /// inserts a node; returns err if id exists.
-    fn grow(&mut self, obj: Self::Node) -> Result<Self::Id, FlowError>;
+    fn grow(
+        &mut self,
+        obj: Self::Node,
+    ) -> Result<Self::Id, FlowError<Self::Id>>;
 
     /// removes a node; returns err if id not found under root
-    fn erase(&mut self, obj: &Self::Id) -> Result<Self::Node, FlowError>;
+    fn erase(
+        &mut self,
+        obj: &Self::Id,
+    ) -> Result<Self::Node, FlowError<Self::Id>>;
 }
 
 /// provides ability to link nodes; graph-ish
@@ -150,25 +329,36 @@ pub trait FlowLink: FlowBase + FlowCheck {
         obj: &Self::Id,
         owner: &Self::Id,
         nth: usize,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         if !self.contains_node(obj) {
-            Err(FlowError::NotExistObj)?
+            Err(FlowError::NotExistObj { obj: obj.clone() })?
+        }
+        if obj == owner {
+            Err(FlowError::SelfLink { obj: obj.clone() })?
         }
         let res = self
             .node_mut(owner)
-            .map(|owner| {
-                if owner.children().contains(obj) {
+            .map(|node| {
+                if node.children().contains(obj) {
                     return Ok(());
-                    // owner.children_ref_mut().retain(|id| id != obj)
+                    // node.children_ref_mut().retain(|id| id != obj)
                 }
-                if nth > owner.children().len() {
-                    Err(FlowError::InvalidLen)?
+                let len = node.children().len();
+                if nth > len {
+                    Err(FlowError::InvalidLen {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                        nth,
+                        len,
+                    })?
                 }
-                owner.children_ref_mut().insert(nth, obj.clone());
+                node.children_ref_mut().insert(nth, obj.clone());
                 Ok(())
             })
-            .unwrap_or(Err(FlowError::NotExistOwner));
-        self.check_assert();
+            .unwrap_or(Err(FlowError::NotExistOwner {
+                owner: owner.clone(),
+            }));
+        self.check_touched()?;
         res
     }
 
@@ -176,10 +366,10 @@ pub trait FlowLink: FlowBase + FlowCheck {
         &mut self,
         obj: &Self::Id,
         owner: &Self::Id,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         let nth = self.node(owner).map_or(0, |node| node.children().len());
         let res = self.link(obj, owner, nth);
-        self.check_assert();
+        self.check_touched()?;
         res
     }
     /// detaches a node from a non-owner link
@@ -187,43 +377,53 @@ pub trait FlowLink: FlowBase + FlowCheck {
         &mut self,
         obj: &Self::Id,
         owner: &Self::Id,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         if !self.contains_node(obj) {
-            Err(FlowError::NotExistObj)?
+            Err(FlowError::NotExistObj { obj: obj.clone() })?
         }
         let res = self
             .node_mut(owner)
-            .map(|owner| {
-                if !owner.children().contains(obj) {
-                    Err(FlowError::AbandonedChild)?
+            .map(|node| {
+                if !node.children().contains(obj) {
+                    Err(FlowError::AbandonedChild {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                    })?
                 }
-                owner.children_ref_mut().retain(|x| x != obj);
+                node.children_ref_mut().retain(|x| x != obj);
                 Ok(())
             })
-            .unwrap_or(Err(FlowError::NotExistOwner));
-        self.check_assert();
+            .unwrap_or(Err(FlowError::NotExistOwner {
+                owner: owner.clone(),
+            }));
+        self.check_touched()?;
         res
     }
 }
 
 /// provides ability to devote / own nodes; tree-ish
-pub trait FlowDevote: FlowBase + FlowLink + FlowCheck {
+pub trait FlowDevote: FlowBase + FlowLink + FlowCheck + FlowTransact {
     /// appoints and ensures an owner; also links to owner; won't do anything if aleady has the node as child
+    ///
+    /// Err if the owner is already owned by obj recursively, which forms an ownership cycle.
     fn devote(
         &mut self,
         obj: &Self::Id,
         owner: &Self::Id,
         nth: usize,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
+        if let Some(cycle) = self.ownership_chain(owner, obj) {
+            Err(FlowError::OwnershipCycle { cycle })?
+        }
         let res = self.link(obj, owner, nth).and_then(|_| {
             self.node_mut(obj)
-                .map(|obj| {
-                    obj.parent_set(owner.clone());
+                .map(|node| {
+                    node.parent_set(owner.clone());
                     Ok(())
                 })
-                .unwrap_or(Err(FlowError::NotExistObj))
+                .unwrap_or(Err(FlowError::NotExistObj { obj: obj.clone() }))
         });
-        self.check_assert();
+        self.check_touched()?;
         res
     }
 
@@ -231,20 +431,25 @@ pub trait FlowDevote: FlowBase + FlowLink + FlowCheck {
         &mut self,
         obj: &Self::Id,
         owner: &Self::Id,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
+        if let Some(cycle) = self.ownership_chain(owner, obj) {
+            Err(FlowError::OwnershipCycle { cycle })?
+        }
         let res = self.link_push(obj, owner).and_then(|_| {
-            self.node_mut(obj)
-                .map_or(Err(FlowError::NotExistObj), |obj| {
-                    obj.parent_set(owner.clone());
+            self.node_mut(obj).map_or(
+                Err(FlowError::NotExistObj { obj: obj.clone() }),
+                |node| {
+                    node.parent_set(owner.clone());
                     Ok(())
-                })
+                },
+            )
         });
-        self.check_assert();
+        self.check_touched()?;
         res
     }
 
     /// removes ownership; also detaches
-    fn decay(&mut self, obj: &Self::Id) -> Result<(), FlowError> {
+    fn decay(&mut self, obj: &Self::Id) -> Result<(), FlowError<Self::Id>> {
         let owner = self.node(obj).map(|x| x.parent());
         // Not owned by anyone
         if let Some(None) = owner {
@@ -253,16 +458,17 @@ pub trait FlowDevote: FlowBase + FlowLink + FlowCheck {
         let owner = owner.flatten();
         let res = self
             .node_mut(obj)
-            .map_or(Err(FlowError::NotExistObj), |obj| {
-                obj.parent_set_none();
+            .map_or(Err(FlowError::NotExistObj { obj: obj.clone() }), |node| {
+                node.parent_set_none();
                 Ok(())
             })
             .and_then(|_| {
-                owner.map_or(Err(FlowError::NotExistOwner), |owner| {
-                    self.detach(obj, &owner)
-                })
+                owner.map_or(
+                    Err(FlowError::IsOrphaned { obj: obj.clone() }),
+                    |owner| self.detach(obj, &owner),
+                )
             });
-        self.check_assert();
+        self.check_touched()?;
         res
     }
 
@@ -272,9 +478,11 @@ pub trait FlowDevote: FlowBase + FlowLink + FlowCheck {
         obj: &Self::Id,
         owner: &Self::Id,
         nth: usize,
-    ) -> Result<(), FlowError> {
-        self.decay(&obj)?;
-        self.devote(&obj, &owner, nth)
+    ) -> Result<(), FlowError<Self::Id>> {
+        self.transact(|flow| {
+            flow.decay(obj)?;
+            flow.devote(obj, owner, nth)
+        })
     }
 
     /// decay before devote
@@ -282,12 +490,18 @@ pub trait FlowDevote: FlowBase + FlowLink + FlowCheck {
         &mut self,
         obj: &Self::Id,
         owner: &Self::Id,
-    ) -> Result<(), FlowError> {
-        self.decay(&obj)?;
-        self.devote_push(&obj, &owner)
+    ) -> Result<(), FlowError<Self::Id>> {
+        self.transact(|flow| {
+            flow.decay(obj)?;
+            flow.devote_push(obj, owner)
+        })
     }
 }
 
+/// a flow cut or copied out, along with the order of its orphans.
+pub type DockResult<F> =
+    Result<(F, Vec<<F as FlowBase>::Id>), FlowError<<F as FlowBase>::Id>>;
+
 /// provides ability to cut (undock) and copy (snap) a flow from a node and paste it to another node (dock)
 pub trait FlowDock: FlowDevote + FlowCheck + Sized {
     /// adds all the nodes in another flow to self and mounts all orphan nodes to the designated node
@@ -299,7 +513,7 @@ pub trait FlowDock: FlowDevote + FlowCheck + Sized {
         &mut self,
         owner: &Self::Id,
         flow: Self,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         self.dock(owner, flow.orphan(), flow)
     }
     fn dock(
@@ -307,42 +521,29 @@ pub trait FlowDock: FlowDevote + FlowCheck + Sized {
         owner: &Self::Id,
         vec: Vec<Self::Id>,
         flow: Self,
-    ) -> Result<(), FlowError>;
+    ) -> Result<(), FlowError<Self::Id>>;
     /// moves all the nodes under the designated node out of the current flow and unmounts them
     ///
     /// Err if:
     /// 1. Obj not found.
     /// 2. Node linked by other nodes.
-    fn undock_impl(
-        &mut self,
-        obj: &Self::Id,
-        owned: bool,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError>;
-    fn undock(
-        &mut self,
-        obj: &Self::Id,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError> {
+    fn undock_impl(&mut self, obj: &Self::Id, owned: bool) -> DockResult<Self>;
+    fn undock(&mut self, obj: &Self::Id) -> DockResult<Self> {
         self.undock_impl(obj, false)
     }
-    fn undock_owned(
-        &mut self,
-        obj: &Self::Id,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError> {
+    fn undock_owned(&mut self, obj: &Self::Id) -> DockResult<Self> {
         self.undock_impl(obj, true)
     }
     /// clones all the nodes linked under the designated node and unmounts the clone
     ///
     /// Err if:
     /// 1. Obj not found.
-    fn snap(&self, obj: &Self::Id) -> Result<(Self, Vec<Self::Id>), FlowError>;
+    fn snap(&self, obj: &Self::Id) -> DockResult<Self>;
     /// clones all the nodes owned under the designated node and unmounts the clone
     ///
     /// Err if:
     /// 1. Obj not found.
-    fn snap_owned(
-        &self,
-        obj: &Self::Id,
-    ) -> Result<(Self, Vec<Self::Id>), FlowError>;
+    fn snap_owned(&self, obj: &Self::Id) -> DockResult<Self>;
 }
 
 /// Direction under FlowView:
@@ -351,7 +552,7 @@ pub trait FlowDock: FlowDevote + FlowCheck + Sized {
 /// - Backward - Up
 /// - Ascend - Left
 /// - Descend - Right
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
     Forward,
     Backward,
@@ -390,21 +591,32 @@ pub trait FlowShift: FlowBase + FlowDevote {
         &self,
         obj: &Self::Id,
         dir: Direction,
-    ) -> Result<Self::Id, FlowError> {
+    ) -> Result<Self::Id, FlowError<Self::Id>> {
         use Direction::*;
         match dir {
             Forward | Backward => {
-                let friends = self.friends(obj);
+                let owner = self
+                    .parent(obj)
+                    .ok_or(FlowError::IsOrphaned { obj: obj.clone() })?;
+                let friends = self.children(&owner);
                 let nth = if let Some(nth) =
                     friends.iter().position(|id| id == obj)
                 {
                     nth
                 } else {
-                    Err(FlowError::AbandonedChild)?
+                    return Err(FlowError::AbandonedChild {
+                        obj: obj.clone(),
+                        owner,
+                    });
                 };
                 let len = friends.len();
                 let walk =
-                    dir.walk(nth, len).map_err(|_| FlowError::InvalidLen)?;
+                    dir.walk(nth, len).map_err(|_| FlowError::InvalidLen {
+                        obj: obj.clone(),
+                        owner,
+                        nth,
+                        len,
+                    })?;
                 Ok(friends[walk].clone())
             }
             Ascend => {
@@ -425,7 +637,7 @@ pub trait FlowShift: FlowBase + FlowDevote {
         &self,
         obj: &Self::Id,
         dir: Direction,
-    ) -> Result<Self::Id, FlowError> {
+    ) -> Result<Self::Id, FlowError<Self::Id>> {
         use Direction::*;
         match dir {
             Forward | Backward => self.shuttle(obj, dir).map_or_else(
@@ -441,36 +653,53 @@ pub trait FlowShift: FlowBase + FlowDevote {
         &mut self,
         obj: &Self::Id,
         dir: Direction,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         use Direction::*;
         if !self.contains_node(obj) {
-            Err(FlowError::NotExistObj)?
+            Err(FlowError::NotExistObj { obj: obj.clone() })?
         }
         match dir {
             Forward | Backward => {
-                let owner = self.parent(obj).ok_or(FlowError::NotExistObj)?;
+                let owner = self
+                    .parent(obj)
+                    .ok_or(FlowError::IsOrphaned { obj: obj.clone() })?;
                 let nth =
-                    self.nth_friend(obj).ok_or(FlowError::AbandonedChild)?;
-                let len = self.friends(&owner).len();
+                    self.nth_friend(obj).ok_or(FlowError::AbandonedChild {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                    })?;
+                let len = self.friends(obj).len();
                 let walk =
-                    dir.walk(nth, len).map_err(|_| FlowError::InvalidLen)?;
-                self.decay(obj)?;
-                self.devote(obj, &owner, walk)?
+                    dir.walk(nth, len).map_err(|_| FlowError::InvalidLen {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                        nth,
+                        len,
+                    })?;
+                self.devote_loyal(obj, &owner, walk)?
             }
             Ascend => {
-                let parent = self.parent(obj).ok_or(FlowError::NotExistObj)?;
-                let owner =
-                    self.parent(&parent).ok_or(FlowError::IsOrphaned)?;
-                let nth = self
-                    .nth_friend(&parent)
-                    .ok_or(FlowError::AbandonedChild)?
-                    + 1;
-                self.decay(obj)?;
-                self.devote(obj, &owner, nth)?
+                let mut ancestors = self.ancestors(obj);
+                let parent = ancestors
+                    .next()
+                    .ok_or(FlowError::IsOrphaned { obj: obj.clone() })?;
+                let owner = ancestors.next().ok_or(FlowError::IsOrphaned {
+                    obj: parent.clone(),
+                })?;
+                let nth = self.nth_friend(&parent).ok_or(
+                    FlowError::AbandonedChild {
+                        obj: parent.clone(),
+                        owner: owner.clone(),
+                    },
+                )? + 1;
+                self.devote_loyal(obj, &owner, nth)?
             }
-            Descend => Err(FlowError::InvalidDir)?,
+            Descend => Err(FlowError::InvalidDir {
+                obj: obj.clone(),
+                dir,
+            })?,
         }
-        self.check_assert();
+        self.check_touched()?;
         Ok(())
     }
 
@@ -479,12 +708,12 @@ pub trait FlowShift: FlowBase + FlowDevote {
         &mut self,
         obj: &Self::Id,
         dir: Direction,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<Self::Id>> {
         use Direction::*;
         match dir {
             Forward | Backward => self.migrate(obj, dir).map_or_else(
                 |e| {
-                    if let FlowError::InvalidLen = e {
+                    if let FlowError::InvalidLen { .. } = e {
                         self.migrate(obj, Ascend)
                     } else {
                         Err(e)
@@ -498,30 +727,165 @@ pub trait FlowShift: FlowBase + FlowDevote {
 }
 
 /// Represents all possible Errors in Flow Operations
-#[derive(Debug)]
-pub enum FlowError {
-    NotExistObj,
-    NotExistOwner,
-    InvalidLen,
-    ExistGrow,
-    ExistDock,
-    OwnerDetach,
-    LinkedUndock,
-    InvalidDir,
+///
+/// `obj` is always the node being operated on, and `owner` the node linking to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowError<Id> {
+    NotExistObj {
+        obj: Id,
+    },
+    NotExistOwner {
+        owner: Id,
+    },
+    /// nth is out of the bound of the owner's children, whose length is len
+    InvalidLen {
+        obj: Id,
+        owner: Id,
+        nth: usize,
+        len: usize,
+    },
+    ExistGrow {
+        obj: Id,
+    },
+    ExistDock {
+        obj: Id,
+    },
+    OwnerDetach {
+        obj: Id,
+        owner: Id,
+    },
+    /// obj is linked by a node outside the undocked flow
+    LinkedUndock {
+        obj: Id,
+        owner: Id,
+    },
+    /// obj owns a stub, whose page is to be loaded first; see `PagedFlow`
+    StubErase {
+        obj: Id,
+        stub: Id,
+    },
+    InvalidDir {
+        obj: Id,
+        dir: Direction,
+    },
     /// certain operations requires node to be orphaned
-    NotOrphaned,
+    NotOrphaned {
+        obj: Id,
+    },
     /// certain operations requires node to be unorphaned
-    IsOrphaned,
+    IsOrphaned {
+        obj: Id,
+    },
 
-    NodeIdNotMatch,
-    NotExistParent,
-    NotExistChild,
+    /// obj is stored under a node whose id is `found`
+    NodeIdNotMatch {
+        obj: Id,
+        found: Id,
+    },
+    NotExistParent {
+        obj: Id,
+        owner: Id,
+    },
+    NotExistChild {
+        obj: Id,
+        owner: Id,
+    },
     /// potential parent doesn't have the child
-    AbandonedChild,
+    AbandonedChild {
+        obj: Id,
+        owner: Id,
+    },
+    /// a node appears more than once in the children of another
+    DuplicateChild {
+        obj: Id,
+        owner: Id,
+    },
+    /// a node has itself as one of its children
+    SelfLink {
+        obj: Id,
+    },
+    /// a node is owned by itself recursively; the cycle lists the owners in order
+    OwnershipCycle {
+        cycle: Vec<Id>,
+    },
+}
+
+impl<Id: Debug> fmt::Display for FlowError<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FlowError::*;
+        match self {
+            NotExistObj { obj } => write!(f, "node {:?} doesn't exist", obj),
+            NotExistOwner { owner } => {
+                write!(f, "owner {:?} doesn't exist", owner)
+            }
+            InvalidLen {
+                obj,
+                owner,
+                nth,
+                len,
+            } => write!(
+                f,
+                "position {} of {:?} is out of the {} children of {:?}",
+                nth, obj, len, owner
+            ),
+            ExistGrow { obj } => {
+                write!(f, "node {:?} already exists, can't grow", obj)
+            }
+            ExistDock { obj } => {
+                write!(f, "node {:?} already exists, can't dock", obj)
+            }
+            OwnerDetach { obj, owner } => {
+                write!(f, "can't detach {:?} from its owner {:?}", obj, owner)
+            }
+            LinkedUndock { obj, owner } => write!(
+                f,
+                "can't undock {:?} which is still linked by {:?}",
+                obj, owner
+            ),
+            StubErase { obj, stub } => write!(
+                f,
+                "can't erase {:?} which owns the unloaded stub {:?}",
+                obj, stub
+            ),
+            InvalidDir { obj, dir } => {
+                write!(f, "can't move {:?} towards {:?}", obj, dir)
+            }
+            NotOrphaned { obj } => write!(f, "node {:?} isn't orphaned", obj),
+            IsOrphaned { obj } => write!(f, "node {:?} is orphaned", obj),
+            NodeIdNotMatch { obj, found } => {
+                write!(f, "node {:?} is stored as {:?}", found, obj)
+            }
+            NotExistParent { obj, owner } => {
+                write!(f, "parent {:?} of {:?} doesn't exist", owner, obj)
+            }
+            NotExistChild { obj, owner } => {
+                write!(f, "child {:?} of {:?} doesn't exist", obj, owner)
+            }
+            AbandonedChild { obj, owner } => {
+                write!(f, "{:?} isn't a child of {:?}", obj, owner)
+            }
+            DuplicateChild { obj, owner } => {
+                write!(f, "{:?} appears twice in children of {:?}", obj, owner)
+            }
+            SelfLink { obj } => write!(f, "node {:?} links to itself", obj),
+            OwnershipCycle { cycle } => {
+                write!(f, "ownership forms a cycle: {:?}", cycle)
+            }
+        }
+    }
 }
 
+impl<Id: Debug> Error for FlowError<Id> {}
+
 /// The overall `Flow` trait, checking whether anything is missing.
 pub trait Flow:
-    FlowBase + FlowCheck + FlowMap + FlowLink + FlowDevote + FlowDock + FlowShift
+    FlowBase
+    + FlowCheck
+    + FlowTransact
+    + FlowMap
+    + FlowLink
+    + FlowDevote
+    + FlowDock
+    + FlowShift
 {
 }
@@ -8,6 +8,13 @@
 //! 1. a flow data model representation `trait Flow` and `struct FlowArena`
 //! 2. a node representation `trait Node` and `struct FlowNode`
 //! 3. variants like `GraphNode` and `GraphArena`.
+//! 4. a lazy traversal `Traverse` in pre-order, post-order or breadth-first, either tree-ish or graph-ish.
+//! 5. a structural `diff` and a three-way `merge` between flows, which reports `Conflict`s explicitly.
+//! 6. optional fractional `Position` keys for children, so that inserting between two friends is stable and mergeable.
+//! 7. a `Replica` of a flow to be edited apart, e.g. on several devices, whose ops commute and converge without a server.
+//! 8. a pluggable `NodeStore` for the nodes: a `HashMap` by default, or a `Slab` with generational `SlabKey`s as in `SlabArena`.
+//! 9. a `PagedFlow` whose subtrees can be unloaded into a `Pager`, e.g. on disk, leaving stubs that are loaded back on first access; its `PageIndex` is kept along with the flow to find the pages again.
+//! 10. structural `FlowStats` of a flow or of the part owned by a node: depths, fan-out, links and the largest subtrees.
 //!
 //! ## Motivation
 //!
@@ -54,17 +61,21 @@
 //! 1. directly use `struct FlowNode<Id>` and the `struct FlowArena<Id, Entity>` to represent a flow model
 //! 2. impl `trait Node<Id>` and `trait Flow` - see [Trait Implementation](#triat-implementation) for reference
 //! 3. when called upon, `use` the corresponding trait
+//! 4. enable feature `persistent` to store nodes in a structurally shared map (`im::HashMap`), so that cloning a flow is O(1)
+//! 5. pick a `CheckMode` to decide how the flow validates the nodes touched by each operation, from debug-only assertions to paranoid full checks returning errors
+//! 6. pick a `NodeStore` as the third parameter of `FlowArena`, e.g. `SlabArena`; `cargo bench` compares them
 //!
 //! ## Triat Implementation
 //!
 //! 1. `FlowBase`: provides basic node-reflection abiliy; no check
 //! 2. `FlowCheck`: checks the Flow's properties and see whether they hold
-//! 3. `FlowMap`: provides hashmap functionality
-//! 4. `FlowLink`: provides ability to link nodes; graph-ish
-//! 5. `FlowDevote`: provides ability to devote / own nodes; tree-ish
-//! 6. `FlowDock`: provides ability to cut (undock) and copy (snap) a flow from a node and paste it to another node (dock)
-//! 7. `FlowShift`: provides ability to move around in flow with `Direction`
-//! 8. `Flow`: checks all the traits are implemented
+//! 3. `FlowTransact`: provides ability to apply a group of operations as a whole; all or nothing
+//! 4. `FlowMap`: provides hashmap functionality
+//! 5. `FlowLink`: provides ability to link nodes; graph-ish
+//! 6. `FlowDevote`: provides ability to devote / own nodes; tree-ish
+//! 7. `FlowDock`: provides ability to cut (undock) and copy (snap) a flow from a node and paste it to another node (dock)
+//! 8. `FlowShift`: provides ability to move around in flow with `Direction`
+//! 9. `Flow`: checks all the traits are implemented
 //!
 //!
 //! ## Related App
@@ -77,19 +88,34 @@
 //!
 
 mod arena;
+mod diff;
 mod flow;
+mod paged;
+mod position;
+mod replica;
 mod serde_impl;
+mod stats;
+mod store;
+mod traverse;
 mod variants;
 
 pub use self::{
-    arena::{FlowArena, FlowNode},
+    arena::{FlowArena, FlowNode, FlowStep, OuterLinks},
+    diff::{Conflict, FlowDiff, FlowMerge},
     flow::{
-        Direction, Flow, FlowBase, FlowCheck, FlowDevote, FlowDock, FlowError,
-        FlowLink, FlowMap, FlowShift, Node,
+        CheckMode, Direction, DockResult, Flow, FlowBase, FlowCheck,
+        FlowDevote, FlowDock, FlowError, FlowLink, FlowMap, FlowShift,
+        FlowTransact, Node,
     },
-    variants::{FlowPure, GraphArena, GraphNode, NodePure},
+    paged::{PageError, PageIndex, PagedFlow, Pager},
+    position::{Position, Positions},
+    replica::{Op, OpKind, Patch, Replica, Stamp},
+    stats::FlowStats,
+    store::{NodeStore, Slab, SlabKey},
+    traverse::{Ancestors, Traverse, TraverseMode, TraverseOrder, Visit},
+    variants::{FlowPure, GraphArena, GraphNode, NodePure, SlabArena},
 };
 
 pub mod prelude {
-    pub use crate::{arena::*, flow::*};
+    pub use crate::{arena::*, flow::*, traverse::*};
 }
@@ -0,0 +1,512 @@
+#[cfg(feature = "serde_impl")]
+use serde::{Deserialize, Serialize};
+
+use super::{Flow, FlowBase, FlowError};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+/// Keeps the pages of a `PagedFlow` out of the way, e.g. on disk; a page
+/// is the flow undocked from its stub.
+pub trait Pager<F: FlowBase> {
+    type Error;
+    /// keeps the page of stub, in place of any before.
+    fn store(&mut self, stub: &F::Id, page: &F) -> Result<(), Self::Error>;
+    /// the page of stub, which is kept till discarded.
+    fn fetch(&mut self, stub: &F::Id) -> Result<F, Self::Error>;
+    fn discard(&mut self, stub: &F::Id) -> Result<(), Self::Error>;
+}
+
+/// Keeps the pages in memory.
+impl<F: FlowBase + Clone> Pager<F> for HashMap<F::Id, F> {
+    type Error = FlowError<F::Id>;
+    fn store(&mut self, stub: &F::Id, page: &F) -> Result<(), Self::Error> {
+        self.insert(stub.clone(), page.clone());
+        Ok(())
+    }
+    fn fetch(&mut self, stub: &F::Id) -> Result<F, Self::Error> {
+        self.get(stub)
+            .cloned()
+            .ok_or(FlowError::NotExistObj { obj: stub.clone() })
+    }
+    fn discard(&mut self, stub: &F::Id) -> Result<(), Self::Error> {
+        self.remove(stub);
+        Ok(())
+    }
+}
+
+/// Lends a pager out, e.g. to a `PagedFlow` made for a single call.
+impl<F: FlowBase, P: Pager<F>> Pager<F> for &mut P {
+    type Error = P::Error;
+    fn store(&mut self, stub: &F::Id, page: &F) -> Result<(), Self::Error> {
+        (**self).store(stub, page)
+    }
+    fn fetch(&mut self, stub: &F::Id) -> Result<F, Self::Error> {
+        (**self).fetch(stub)
+    }
+    fn discard(&mut self, stub: &F::Id) -> Result<(), Self::Error> {
+        (**self).discard(stub)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageError<Id, E> {
+    Flow(FlowError<Id>),
+    Pager(E),
+}
+
+impl<Id, E> From<FlowError<Id>> for PageError<Id, E> {
+    fn from(e: FlowError<Id>) -> Self {
+        PageError::Flow(e)
+    }
+}
+
+impl<Id: Debug, E: fmt::Display> fmt::Display for PageError<Id, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::Flow(e) => write!(f, "{}", e),
+            PageError::Pager(e) => write!(f, "pager failed: {}", e),
+        }
+    }
+}
+
+impl<Id: Debug, E: Debug + fmt::Display> Error for PageError<Id, E> {}
+
+/// What's known of a page without fetching it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde_impl",
+    serde(bound(deserialize = "Id: Deserialize<'de> + Hash + Eq"))
+)]
+struct Page<Id: Hash + Eq> {
+    /// the children of the stub, in order.
+    children: Vec<Id>,
+    /// all the nodes in the page.
+    members: HashSet<Id>,
+}
+
+/// The stubs of a `PagedFlow` and what's known of their pages; kept along
+/// with the flow, it leads to the pages again, e.g. after a restart.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde_impl",
+    serde(bound(deserialize = "Id: Deserialize<'de> + Hash + Eq"))
+)]
+pub struct PageIndex<Id: Hash + Eq> {
+    pages: HashMap<Id, Page<Id>>,
+}
+
+impl<Id: Hash + Eq> Default for PageIndex<Id> {
+    fn default() -> Self {
+        Self {
+            pages: HashMap::new(),
+        }
+    }
+}
+
+impl<Id: Hash + Eq> PageIndex<Id> {
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+    pub fn is_stub(&self, obj: &Id) -> bool {
+        self.pages.contains_key(obj)
+    }
+    pub fn stubs(&self) -> impl Iterator<Item = &Id> {
+        self.pages.keys()
+    }
+    /// keeps the stubs f holds to, along with the stubs in their pages;
+    /// e.g. those still in a flow cut short.
+    pub fn retain(&mut self, mut f: impl FnMut(&Id) -> bool)
+    where
+        Id: Clone,
+    {
+        let mut kept = HashSet::new();
+        let mut stubs: Vec<Id> =
+            self.pages.keys().filter(|stub| f(stub)).cloned().collect();
+        while let Some(stub) = stubs.pop() {
+            let members = &self.pages[&stub].members;
+            stubs.extend(
+                members
+                    .iter()
+                    .filter(|id| self.pages.contains_key(id))
+                    .cloned(),
+            );
+            kept.insert(stub);
+        }
+        self.pages.retain(|stub, _| kept.contains(stub));
+    }
+}
+
+/// A flow whose subtrees may be unloaded into a `Pager` and loaded back
+/// on demand.
+///
+/// An unloaded subtree leaves its root behind as a stub, childless to the
+/// flow but still knowing the ids of its children; the rest of it is
+/// fetched the first time any node in it is asked for. A subtree must be
+/// one `FlowDock::undock` can cut out, i.e. linked from nowhere outside.
+///
+/// The stubs are told by a `PageIndex`, which is to be kept with the flow
+/// for the pages to be found again; see `PagedFlow::into_parts`.
+///
+/// `flow` shows the loaded nodes only. Changes go through `flow_mut`,
+/// which loads the nodes given first. The ownership sets and `erase` count
+/// the unloaded nodes in, without fetching them.
+#[derive(Debug, Clone)]
+pub struct PagedFlow<F: FlowBase, P> {
+    flow: F,
+    pager: P,
+    index: PageIndex<F::Id>,
+    /// the stub each unloaded node is kept under.
+    home: HashMap<F::Id, F::Id>,
+}
+
+type PageResult<T, F, P> =
+    Result<T, PageError<<F as FlowBase>::Id, <P as Pager<F>>::Error>>;
+
+impl<F: Flow, P: Pager<F>> PagedFlow<F, P> {
+    pub fn new(flow: F, pager: P) -> Self {
+        Self::with_index(flow, pager, PageIndex::default())
+    }
+    /// the flow paged before, with the index kept along with it.
+    pub fn with_index(flow: F, pager: P, index: PageIndex<F::Id>) -> Self {
+        let mut home = HashMap::new();
+        for (stub, page) in index.pages.iter() {
+            for id in page.members.iter() {
+                home.insert(id.clone(), stub.clone());
+            }
+        }
+        Self {
+            flow,
+            pager,
+            index,
+            home,
+        }
+    }
+    /// the loaded nodes, the pager and the index, to be kept together.
+    pub fn into_parts(self) -> (F, P, PageIndex<F::Id>) {
+        (self.flow, self.pager, self.index)
+    }
+    /// the nodes loaded.
+    pub fn flow(&self) -> &F {
+        &self.flow
+    }
+    pub fn pager(&self) -> &P {
+        &self.pager
+    }
+    pub fn index(&self) -> &PageIndex<F::Id> {
+        &self.index
+    }
+    pub fn is_stub(&self, obj: &F::Id) -> bool {
+        self.index.is_stub(obj)
+    }
+    pub fn stubs(&self) -> impl Iterator<Item = &F::Id> {
+        self.index.stubs()
+    }
+    pub fn is_loaded(&self, obj: &F::Id) -> bool {
+        self.flow.contains_node(obj)
+    }
+    /// whether obj is in the flow, loaded or not.
+    pub fn contains_node(&self, obj: &F::Id) -> bool {
+        self.flow.contains_node(obj) || self.home.contains_key(obj)
+    }
+    pub fn node(&mut self, obj: &F::Id) -> PageResult<&F::Node, F, P> {
+        self.ensure(obj)?;
+        let node = self.flow.node(obj).expect("ensured");
+        Ok(node)
+    }
+    /// the node of obj, along with its children if it's a stub.
+    pub fn node_mut(&mut self, obj: &F::Id) -> PageResult<&mut F::Node, F, P> {
+        self.ensure(obj)?;
+        self.load_page(obj)?;
+        let node = self.flow.node_mut(obj).expect("ensured");
+        Ok(node)
+    }
+    /// the children of obj; a stub tells them without loading.
+    pub fn children(&mut self, obj: &F::Id) -> PageResult<Vec<F::Id>, F, P> {
+        if let Some(page) = self.index.pages.get(obj) {
+            return Ok(page.children.clone());
+        }
+        self.ensure(obj)?;
+        Ok(self.flow.children(obj))
+    }
+    /// the flow, with all the objs loaded and none of them a stub, e.g. to
+    /// devote or link them.
+    ///
+    /// The nodes under the other stubs are out of its reach: erase with
+    /// `PagedFlow::erase` instead, and never grow an unloaded node again.
+    pub fn flow_mut<'a>(
+        &mut self,
+        objs: impl IntoIterator<Item = &'a F::Id>,
+    ) -> PageResult<&mut F, F, P>
+    where
+        F::Id: 'a,
+    {
+        for obj in objs {
+            self.ensure(obj)?;
+            self.load_page(obj)?;
+        }
+        Ok(&mut self.flow)
+    }
+
+    /// moves the nodes owned by obj into the pager, leaving obj a stub.
+    pub fn unload(&mut self, obj: &F::Id) -> PageResult<(), F, P> {
+        self.ensure(obj)?;
+        if self.is_stub(obj) {
+            return Ok(());
+        }
+        let (page, children) = self.flow.undock(obj)?;
+        if let Err(err) = self.pager.store(obj, &page) {
+            self.flow.dock(obj, children, page)?;
+            return Err(PageError::Pager(err));
+        }
+        let members: HashSet<F::Id> = page
+            .orphan()
+            .iter()
+            .flat_map(|id| page.node_ownership_set(id))
+            .collect();
+        for id in members.iter() {
+            self.home.insert(id.clone(), obj.clone());
+        }
+        self.index
+            .pages
+            .insert(obj.clone(), Page { children, members });
+        Ok(())
+    }
+    /// loads obj, and the nodes under it if it's a stub.
+    pub fn load(&mut self, obj: &F::Id) -> PageResult<(), F, P> {
+        self.ensure(obj)?;
+        self.load_page(obj)
+    }
+    /// loads every page, till no stub is left.
+    pub fn load_all(&mut self) -> PageResult<(), F, P> {
+        while let Some(stub) = self.loaded_stub() {
+            self.load_page(&stub)?;
+        }
+        Ok(())
+    }
+    /// the flow with every page loaded.
+    pub fn into_flow(mut self) -> PageResult<F, F, P> {
+        self.load_all()?;
+        Ok(self.flow)
+    }
+
+    /// all the nodes owned by obj, including itself and those unloaded;
+    /// only the pages on the way to obj are loaded.
+    pub fn node_ownership_set(
+        &mut self,
+        obj: &F::Id,
+    ) -> PageResult<HashSet<F::Id>, F, P> {
+        self.ensure(obj)?;
+        let mut set = self.flow.node_ownership_set(obj);
+        let mut stubs: Vec<F::Id> =
+            set.iter().filter(|id| self.is_stub(id)).cloned().collect();
+        while let Some(stub) = stubs.pop() {
+            let page = &self.index.pages[&stub];
+            stubs.extend(
+                page.members.iter().filter(|id| self.is_stub(id)).cloned(),
+            );
+            set.extend(page.members.iter().cloned());
+        }
+        Ok(set)
+    }
+    /// erases obj and all it owns; the pages among them are discarded
+    /// without being fetched.
+    pub fn erase(&mut self, obj: &F::Id) -> PageResult<F::Node, F, P> {
+        self.ensure(obj)?;
+        let mut stubs: Vec<F::Id> = self
+            .flow
+            .node_ownership_set(obj)
+            .into_iter()
+            .filter(|id| self.is_stub(id))
+            .collect();
+        let node = self.flow.erase(obj)?;
+        let mut failed = None;
+        while let Some(stub) = stubs.pop() {
+            let page = self.index.pages.remove(&stub).expect("a stub");
+            for id in page.members {
+                if self.is_stub(&id) {
+                    stubs.push(id.clone());
+                }
+                self.home.remove(&id);
+            }
+            if let Err(err) = self.pager.discard(&stub) {
+                failed.get_or_insert(err);
+            }
+        }
+        match failed {
+            Some(err) => Err(PageError::Pager(err)),
+            None => Ok(node),
+        }
+    }
+
+    /// loads the pages on the way to obj, outermost first.
+    fn ensure(&mut self, obj: &F::Id) -> PageResult<(), F, P> {
+        let mut way = Vec::new();
+        let mut current = obj;
+        while !self.flow.contains_node(current) {
+            current = self
+                .home
+                .get(current)
+                .ok_or(FlowError::NotExistObj { obj: obj.clone() })?;
+            way.push(current.clone());
+        }
+        for stub in way.into_iter().rev() {
+            self.load_page(&stub)?;
+        }
+        Ok(())
+    }
+    /// docks the page of a loaded stub back; nothing to do if not a stub.
+    fn load_page(&mut self, stub: &F::Id) -> PageResult<(), F, P> {
+        let children = match self.index.pages.get(stub) {
+            Some(page) => page.children.clone(),
+            None => return Ok(()),
+        };
+        let page = self.pager.fetch(stub).map_err(PageError::Pager)?;
+        self.flow.dock(stub, children, page)?;
+        let page = self.index.pages.remove(stub).expect("a stub");
+        for id in page.members {
+            self.home.remove(&id);
+        }
+        self.pager.discard(stub).map_err(PageError::Pager)
+    }
+    fn loaded_stub(&self) -> Option<F::Id> {
+        self.index
+            .pages
+            .keys()
+            .find(|stub| self.flow.contains_node(stub))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FlowArena, FlowCheck, FlowDevote, FlowLink, FlowMap, FlowNode, Node,
+    };
+    type Arena = FlowArena<u64, FlowNode<u64, ()>>;
+    type Paged = PagedFlow<Arena, HashMap<u64, Arena>>;
+
+    /// 0 -> {1 -> {2, 3 -> {4}}, 5}
+    fn arena() -> Arena {
+        let mut flow = Arena::new();
+        for i in 0..6 {
+            flow.grow(FlowNode::from_id(i, ())).expect("grow");
+        }
+        for (obj, owner) in [(1, 0), (5, 0), (2, 1), (3, 1), (4, 3)] {
+            flow.devote_push(&obj, &owner).expect("devote");
+        }
+        flow
+    }
+
+    #[test]
+    fn unload_and_load() {
+        let origin = arena();
+        let mut paged = Paged::new(origin.clone(), HashMap::new());
+        paged.unload(&3).expect("unload");
+        paged.unload(&1).expect("unload");
+        paged.flow().check().expect("consistent");
+        assert!(paged.is_stub(&1) && paged.is_stub(&3));
+        assert!(!paged.is_loaded(&2) && !paged.is_loaded(&3));
+        assert!(paged.contains_node(&4));
+        assert!(paged.flow().children(&1).is_empty());
+
+        // a stub knows its children; the ownership counts them in
+        assert_eq!(paged.children(&1).expect("children"), vec![2, 3]);
+        let set = paged.node_ownership_set(&0).expect("set");
+        assert_eq!(set, (0..6).collect());
+        assert_eq!(paged.pager().len(), 2);
+
+        // the first access loads the pages on the way
+        assert_eq!(paged.node(&3).expect("node").parent(), Some(1));
+        assert!(paged.is_stub(&3) && !paged.is_stub(&1));
+        assert!(!paged.is_loaded(&4));
+        paged.flow().check().expect("consistent");
+        assert_eq!(paged.node(&4).expect("node").parent(), Some(3));
+        assert!(paged.pager().is_empty());
+        assert_eq!(paged.into_flow().expect("flow"), origin);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_impl")]
+    fn index() {
+        let origin = arena();
+        let mut paged = Paged::new(origin.clone(), HashMap::new());
+        paged.unload(&3).expect("unload");
+        paged.unload(&1).expect("unload");
+        let (flow, pager, index) = paged.into_parts();
+        assert_eq!(index.len(), 2);
+
+        // kept apart, as if across a restart
+        let json = serde_json::to_string(&index).expect("to json");
+        let index: PageIndex<u64> =
+            serde_json::from_str(&json).expect("from json");
+        let index_ = index.clone();
+        let mut paged = Paged::with_index(flow, pager, index);
+        assert!(paged.is_stub(&1) && paged.contains_node(&4));
+        assert_eq!(paged.node(&4).expect("node").parent(), Some(3));
+        assert_eq!(paged.into_flow().expect("flow"), origin);
+
+        // the stubs inside the pages kept are kept as well
+        let mut kept = index_.clone();
+        kept.retain(|stub| stub == &1);
+        assert_eq!(kept, index_);
+        kept.retain(|stub| stub == &3);
+        assert_eq!(kept.stubs().collect::<Vec<_>>(), vec![&3]);
+        kept.retain(|_| false);
+        assert!(kept.is_empty());
+    }
+
+    #[test]
+    fn erase() {
+        let mut paged = Paged::new(arena(), HashMap::new());
+        paged.unload(&3).expect("unload");
+        paged.unload(&1).expect("unload");
+        paged.erase(&1).expect("erase");
+        assert!(paged.pager().is_empty());
+        assert!(paged.stubs().next().is_none());
+        for id in 1..5 {
+            assert!(!paged.contains_node(&id));
+        }
+        assert_eq!(
+            paged.node(&4),
+            Err(PageError::Flow(FlowError::NotExistObj { obj: 4 }))
+        );
+        let flow = paged.into_flow().expect("flow");
+        flow.check().expect("consistent");
+        assert_eq!(flow.children(&0), vec![5]);
+    }
+
+    #[test]
+    fn linked() {
+        let mut paged = Paged::new(arena(), HashMap::new());
+        paged
+            .flow_mut([&5, &2])
+            .expect("load")
+            .link(&2, &5, 0)
+            .unwrap();
+        // still linked from outside
+        assert_eq!(
+            paged.unload(&1),
+            Err(PageError::Flow(FlowError::LinkedUndock {
+                obj: 2,
+                owner: 5
+            }))
+        );
+        assert!(paged.stubs().next().is_none());
+        paged.unload(&3).expect("unload");
+        // moving a node into a stub loads it first
+        let flow = paged.flow_mut([&5, &3]).expect("load");
+        flow.devote_push(&5, &3).expect("devote");
+        flow.check().expect("consistent");
+        assert_eq!(flow.children(&3), vec![4, 5]);
+    }
+}
@@ -0,0 +1,458 @@
+#[cfg(feature = "serde_impl")]
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{
+    FlowArena, FlowBase, FlowDevote, FlowError, FlowLink, FlowTransact, Node,
+    NodeStore,
+};
+
+const DIGITS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+/// A fractional key placing a child among its friends, compared as a
+/// string; there's always room for another key between two.
+///
+/// Unlike an index, a key stays put when friends come and go, so inserts
+/// made apart, e.g. on two copies of a flow, still land where they were
+/// meant to when merged. A key never ends with the digit `0`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+pub struct Position(String);
+
+impl Position {
+    fn digits(&self) -> Vec<usize> {
+        self.0
+            .bytes()
+            .map(|b| DIGITS.iter().position(|&d| d == b).unwrap_or(0))
+            .collect()
+    }
+    fn from_digits(digits: &[usize]) -> Self {
+        Self(digits.iter().map(|&d| DIGITS[d] as char).collect())
+    }
+    /// the key between lo and hi, both excluded; None for an open end.
+    ///
+    /// The same bounds always give the same key. If hi isn't above lo,
+    /// it's ignored.
+    pub fn between(lo: Option<&Position>, hi: Option<&Position>) -> Self {
+        let lo = lo.map_or(Vec::new(), |lo| lo.digits());
+        let mut hi = hi.map(|hi| hi.digits()).filter(|hi| hi > &lo);
+        let mut key = Vec::new();
+        for i in 0.. {
+            let l = lo.get(i).cloned().unwrap_or(0);
+            // a hi above lo can't run out before they differ
+            let h = hi.as_ref().map_or(BASE, |hi| hi[i]);
+            if h > l + 1 {
+                key.push((l + h) / 2);
+                break;
+            }
+            key.push(l);
+            if h > l {
+                // anything after this digit stays below hi
+                hi = None;
+            }
+        }
+        Self::from_digits(&key)
+    }
+    /// n keys in order between lo and hi, both excluded; as short as
+    /// bisecting allows.
+    pub fn spread(
+        lo: Option<&Position>,
+        hi: Option<&Position>,
+        n: usize,
+    ) -> Vec<Self> {
+        let mut keys = Vec::with_capacity(n);
+        Self::spread_into(lo, hi, n, &mut keys);
+        keys
+    }
+    fn spread_into(
+        lo: Option<&Position>,
+        hi: Option<&Position>,
+        n: usize,
+        keys: &mut Vec<Self>,
+    ) {
+        if n == 0 {
+            return;
+        }
+        let mid = Self::between(lo, hi);
+        Self::spread_into(lo, Some(&mid), n / 2, keys);
+        keys.push(mid.clone());
+        Self::spread_into(Some(&mid), hi, n - n / 2 - 1, keys);
+    }
+}
+
+impl Debug for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.0)
+    }
+}
+
+/// The position keys of some children of a node, by child id.
+///
+/// Kept apart from the children list, which still holds the order; an
+/// entry whose child is gone is ignored and dropped on the next
+/// `FlowArena::ensure_positions`.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+pub struct Positions<Id> {
+    data: Vec<(Id, Position)>,
+}
+
+impl<Id> Default for Positions<Id> {
+    fn default() -> Self {
+        Self { data: Vec::new() }
+    }
+}
+
+impl<Id: Debug> Debug for Positions<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.data.iter().map(|(id, key)| (id, key)))
+            .finish()
+    }
+}
+
+impl<Id> Positions<Id> {
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(Id, Position)> {
+        self.data.iter()
+    }
+    pub fn clear(&mut self) {
+        self.data.clear()
+    }
+}
+
+impl<Id: PartialEq + Clone> Positions<Id> {
+    pub fn get(&self, obj: &Id) -> Option<&Position> {
+        self.data
+            .iter()
+            .find(|(id, _)| id == obj)
+            .map(|(_, key)| key)
+    }
+    pub fn set(&mut self, obj: Id, key: Position) {
+        self.remove(&obj);
+        self.data.push((obj, key))
+    }
+    pub fn remove(&mut self, obj: &Id) -> Option<Position> {
+        let pos = self.data.iter().position(|(id, _)| id == obj)?;
+        Some(self.data.remove(pos).1)
+    }
+    /// renames the children, e.g. after copying under fresh ids; the ones
+    /// mapped to None are dropped.
+    pub fn remap(&self, map: impl Fn(&Id) -> Option<Id>) -> Self {
+        let data = self
+            .data
+            .iter()
+            .filter_map(|(id, key)| Some((map(id)?, key.clone())))
+            .collect();
+        Self { data }
+    }
+}
+
+/// the positions of the longest strictly increasing run of keys, gaps
+/// allowed; the ones to keep when rekeying.
+fn longest_run(keys: &[Option<&Position>]) -> Vec<bool> {
+    // tails[len]: the index ending the best run of length len + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; keys.len()];
+    for (i, key) in keys.iter().enumerate() {
+        let key = match key {
+            Some(key) => key,
+            None => continue,
+        };
+        let len = tails.partition_point(|&j| keys[j].expect("keyed") < *key);
+        prev[i] = len.checked_sub(1).map(|len| tails[len]);
+        if len == tails.len() {
+            tails.push(i)
+        } else {
+            tails[len] = i
+        }
+    }
+    let mut kept = vec![false; keys.len()];
+    let mut current = tails.last().cloned();
+    while let Some(i) = current {
+        kept[i] = true;
+        current = prev[i];
+    }
+    kept
+}
+
+/// position keys
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// the key of obj among the children of owner, if it has one.
+    pub fn position(&self, owner: &Id, obj: &Id) -> Option<Position> {
+        let node = self.node(owner)?;
+        if !node.children().contains(obj) {
+            return None;
+        }
+        node.positions()?.get(obj).cloned()
+    }
+    /// keys all the children of owner in their order, keeping as many of
+    /// the keys they have as possible; the keys of the children gone are
+    /// dropped. Does nothing to nodes keeping no keys.
+    ///
+    /// Index-based edits leave the keys behind, so the children moved by
+    /// them are rekeyed here.
+    pub fn ensure_positions(
+        &mut self,
+        owner: &Id,
+    ) -> Result<(), FlowError<Id>> {
+        let fixed = match self.positions_fixed(owner)? {
+            Some(fixed) => fixed,
+            None => return Ok(()),
+        };
+        let node = self.node_mut(owner).expect("owner exists");
+        let children = node.children().to_vec();
+        if let Some(positions) = node.positions_mut() {
+            positions.clear();
+            for (id, key) in children.into_iter().zip(fixed) {
+                positions.set(id, key)
+            }
+        }
+        Ok(())
+    }
+    /// the keys `ensure_positions` gives the children of owner, in their
+    /// order; None if there's nothing to fix.
+    fn positions_fixed(
+        &self,
+        owner: &Id,
+    ) -> Result<Option<Vec<Position>>, FlowError<Id>> {
+        let node = self.node(owner).ok_or(FlowError::NotExistOwner {
+            owner: owner.clone(),
+        })?;
+        let positions = match node.positions() {
+            Some(positions) => positions,
+            None => return Ok(None),
+        };
+        let children = node.children();
+        let keys: Vec<Option<&Position>> =
+            children.iter().map(|id| positions.get(id)).collect();
+        let kept = longest_run(&keys);
+        if positions.len() == children.len() && kept.iter().all(|&x| x) {
+            return Ok(None);
+        }
+        let mut fixed: Vec<Position> = Vec::with_capacity(children.len());
+        let mut i = 0;
+        while i < children.len() {
+            if kept[i] {
+                fixed.push(keys[i].expect("kept").clone());
+                i += 1;
+                continue;
+            }
+            let next = (i..children.len()).find(|&j| kept[j]);
+            let hi = next.map(|j| keys[j].expect("kept").clone());
+            let n = next.unwrap_or(children.len()) - i;
+            fixed.extend(Position::spread(fixed.last(), hi.as_ref(), n));
+            i += n;
+        }
+        Ok(Some(fixed))
+    }
+    /// the key for a child right after `after` among the children of
+    /// owner, or the first if None; the children are taken as keyed by
+    /// `ensure_positions`, though left as they are.
+    pub fn position_after(
+        &self,
+        owner: &Id,
+        after: Option<&Id>,
+    ) -> Result<Position, FlowError<Id>> {
+        let fixed = self.positions_fixed(owner)?;
+        let children = self.children(owner);
+        let nth = match after {
+            Some(after) => {
+                let pos = children.iter().position(|id| id == after).ok_or(
+                    FlowError::NotExistChild {
+                        obj: after.clone(),
+                        owner: owner.clone(),
+                    },
+                )?;
+                pos + 1
+            }
+            None => 0,
+        };
+        let key = |i: usize| match &fixed {
+            Some(fixed) => fixed.get(i).cloned(),
+            None => children.get(i).and_then(|id| self.position(owner, id)),
+        };
+        let lo = nth.checked_sub(1).and_then(key);
+        let hi = key(nth);
+        Ok(Position::between(lo.as_ref(), hi.as_ref()))
+    }
+    /// links obj to owner at the place of key, after the equal ones; a
+    /// child of owner already is moved there. All or nothing.
+    pub fn link_at(
+        &mut self,
+        obj: &Id,
+        owner: &Id,
+        key: Position,
+    ) -> Result<(), FlowError<Id>> {
+        self.transact(|flow| {
+            flow.ensure_positions(owner)?;
+            let node = flow.node_mut(owner).expect("owner exists");
+            node.children_ref_mut().retain(|id| id != obj);
+            let children = node.children().to_vec();
+            let nth = match node.positions_mut() {
+                Some(positions) => {
+                    positions.remove(obj);
+                    children
+                        .iter()
+                        .take_while(|id| positions.get(id) <= Some(&key))
+                        .count()
+                }
+                None => children.len(),
+            };
+            flow.link(obj, owner, nth)?;
+            let node = flow.node_mut(owner).expect("owner exists");
+            if let Some(positions) = node.positions_mut() {
+                positions.set(obj.clone(), key)
+            }
+            Ok(())
+        })
+    }
+    /// devotes obj to owner at the place of key, as `link_at` does.
+    pub fn devote_at(
+        &mut self,
+        obj: &Id,
+        owner: &Id,
+        key: Position,
+    ) -> Result<(), FlowError<Id>> {
+        self.transact(|flow| {
+            flow.link_at(obj, owner, key)?;
+            // already linked, so nth is ignored
+            flow.devote(obj, owner, 0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FlowMap, FlowNode};
+    type Flow = FlowArena<u64, FlowNode<u64, ()>>;
+
+    fn key(s: &str) -> Position {
+        Position(s.to_string())
+    }
+
+    #[test]
+    fn between() {
+        let mid = Position::between(None, None);
+        assert_eq!(mid, key("V"));
+        for (lo, hi) in [("1", "2"), ("1", "11"), ("Az", "B"), ("z", "zz1")] {
+            let (lo, hi) = (key(lo), key(hi));
+            let mid = Position::between(Some(&lo), Some(&hi));
+            assert!(lo < mid && mid < hi, "{:?} {:?} {:?}", lo, mid, hi);
+            assert!(!mid.0.ends_with('0'));
+        }
+        // always room in between, and the keys stay short
+        let (mut lo, hi) = (key("1"), key("2"));
+        for _ in 0..100 {
+            let mid = Position::between(Some(&lo), Some(&hi));
+            assert!(lo < mid && mid < hi);
+            lo = mid;
+        }
+        assert!(lo.0.len() < 20);
+        let keys = Position::spread(None, None, 1000);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|key| key.0.len() <= 3));
+    }
+
+    #[test]
+    fn link_at() {
+        let mut flow = Flow::new();
+        for i in 0..6 {
+            flow.grow(FlowNode::from_id(i, ())).expect("grow");
+        }
+        for i in 1..4 {
+            flow.devote_push(&i, &0).expect("devote");
+        }
+        // 0 -> {1, 2, 3}; between 1 and 2, on two copies apart
+        let mut ours = flow.clone();
+        let key = ours.position_after(&0, Some(&1)).expect("key");
+        ours.devote_at(&4, &0, key.clone()).expect("devote");
+        assert_eq!(ours.children(&0), vec![1, 4, 2, 3]);
+        let mut theirs = flow.clone();
+        let key = theirs.position_after(&0, Some(&2)).expect("key");
+        theirs.devote_at(&5, &0, key).expect("devote");
+        // index-based edits are rekeyed, keeping the other keys
+        theirs.devote(&3, &0, 0).expect("devote");
+        let flow_ = theirs.clone();
+        let key = flow_.position_after(&0, Some(&3)).expect("key");
+        theirs.ensure_positions(&0).expect("ensure");
+        assert_eq!(theirs.position_after(&0, Some(&3)).expect("key"), key);
+        assert_eq!(theirs.children(&0), flow_.children(&0));
+        assert!(flow_
+            .children(&0)
+            .windows(2)
+            .all(|w| theirs.position(&0, &w[0]) < theirs.position(&0, &w[1])));
+        // moving a child only changes its key
+        let key = ours.position_after(&0, Some(&3)).expect("key");
+        let before = ours.position(&0, &2);
+        ours.link_at(&1, &0, key).expect("link");
+        assert_eq!(ours.children(&0), vec![4, 2, 3, 1]);
+        assert_eq!(ours.position(&0, &2), before);
+        // all or nothing
+        let flow_ = ours.clone();
+        let key = ours.position_after(&0, None).expect("key");
+        assert!(ours.devote_at(&0, &1, key).is_err());
+        assert_eq!(ours, flow_);
+        assert!(matches!(
+            ours.position_after(&0, Some(&5)),
+            Err(FlowError::NotExistChild { .. })
+        ));
+    }
+
+    #[test]
+    fn merge() {
+        let mut base = Flow::new();
+        for i in 0..7 {
+            base.grow(FlowNode::from_id(i, ())).expect("grow");
+        }
+        for i in 1..4 {
+            let key = base.position_after(&0, base.children(&0).last());
+            base.devote_at(&i, &0, key.expect("key")).expect("devote");
+        }
+        // both insert between 1 and 2, and after 3
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        for (flow, obj) in [(&mut ours, 4), (&mut theirs, 5)] {
+            let key = flow.position_after(&0, Some(&1)).expect("key");
+            flow.devote_at(&obj, &0, key).expect("devote");
+        }
+        let key = theirs.position_after(&0, Some(&3)).expect("key");
+        theirs.devote_at(&6, &0, key).expect("devote");
+        // and reorder differently
+        let key = ours.position_after(&0, None).expect("key");
+        ours.link_at(&3, &0, key).expect("link");
+        let key = theirs.position_after(&0, Some(&3)).expect("key");
+        theirs.link_at(&2, &0, key).expect("link");
+
+        let merge = FlowArena::merge(&base, &ours, &theirs);
+        assert!(merge.is_clean(), "{:?}", merge.conflicts);
+        let children = merge.flow.children(&0);
+        assert_eq!(children[..2], [3, 1]);
+        assert_eq!(children[4..], [2, 6]);
+        // the same either way, but for the equal keys
+        let merge_ = FlowArena::merge(&base, &theirs, &ours);
+        let children_ = merge_.flow.children(&0);
+        assert_eq!(children_[..2], children[..2]);
+        assert_eq!(children_[4..], children[4..]);
+        let mut tied = children[2..4].to_vec();
+        tied.sort();
+        assert_eq!(tied, [4, 5]);
+        assert_eq!(merge.flow.position(&0, &6), theirs.position(&0, &6));
+    }
+}
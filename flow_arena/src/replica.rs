@@ -0,0 +1,516 @@
+#[cfg(feature = "serde_impl")]
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashSet, fmt::Debug, hash::Hash};
+
+use super::{
+    CheckMode, FlowArena, FlowBase, FlowDevote, FlowError, FlowLink, FlowMap,
+    FlowNode, FlowTransact, Position,
+};
+
+/// An edit to an entity, replicated as is.
+///
+/// Patches are applied in the order of their ops on every replica, so
+/// they don't need to commute themselves.
+pub trait Patch<Entity> {
+    fn patch(&self, entity: &mut Entity);
+}
+
+/// A whole entity patches by replacing; the last writer wins.
+impl<Entity: Clone> Patch<Entity> for Entity {
+    fn patch(&self, entity: &mut Entity) {
+        *entity = self.clone()
+    }
+}
+
+/// The total order of all ops: a Lamport clock, ties broken by replica.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+pub struct Stamp {
+    pub clock: u64,
+    pub replica: u64,
+}
+
+/// An edit to a replicated flow; any that doesn't hold by the time it's
+/// reached, e.g. on an erased node, is skipped.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+pub enum OpKind<Id, Entity, P> {
+    Grow {
+        obj: Id,
+        entity: Entity,
+    },
+    /// erases obj along with all it owns.
+    Erase {
+        obj: Id,
+    },
+    /// moves obj under owner, away from its former owner if any; skipped if
+    /// it would make an ownership cycle.
+    Devote {
+        obj: Id,
+        owner: Id,
+        key: Position,
+    },
+    /// makes obj an orphan.
+    Decay {
+        obj: Id,
+    },
+    Link {
+        obj: Id,
+        owner: Id,
+        key: Position,
+    },
+    /// removes a pure link.
+    Detach {
+        obj: Id,
+        owner: Id,
+    },
+    /// moves a child of owner to key.
+    Reorder {
+        obj: Id,
+        owner: Id,
+        key: Position,
+    },
+    Update {
+        obj: Id,
+        patch: P,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde_impl", derive(Serialize, Deserialize))]
+pub struct Op<Id, Entity, P> {
+    pub stamp: Stamp,
+    pub kind: OpKind<Id, Entity, P>,
+}
+
+type Arena<Id, Entity> = FlowArena<Id, FlowNode<Id, Entity>>;
+
+/// replays start from the nearest flow kept every so many ops.
+const CHECKPOINT: usize = 64;
+
+/// A copy of a flow edited apart from the others, e.g. on another device;
+/// the copies converge once they've all seen the same ops, in whatever
+/// order and however many times.
+///
+/// Every edit is an `Op` under a unique `Stamp`. The flow is the result of
+/// applying all the ops known in the order of stamps, so an op arriving
+/// late is put in place and the ops after it replayed. Ops that fail by
+/// then are skipped alike everywhere; among concurrent moves making an
+/// ownership cycle together, the one with the later stamp is dropped.
+///
+/// Children are placed by `Position` keys, so concurrent inserts keep
+/// their places; equal keys are ordered by stamp.
+#[derive(Clone, Debug)]
+pub struct Replica<Id, Entity, P = Entity>
+where
+    Id: Hash + Eq + Clone,
+    Entity: Clone,
+{
+    replica: u64,
+    clock: u64,
+    /// all ops known, in the order of stamps.
+    log: Vec<Op<Id, Entity, P>>,
+    seen: HashSet<Stamp>,
+    flow: Arena<Id, Entity>,
+    /// the flow before every `CHECKPOINT`-th op.
+    checkpoints: Vec<Arena<Id, Entity>>,
+}
+
+impl<Id, Entity, P> Replica<Id, Entity, P>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    Entity: Clone + Default + Debug,
+    P: Clone + Patch<Entity>,
+{
+    /// an empty flow; replica tells the copies apart, so it must be unique.
+    pub fn new(replica: u64) -> Self {
+        let mut flow = FlowArena::new();
+        flow.set_check_mode(CheckMode::Touched);
+        Self {
+            replica,
+            clock: 0,
+            log: Vec::new(),
+            seen: HashSet::new(),
+            checkpoints: vec![flow.clone()],
+            flow,
+        }
+    }
+    pub fn replica(&self) -> u64 {
+        self.replica
+    }
+    pub fn flow(&self) -> &Arena<Id, Entity> {
+        &self.flow
+    }
+    /// all ops known, in the order of stamps; to be sent to the others.
+    pub fn ops(&self) -> &[Op<Id, Entity, P>] {
+        &self.log
+    }
+    /// the ops the other side hasn't seen, judged by the stamps it has.
+    pub fn ops_missing(&self, seen: &HashSet<Stamp>) -> Vec<Op<Id, Entity, P>> {
+        self.log
+            .iter()
+            .filter(|op| !seen.contains(&op.stamp))
+            .cloned()
+            .collect()
+    }
+    pub fn stamps(&self) -> &HashSet<Stamp> {
+        &self.seen
+    }
+    /// takes in an op from another replica; seen ones are ignored.
+    pub fn apply(&mut self, op: Op<Id, Entity, P>) {
+        if !self.seen.insert(op.stamp) {
+            return;
+        }
+        self.clock = self.clock.max(op.stamp.clock);
+        let n = self.log.partition_point(|x| x.stamp < op.stamp);
+        self.log.insert(n, op);
+        if n + 1 == self.log.len() {
+            self.step(n);
+        } else {
+            self.replay(n);
+        }
+    }
+    pub fn apply_all(
+        &mut self,
+        ops: impl IntoIterator<Item = Op<Id, Entity, P>>,
+    ) {
+        for op in ops {
+            self.apply(op)
+        }
+    }
+    /// keeps the flow before op n if due.
+    fn checkpoint(&mut self, n: usize) {
+        if n == self.checkpoints.len() * CHECKPOINT {
+            self.checkpoints.push(self.flow.clone())
+        }
+    }
+    fn step(&mut self, n: usize) {
+        self.checkpoint(n);
+        // a failed op is skipped; the flow stays as it was
+        Self::apply_kind(&mut self.flow, &self.log[n].kind).ok();
+    }
+    /// rebuilds the flow from the latest checkpoint before op n.
+    fn replay(&mut self, n: usize) {
+        let k = n / CHECKPOINT;
+        self.checkpoints.truncate(k + 1);
+        self.flow = self.checkpoints[k].clone();
+        for n in k * CHECKPOINT..self.log.len() {
+            self.step(n)
+        }
+    }
+    fn apply_kind(
+        flow: &mut Arena<Id, Entity>,
+        kind: &OpKind<Id, Entity, P>,
+    ) -> Result<(), FlowError<Id>> {
+        use OpKind::*;
+        flow.transact(|flow| match kind {
+            Grow { obj, entity } => flow
+                .grow(FlowNode::from_id(obj.clone(), entity.clone()))
+                .map(|_| ()),
+            Erase { obj } => flow.erase(obj).map(|_| ()),
+            Devote { obj, owner, key } => {
+                if flow.parent(obj).is_some() {
+                    flow.decay(obj)?;
+                }
+                flow.devote_at(obj, owner, key.clone())
+            }
+            Decay { obj } => match flow.contains_node(obj) {
+                true => flow.decay(obj),
+                false => Err(FlowError::NotExistObj { obj: obj.clone() }),
+            },
+            Link { obj, owner, key } => {
+                if flow.children(owner).contains(obj) {
+                    return Ok(());
+                }
+                flow.link_at(obj, owner, key.clone())
+            }
+            Detach { obj, owner } => {
+                if flow.parent(obj).as_ref() == Some(owner) {
+                    return Err(FlowError::OwnerDetach {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                    });
+                }
+                flow.detach(obj, owner)
+            }
+            Reorder { obj, owner, key } => {
+                if !flow.children(owner).contains(obj) {
+                    return Err(FlowError::NotExistChild {
+                        obj: obj.clone(),
+                        owner: owner.clone(),
+                    });
+                }
+                flow.link_at(obj, owner, key.clone())
+            }
+            Update { obj, patch } => {
+                let node = flow
+                    .node_mut(obj)
+                    .ok_or(FlowError::NotExistObj { obj: obj.clone() })?;
+                patch.patch(&mut node.entity);
+                Ok(())
+            }
+        })
+    }
+    /// applies a local edit under a new stamp, the latest known, and
+    /// returns the op to be sent; nothing is kept if it fails.
+    fn local(
+        &mut self,
+        kind: OpKind<Id, Entity, P>,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        let stamp = Stamp {
+            clock: self.clock + 1,
+            replica: self.replica,
+        };
+        let n = self.log.len();
+        self.checkpoint(n);
+        Self::apply_kind(&mut self.flow, &kind)?;
+        self.clock = stamp.clock;
+        self.seen.insert(stamp);
+        let op = Op { stamp, kind };
+        self.log.push(op.clone());
+        Ok(op)
+    }
+    /// the key right after `after` among the children of owner, or the
+    /// first if None.
+    fn key_after(
+        &self,
+        owner: &Id,
+        after: Option<&Id>,
+    ) -> Result<Position, FlowError<Id>> {
+        self.flow.position_after(owner, after)
+    }
+}
+
+/// local edits
+impl<Id, Entity, P> Replica<Id, Entity, P>
+where
+    Id: Clone + Hash + Eq + Default + Debug,
+    Entity: Clone + Default + Debug,
+    P: Clone + Patch<Entity>,
+{
+    /// grows an orphan; obj must be unique among all replicas.
+    pub fn grow(
+        &mut self,
+        obj: Id,
+        entity: Entity,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        self.local(OpKind::Grow { obj, entity })
+    }
+    pub fn erase(
+        &mut self,
+        obj: Id,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        self.local(OpKind::Erase { obj })
+    }
+    /// moves obj under owner, right after `after` or first if None.
+    pub fn devote_after(
+        &mut self,
+        obj: Id,
+        owner: Id,
+        after: Option<&Id>,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        let key = self.key_after(&owner, after)?;
+        self.local(OpKind::Devote { obj, owner, key })
+    }
+    pub fn decay(
+        &mut self,
+        obj: Id,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        self.local(OpKind::Decay { obj })
+    }
+    /// links obj to owner, right after `after` or first if None.
+    pub fn link_after(
+        &mut self,
+        obj: Id,
+        owner: Id,
+        after: Option<&Id>,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        let key = self.key_after(&owner, after)?;
+        self.local(OpKind::Link { obj, owner, key })
+    }
+    pub fn detach(
+        &mut self,
+        obj: Id,
+        owner: Id,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        self.local(OpKind::Detach { obj, owner })
+    }
+    /// moves a child of owner right after `after`, or first if None.
+    pub fn reorder_after(
+        &mut self,
+        obj: Id,
+        owner: Id,
+        after: Option<&Id>,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        let key = self.key_after(&owner, after)?;
+        self.local(OpKind::Reorder { obj, owner, key })
+    }
+    pub fn update(
+        &mut self,
+        obj: Id,
+        patch: P,
+    ) -> Result<Op<Id, Entity, P>, FlowError<Id>> {
+        self.local(OpKind::Update { obj, patch })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FlowCheck;
+
+    type Rep = Replica<u64, String>;
+
+    /// xorshift, to shuffle the same way on every run.
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+        fn shuffle<T>(&mut self, vec: &mut Vec<T>) {
+            for i in (1..vec.len()).rev() {
+                let j = self.next(i + 1);
+                vec.swap(i, j);
+            }
+        }
+    }
+
+    /// syncs all replicas with all ops, shuffled and some sent twice.
+    fn sync(replicas: &mut [Rep], rng: &mut Rng) {
+        let mut ops: Vec<_> =
+            replicas.iter().flat_map(|r| r.ops().to_vec()).collect();
+        for replica in replicas.iter_mut() {
+            rng.shuffle(&mut ops);
+            let again = ops[..ops.len() / 4].to_vec();
+            replica.apply_all(ops.iter().cloned().chain(again));
+        }
+    }
+
+    fn assert_converged(replicas: &[Rep]) {
+        for replica in replicas.iter() {
+            replica.flow().check().expect("valid");
+            assert_eq!(replica.flow(), replicas[0].flow());
+        }
+    }
+
+    #[test]
+    fn move_cycle() {
+        let mut a = Rep::new(1);
+        for (obj, face) in [(1, "x"), (2, "y")] {
+            a.grow(obj, face.into()).expect("grow");
+        }
+        let mut b = Rep::new(2);
+        b.apply_all(a.ops().to_vec());
+        // x under y here, y under x there
+        let op_a = a.devote_after(1, 2, None).expect("devote");
+        let op_b = b.devote_after(2, 1, None).expect("devote");
+        a.apply(op_b.clone());
+        b.apply(op_a.clone());
+        assert_eq!(a.flow(), b.flow());
+        a.flow().check().expect("no cycle");
+        // the later stamp loses
+        let (winner, loser) = match op_a.stamp > op_b.stamp {
+            true => (op_b, op_a),
+            false => (op_a, op_b),
+        };
+        let parent = |op: &Op<u64, String, String>| match &op.kind {
+            OpKind::Devote { obj, owner, .. } => (*obj, *owner),
+            _ => unreachable!(),
+        };
+        let (obj, owner) = parent(&winner);
+        assert_eq!(a.flow().parent(&obj), Some(owner));
+        let (obj, _) = parent(&loser);
+        assert_eq!(a.flow().parent(&obj), None);
+    }
+
+    #[test]
+    fn concurrent() {
+        let mut a = Rep::new(1);
+        a.grow(0, "root".into()).expect("grow");
+        for obj in 1..4 {
+            a.grow(obj, String::new()).expect("grow");
+            let last = a.flow().children(&0).last().cloned();
+            a.devote_after(obj, 0, last.as_ref()).expect("devote");
+        }
+        let mut b = Rep::new(2);
+        b.apply_all(a.ops().to_vec());
+        // both insert after 1; erase 3 here while growing under it there
+        a.grow(10, "a".into()).expect("grow");
+        a.devote_after(10, 0, Some(&1)).expect("devote");
+        a.erase(3).expect("erase");
+        b.grow(20, "b".into()).expect("grow");
+        b.devote_after(20, 0, Some(&1)).expect("devote");
+        b.grow(21, String::new()).expect("grow");
+        b.devote_after(21, 3, None).expect("devote");
+        // edits to one entity; the last wins
+        a.update(2, "a".into()).expect("update");
+        b.update(2, "b".into()).expect("update");
+        let mut replicas = [a, b];
+        sync(&mut replicas, &mut Rng(7));
+        assert_converged(&replicas);
+        let flow = replicas[0].flow();
+        let children = flow.children(&0);
+        assert_eq!(children.len(), 4);
+        assert_eq!(children[0], 1);
+        assert_eq!(children[3], 2);
+        // the erase comes first, so the move under 3 is skipped
+        assert!(!flow.contains_node(&3));
+        assert_eq!(flow.parent(&21), None);
+        assert_eq!(flow.node(&2).unwrap().entity, "b");
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        let mut replicas: Vec<Rep> = (1..=4).map(Rep::new).collect();
+        let mut next = 0;
+        for _round in 0..12 {
+            for replica in replicas.iter_mut() {
+                for _ in 0..8 {
+                    let mut ids: Vec<u64> =
+                        replica.flow().node_map.keys().cloned().collect();
+                    ids.sort();
+                    let pick = |rng: &mut Rng| ids[rng.next(ids.len())];
+                    if ids.len() < 2 {
+                        next += 1;
+                        replica.grow(next, String::new()).expect("grow");
+                        continue;
+                    }
+                    let (obj, owner) = (pick(&mut rng), pick(&mut rng));
+                    let after =
+                        replica.flow().children(&owner).first().cloned();
+                    // failures are fine, they're just not sent
+                    let _ = match rng.next(9) {
+                        0 | 1 => {
+                            next += 1;
+                            replica.grow(next, String::new())
+                        }
+                        2 => replica.erase(obj),
+                        3 | 4 => {
+                            replica.devote_after(obj, owner, after.as_ref())
+                        }
+                        5 => replica.decay(obj),
+                        6 => replica.link_after(obj, owner, None),
+                        7 => replica.reorder_after(obj, owner, after.as_ref()),
+                        _ => replica.update(obj, format!("{}", next)),
+                    };
+                }
+            }
+            // some exchange ops in between
+            let (x, y) = (rng.next(4), rng.next(4));
+            let ops = replicas[x].ops().to_vec();
+            replicas[y].apply_all(ops);
+        }
+        sync(&mut replicas, &mut rng);
+        assert_converged(&replicas);
+        assert!(replicas[0].ops().len() > 200);
+        // a fresh replica in reverse order ends up the same
+        let mut fresh = Rep::new(5);
+        fresh.apply_all(replicas[0].ops().iter().rev().cloned());
+        assert_eq!(fresh.flow(), replicas[0].flow());
+    }
+}
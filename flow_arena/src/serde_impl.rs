@@ -1,41 +1,103 @@
-use super::{FlowArena, FlowNode, Node};
+#[cfg(feature = "serde_impl")]
+use super::{FlowArena, Node, NodeStore};
+#[cfg(feature = "serde_impl")]
 use std::{fmt, hash::Hash};
 
 #[cfg(feature = "serde_impl")]
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+/// Nodes are written level by level from the roots, with the number of
+/// nodes on each level ahead, so that a reader may stop once it has the
+/// levels or the subtree it's after; a flow whose nodes can't all be
+/// reached from the roots is written sorted by id instead. Either way an
+/// unchanged flow always serializes to the same bytes regardless of the
+/// map's inner order.
 #[cfg(feature = "serde_impl")]
-impl<Id, Entity> Serialize for FlowArena<Id, FlowNode<Id, Entity>>
+impl<Id, FlowNode, Store> Serialize for FlowArena<Id, FlowNode, Store>
 where
-    Id: Serialize + Hash + Eq + Clone,
-    Entity: Serialize + Clone,
+    Id: Serialize + Hash + Eq + Ord + Clone,
+    FlowNode: Node<Id> + Serialize + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     fn serialize<S: Serializer>(
         &self,
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
-        let mut flow = serializer.serialize_struct("Flow", 2)?;
-        let seq: Vec<&FlowNode<Id, Entity>> = self.node_map.values().collect();
+        if let Some(levels) = self.levels() {
+            let mut flow = serializer.serialize_struct("Flow", 2)?;
+            let sizes: Vec<usize> = levels.iter().map(Vec::len).collect();
+            let seq: Vec<&FlowNode> = levels.into_iter().flatten().collect();
+            flow.serialize_field("levels", &sizes)?;
+            flow.serialize_field("node_map", &seq)?;
+            return flow.end();
+        }
+        let mut flow = serializer.serialize_struct("Flow", 1)?;
+        let mut seq: Vec<&FlowNode> = self.node_map.values().collect();
+        seq.sort_by(|a, b| a.id().cmp(b.id()));
         flow.serialize_field("node_map", &seq)?;
         flow.end()
     }
 }
 
+#[cfg(feature = "serde_impl")]
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Hash + Eq + Ord + Clone,
+    FlowNode: Node<Id> + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// the roots sorted by id, then level by level the children owned,
+    /// in the order of their owners; None if some node is left out.
+    fn levels(&self) -> Option<Vec<Vec<&FlowNode>>> {
+        let mut level: Vec<&FlowNode> = self
+            .node_map
+            .values()
+            .filter(|node| node.parent().is_none())
+            .collect();
+        level.sort_by(|a, b| a.id().cmp(b.id()));
+        let mut levels = Vec::new();
+        let mut count = 0;
+        while !level.is_empty() {
+            let next = level
+                .iter()
+                .flat_map(|owner| {
+                    owner.children().iter().filter_map(move |child| {
+                        let node = self.node_map.get(child)?;
+                        (node.parent().as_ref() == Some(owner.id()))
+                            .then_some(node)
+                    })
+                })
+                .collect();
+            count += level.len();
+            levels.push(std::mem::replace(&mut level, next));
+        }
+        (count == self.node_map.len()).then_some(levels)
+    }
+}
+
 #[cfg(feature = "serde_impl")]
 use serde::de::{
     self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor,
 };
 #[cfg(feature = "serde_impl")]
 use std::marker::PhantomData;
+
+/// marks what a visitor builds, without owning one.
 #[cfg(feature = "serde_impl")]
-impl<'de, Id, Entity> Deserialize<'de> for FlowArena<Id, FlowNode<Id, Entity>>
+type Building<T> = PhantomData<fn() -> T>;
+
+#[cfg(feature = "serde_impl")]
+impl<'de, Id, FlowNode, Store> Deserialize<'de>
+    for FlowArena<Id, FlowNode, Store>
 where
     Id: Deserialize<'de> + Clone + Hash + Eq,
-    Entity: Deserialize<'de> + Clone,
+    FlowNode: Deserialize<'de> + Node<Id> + Clone,
+    Store: NodeStore<Id, FlowNode>,
 {
     fn deserialize<D: Deserializer<'de>>(
         deserializer: D,
     ) -> Result<Self, D::Error> {
         enum Field {
+            Levels,
             NodeMap,
         }
         impl<'de> Deserialize<'de> for Field {
@@ -51,7 +113,7 @@ where
                         &self,
                         formatter: &mut fmt::Formatter,
                     ) -> fmt::Result {
-                        formatter.write_str("`node_map`")
+                        formatter.write_str("`levels` or `node_map`")
                     }
 
                     fn visit_str<E>(self, value: &str) -> Result<Field, E>
@@ -59,6 +121,7 @@ where
                         E: de::Error,
                     {
                         match value {
+                            "levels" => Ok(Field::Levels),
                             "node_map" => Ok(Field::NodeMap),
                             _ => Err(de::Error::unknown_field(value, FIELDS)),
                         }
@@ -69,17 +132,22 @@ where
             }
         }
 
-        struct FlowVisitor<Id: Hash + Eq + Clone, Entity: Clone> {
-            marker: PhantomData<fn() -> FlowArena<Id, FlowNode<Id, Entity>>>,
+        struct FlowVisitor<
+            Id: Hash + Eq + Clone,
+            FlowNode: Node<Id> + Clone,
+            Store,
+        > {
+            marker: Building<FlowArena<Id, FlowNode, Store>>,
         }
 
         impl<
                 'de,
                 Id: Deserialize<'de> + Clone + Hash + Eq,
-                Entity: Deserialize<'de> + Clone,
-            > Visitor<'de> for FlowVisitor<Id, Entity>
+                FlowNode: Deserialize<'de> + Node<Id> + Clone,
+                Store: NodeStore<Id, FlowNode>,
+            > Visitor<'de> for FlowVisitor<Id, FlowNode, Store>
         {
-            type Value = FlowArena<Id, FlowNode<Id, Entity>>;
+            type Value = FlowArena<Id, FlowNode, Store>;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
                 formatter.write_str("struct FlowArena")
@@ -89,14 +157,14 @@ where
             where
                 V: SeqAccess<'de>,
             {
-                let node_vec: Vec<FlowNode<Id, Entity>> =
-                    seq.next_element()?
-                        .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let node_vec: Vec<FlowNode> = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
                 let node_map = node_vec
                     .into_iter()
                     .map(|node| (node.id().clone(), node))
                     .collect();
-                Ok(Self::Value { node_map })
+                Ok(FlowArena::from_node_map(node_map))
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -106,14 +174,17 @@ where
                 let mut node_map = None;
                 while let Some(key) = map.next_key()? {
                     match key {
+                        // only of use to a reader that stops half way
+                        Field::Levels => {
+                            let _: Vec<usize> = map.next_value()?;
+                        }
                         Field::NodeMap => {
                             if node_map.is_some() {
                                 return Err(de::Error::duplicate_field(
                                     "node_map",
                                 ));
                             }
-                            let node_vec: Vec<FlowNode<Id, Entity>> =
-                                map.next_value()?;
+                            let node_vec: Vec<FlowNode> = map.next_value()?;
                             node_map = Some(
                                 node_vec
                                     .into_iter()
@@ -125,11 +196,11 @@ where
                 }
                 let node_map = node_map
                     .ok_or_else(|| de::Error::missing_field("node_map"))?;
-                Ok(Self::Value { node_map })
+                Ok(FlowArena::from_node_map(node_map))
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["node_map"];
+        const FIELDS: &'static [&'static str] = &["levels", "node_map"];
         deserializer.deserialize_struct(
             "Flow",
             FIELDS,
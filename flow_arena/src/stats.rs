@@ -0,0 +1,211 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{
+    FlowArena, FlowBase, Node, NodeStore, TraverseMode, TraverseOrder,
+};
+
+/// Structural metrics of a flow, or of the part owned by a node.
+///
+/// Only the nodes owned from the roots are counted, i.e. from the orphans
+/// or from the node given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowStats<Id> {
+    /// nodes counted
+    pub nodes: usize,
+    /// nodes without a parent
+    pub orphans: usize,
+    /// nodes without children
+    pub leaves: usize,
+    /// the number of nodes at each depth, from the roots down
+    pub depths: Vec<usize>,
+    /// the number of nodes by their number of children
+    pub fan_out: BTreeMap<usize, usize>,
+    /// links to children owned by the linking node
+    pub owned_links: usize,
+    /// links to children owned elsewhere, or by none
+    pub pure_links: usize,
+    /// links to children not in the flow; none in a checked flow
+    pub dangling_links: usize,
+    /// the largest subtrees as (root, size), largest first; ties by id
+    pub largest: Vec<(Id, usize)>,
+}
+
+impl<Id> Default for FlowStats<Id> {
+    fn default() -> Self {
+        Self {
+            nodes: 0,
+            orphans: 0,
+            leaves: 0,
+            depths: Vec::new(),
+            fan_out: BTreeMap::new(),
+            owned_links: 0,
+            pure_links: 0,
+            dangling_links: 0,
+            largest: Vec::new(),
+        }
+    }
+}
+
+impl<Id> FlowStats<Id> {
+    /// the depth of the deepest node; 0 for the roots alone.
+    pub fn max_depth(&self) -> usize {
+        self.depths.len().saturating_sub(1)
+    }
+    pub fn max_fan_out(&self) -> usize {
+        self.fan_out.keys().last().copied().unwrap_or(0)
+    }
+    pub fn links(&self) -> usize {
+        self.owned_links + self.pure_links + self.dangling_links
+    }
+    /// the average number of children; 0 for no nodes.
+    pub fn mean_fan_out(&self) -> f64 {
+        match self.nodes {
+            0 => 0.,
+            nodes => self.links() as f64 / nodes as f64,
+        }
+    }
+    /// the share of the links that own their children, from 0 (a pure
+    /// graph) to 1 (a pure tree); None without links.
+    pub fn ownership_ratio(&self) -> Option<f64> {
+        match self.links() {
+            0 => None,
+            links => Some(self.owned_links as f64 / links as f64),
+        }
+    }
+}
+
+impl<Id> fmt::Display for FlowStats<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, max depth {}, {} orphans, {} pure links, {} dangling",
+            self.nodes,
+            self.max_depth(),
+            self.orphans,
+            self.pure_links,
+            self.dangling_links
+        )
+    }
+}
+
+impl<Id, FlowNode, Store> FlowArena<Id, FlowNode, Store>
+where
+    Id: Clone + Hash + Eq + Ord + Default + Debug,
+    FlowNode: Node<Id> + Default + Debug + Clone,
+    Store: NodeStore<Id, FlowNode>,
+{
+    /// measures the whole flow, keeping the top largest subtrees.
+    pub fn stats(&self, top: usize) -> FlowStats<Id> {
+        self.stats_from(self.orphan(), top)
+    }
+    /// measures obj and all it owns, with obj at depth 0; None if obj not
+    /// found.
+    pub fn stats_under(&self, obj: &Id, top: usize) -> Option<FlowStats<Id>> {
+        if !self.contains_node(obj) {
+            return None;
+        }
+        Some(self.stats_from(vec![obj.clone()], top))
+    }
+    fn stats_from(&self, roots: Vec<Id>, top: usize) -> FlowStats<Id> {
+        let mut stats = FlowStats::default();
+        let mut sizes: HashMap<Id, usize> = HashMap::new();
+        for root in roots {
+            // children come before their owner, so their sizes are known
+            let visits = self.traverse(
+                &root,
+                TraverseOrder::PostOrder,
+                TraverseMode::Owned,
+            );
+            for visit in visits {
+                let node = self.node_map.get(&visit.id).expect("visited");
+                let children = node.children();
+                stats.nodes += 1;
+                if node.parent().is_none() {
+                    stats.orphans += 1;
+                }
+                if children.is_empty() {
+                    stats.leaves += 1;
+                }
+                if stats.depths.len() <= visit.depth {
+                    stats.depths.resize(visit.depth + 1, 0);
+                }
+                stats.depths[visit.depth] += 1;
+                *stats.fan_out.entry(children.len()).or_insert(0) += 1;
+                let mut size = 1;
+                for child in children {
+                    match self.node_map.get(child) {
+                        Some(dude)
+                            if dude.parent().as_ref() == Some(node.id()) =>
+                        {
+                            stats.owned_links += 1;
+                            size += sizes.get(child).copied().unwrap_or(0);
+                        }
+                        Some(_) => stats.pure_links += 1,
+                        None => stats.dangling_links += 1,
+                    }
+                }
+                sizes.insert(visit.id, size);
+            }
+        }
+        let mut largest: Vec<(Id, usize)> = sizes.into_iter().collect();
+        largest.sort_by(|(x, a), (y, b)| b.cmp(a).then_with(|| x.cmp(y)));
+        largest.truncate(top);
+        stats.largest = largest;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FlowArena, FlowDevote, FlowLink, FlowMap, FlowNode};
+
+    #[test]
+    fn stats() {
+        let mut flow: FlowArena<u64, FlowNode<u64, ()>> = FlowArena::new();
+        for i in 0..8 {
+            flow.grow(FlowNode::from_id(i, ())).expect("grow");
+        }
+        // 0 -> {1 -> {2, 3 -> {4}}, 5}, 6 -> {7}
+        for (obj, owner) in [(1, 0), (5, 0), (2, 1), (3, 1), (4, 3), (7, 6)] {
+            flow.devote_push(&obj, &owner).expect("devote");
+        }
+        flow.link(&4, &5, 0).expect("link");
+        flow.link(&7, &2, 0).expect("link");
+
+        let stats = flow.stats(2);
+        assert_eq!(stats.nodes, 8);
+        assert_eq!(stats.orphans, 2);
+        assert_eq!(stats.leaves, 2);
+        assert_eq!(stats.depths, vec![2, 3, 2, 1]);
+        assert_eq!(stats.max_depth(), 3);
+        assert_eq!(stats.max_fan_out(), 2);
+        assert_eq!(stats.fan_out.get(&1), Some(&4));
+        assert_eq!((stats.owned_links, stats.pure_links), (6, 2));
+        assert_eq!(stats.dangling_links, 0);
+        assert_eq!(stats.ownership_ratio(), Some(0.75));
+        assert_eq!(stats.largest, vec![(0, 6), (1, 4)]);
+        assert_eq!(
+            stats.to_string(),
+            "8 nodes, max depth 3, 2 orphans, 2 pure links, 0 dangling"
+        );
+
+        let stats = flow.stats_under(&1, 2).expect("found");
+        assert_eq!(stats.nodes, 4);
+        assert_eq!(stats.orphans, 0);
+        assert_eq!(stats.depths, vec![1, 2, 1]);
+        assert_eq!((stats.owned_links, stats.pure_links), (3, 1));
+        assert_eq!(stats.largest, vec![(1, 4), (3, 2)]);
+        // equal sizes come by id, the same every time
+        let stats = flow.stats(8);
+        assert_eq!(stats.largest[2..5], [(3, 2), (6, 2), (2, 1)]);
+        assert!(flow.stats_under(&9, 3).is_none());
+        assert_eq!(
+            FlowArena::<u64, FlowNode<u64, ()>>::new().stats(3).nodes,
+            0
+        );
+    }
+}
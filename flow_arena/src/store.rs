@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+/// The storage of the nodes of a `FlowArena`, by id.
+///
+/// Implemented by `HashMap`, `im::HashMap` under feature `persistent`, and
+/// `Slab`; the flow traits work the same on any of them.
+pub trait NodeStore<Id, Node>:
+    Clone
+    + Default
+    + FromIterator<(Id, Node)>
+    + Extend<(Id, Node)>
+    + IntoIterator<Item = (Id, Node)>
+{
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn contains_key(&self, id: &Id) -> bool {
+        self.get(id).is_some()
+    }
+    fn get(&self, id: &Id) -> Option<&Node>;
+    fn get_mut(&mut self, id: &Id) -> Option<&mut Node>;
+    /// returns the node replaced, if any.
+    fn insert(&mut self, id: Id, node: Node) -> Option<Node>;
+    fn remove(&mut self, id: &Id) -> Option<Node>;
+    /// keeps only the nodes f holds for.
+    fn retain(&mut self, f: impl FnMut(&Id, &Node) -> bool);
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a Id>
+    where
+        Id: 'a,
+        Node: 'a;
+    fn values<'a>(&'a self) -> impl Iterator<Item = &'a Node>
+    where
+        Id: 'a,
+        Node: 'a;
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Id, &'a Node)>
+    where
+        Id: 'a,
+        Node: 'a;
+    fn iter_mut<'a>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (&'a Id, &'a mut Node)>
+    where
+        Id: 'a,
+        Node: 'a;
+}
+
+impl<Id, Node> NodeStore<Id, Node> for HashMap<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone,
+{
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+    fn contains_key(&self, id: &Id) -> bool {
+        HashMap::contains_key(self, id)
+    }
+    fn get(&self, id: &Id) -> Option<&Node> {
+        HashMap::get(self, id)
+    }
+    fn get_mut(&mut self, id: &Id) -> Option<&mut Node> {
+        HashMap::get_mut(self, id)
+    }
+    fn insert(&mut self, id: Id, node: Node) -> Option<Node> {
+        HashMap::insert(self, id, node)
+    }
+    fn remove(&mut self, id: &Id) -> Option<Node> {
+        HashMap::remove(self, id)
+    }
+    fn retain(&mut self, mut f: impl FnMut(&Id, &Node) -> bool) {
+        HashMap::retain(self, |id, node| f(id, node))
+    }
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a Id>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        HashMap::keys(self)
+    }
+    fn values<'a>(&'a self) -> impl Iterator<Item = &'a Node>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        HashMap::values(self)
+    }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Id, &'a Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        HashMap::iter(self)
+    }
+    fn iter_mut<'a>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (&'a Id, &'a mut Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        HashMap::iter_mut(self)
+    }
+}
+
+#[cfg(feature = "persistent")]
+impl<Id, Node> NodeStore<Id, Node> for im::HashMap<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone,
+{
+    fn len(&self) -> usize {
+        im::HashMap::len(self)
+    }
+    fn contains_key(&self, id: &Id) -> bool {
+        im::HashMap::contains_key(self, id)
+    }
+    fn get(&self, id: &Id) -> Option<&Node> {
+        im::HashMap::get(self, id)
+    }
+    fn get_mut(&mut self, id: &Id) -> Option<&mut Node> {
+        im::HashMap::get_mut(self, id)
+    }
+    fn insert(&mut self, id: Id, node: Node) -> Option<Node> {
+        im::HashMap::insert(self, id, node)
+    }
+    fn remove(&mut self, id: &Id) -> Option<Node> {
+        im::HashMap::remove(self, id)
+    }
+    fn retain(&mut self, f: impl FnMut(&Id, &Node) -> bool) {
+        im::HashMap::retain(self, f)
+    }
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a Id>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        im::HashMap::keys(self)
+    }
+    fn values<'a>(&'a self) -> impl Iterator<Item = &'a Node>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        im::HashMap::values(self)
+    }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Id, &'a Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        im::HashMap::iter(self)
+    }
+    fn iter_mut<'a>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (&'a Id, &'a mut Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        im::HashMap::iter_mut(self)
+    }
+}
+
+/// A generational index into a `Slab`; it goes stale once its node is
+/// removed, even if the slot is taken again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SlabKey {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone)]
+struct Slot<Id, Node> {
+    generation: u32,
+    entry: Option<(Id, Node)>,
+}
+
+/// Nodes kept side by side in a `Vec`, with the ids mapped to their slots;
+/// freed slots are reused under a new generation.
+///
+/// Visiting all the nodes walks the `Vec` rather than the buckets of a
+/// map, and a `SlabKey` reaches a node without hashing its id.
+#[derive(Clone)]
+pub struct Slab<Id, Node> {
+    slots: Vec<Slot<Id, Node>>,
+    /// the indices of the empty slots.
+    free: Vec<u32>,
+    keys: HashMap<Id, SlabKey>,
+}
+
+impl<Id, Node> Default for Slab<Id, Node> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl<Id, Node> Slab<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            keys: HashMap::with_capacity(capacity),
+        }
+    }
+    /// the key of the slot holding id, for lookups without hashing.
+    pub fn key(&self, id: &Id) -> Option<SlabKey> {
+        self.keys.get(id).copied()
+    }
+    /// None if the key went stale.
+    pub fn get_by_key(&self, key: SlabKey) -> Option<&Node> {
+        match self.slots.get(key.index as usize) {
+            Some(Slot {
+                generation,
+                entry: Some((_, node)),
+            }) if *generation == key.generation => Some(node),
+            _ => None,
+        }
+    }
+    pub fn get_by_key_mut(&mut self, key: SlabKey) -> Option<&mut Node> {
+        match self.slots.get_mut(key.index as usize) {
+            Some(Slot {
+                generation,
+                entry: Some((_, node)),
+            }) if *generation == key.generation => Some(node),
+            _ => None,
+        }
+    }
+    /// empties the slot and bumps its generation, staling its keys.
+    fn free_slot(&mut self, index: u32) -> Option<(Id, Node)> {
+        let slot = &mut self.slots[index as usize];
+        let entry = slot.entry.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+        Some(entry)
+    }
+}
+
+impl<Id, Node> NodeStore<Id, Node> for Slab<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone,
+{
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+    fn contains_key(&self, id: &Id) -> bool {
+        self.keys.contains_key(id)
+    }
+    fn get(&self, id: &Id) -> Option<&Node> {
+        self.get_by_key(self.key(id)?)
+    }
+    fn get_mut(&mut self, id: &Id) -> Option<&mut Node> {
+        self.get_by_key_mut(self.key(id)?)
+    }
+    fn insert(&mut self, id: Id, node: Node) -> Option<Node> {
+        if let Some(old) = self.get_mut(&id) {
+            return Some(std::mem::replace(old, node));
+        }
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: None,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        let generation = slot.generation;
+        slot.entry = Some((id.clone(), node));
+        self.keys.insert(id, SlabKey { index, generation });
+        None
+    }
+    fn remove(&mut self, id: &Id) -> Option<Node> {
+        let key = self.keys.remove(id)?;
+        self.free_slot(key.index).map(|(_, node)| node)
+    }
+    fn retain(&mut self, mut f: impl FnMut(&Id, &Node) -> bool) {
+        for index in 0..self.slots.len() {
+            let kept = match &self.slots[index].entry {
+                Some((id, node)) => f(id, node),
+                None => continue,
+            };
+            if !kept {
+                if let Some((id, _)) = self.free_slot(index as u32) {
+                    self.keys.remove(&id);
+                }
+            }
+        }
+    }
+    fn keys<'a>(&'a self) -> impl Iterator<Item = &'a Id>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        self.iter().map(|(id, _)| id)
+    }
+    fn values<'a>(&'a self) -> impl Iterator<Item = &'a Node>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        self.iter().map(|(_, node)| node)
+    }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Id, &'a Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.entry.as_ref().map(|(id, node)| (id, node)))
+    }
+    fn iter_mut<'a>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (&'a Id, &'a mut Node)>
+    where
+        Id: 'a,
+        Node: 'a,
+    {
+        self.slots.iter_mut().filter_map(|slot| {
+            slot.entry.as_mut().map(|(id, node)| (&*id, node))
+        })
+    }
+}
+
+/// Moves the nodes out of a `Slab`, in the order of slots.
+pub struct IntoIter<Id, Node> {
+    slots: std::vec::IntoIter<Slot<Id, Node>>,
+}
+
+impl<Id, Node> Iterator for IntoIter<Id, Node> {
+    type Item = (Id, Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.by_ref().find_map(|slot| slot.entry)
+    }
+}
+
+impl<Id, Node> IntoIterator for Slab<Id, Node> {
+    type Item = (Id, Node);
+    type IntoIter = IntoIter<Id, Node>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            slots: self.slots.into_iter(),
+        }
+    }
+}
+
+impl<Id, Node> FromIterator<(Id, Node)> for Slab<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone,
+{
+    fn from_iter<T: IntoIterator<Item = (Id, Node)>>(iter: T) -> Self {
+        let mut slab = Slab::new();
+        slab.extend(iter);
+        slab
+    }
+}
+
+impl<Id, Node> Extend<(Id, Node)> for Slab<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone,
+{
+    fn extend<T: IntoIterator<Item = (Id, Node)>>(&mut self, iter: T) {
+        for (id, node) in iter {
+            self.insert(id, node);
+        }
+    }
+}
+
+/// Equal if holding the same nodes under the same ids, wherever they are.
+impl<Id, Node> PartialEq for Slab<Id, Node>
+where
+    Id: Hash + Eq + Clone,
+    Node: Clone + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().all(|(id, node)| other.get(id) == Some(node))
+    }
+}
+
+impl<Id: Debug, Node: Debug> Debug for Slab<Id, Node> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.slots.iter().filter_map(|slot| {
+                slot.entry.as_ref().map(|(id, node)| (id, node))
+            }))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slab() {
+        let mut slab: Slab<u64, &str> = (0..4).map(|i| (i, "x")).collect();
+        let key = slab.key(&1).expect("key");
+        assert_eq!(slab.insert(1, "y"), Some("x"));
+        assert_eq!(slab.get_by_key(key), Some(&"y"));
+        assert_eq!(slab.remove(&1), Some("y"));
+        assert_eq!(slab.get_by_key(key), None);
+        // the slot is taken again, but the old key stays stale
+        slab.insert(9, "z");
+        assert_eq!(slab.key(&9).map(|k| k.index), Some(key.index));
+        assert_eq!(slab.get_by_key(key), None);
+        slab.retain(|id, _| id % 2 == 1);
+        let mut ids: Vec<u64> = slab.keys().cloned().collect();
+        ids.sort();
+        assert_eq!(ids, vec![3, 9]);
+        assert_eq!(slab.len(), 2);
+        let other: Slab<u64, &str> = [(9, "z"), (3, "x")].into_iter().collect();
+        assert_eq!(slab, other);
+    }
+}
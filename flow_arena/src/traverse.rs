@@ -0,0 +1,190 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::{FlowBase, Node};
+
+/// The order in which a traversal yields nodes; children are always visited in their stored order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraverseOrder {
+    /// a node comes before its children
+    PreOrder,
+    /// a node comes after its children
+    PostOrder,
+    /// level by level, from the shallowest
+    BreadthFirst,
+}
+
+/// Which children a traversal follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraverseMode {
+    /// only owned children; tree-ish
+    Owned,
+    /// all linked children, each node visited once; graph-ish
+    Linked,
+}
+
+/// A node met in a traversal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Visit<Id> {
+    pub id: Id,
+    /// distance from the root the traversal started from
+    pub depth: usize,
+    /// the owning / linking node it's reached from; None for roots
+    pub parent: Option<Id>,
+}
+
+/// A lazy traversal over a flow, created by `FlowBase::traverse`.
+///
+/// Roots not found in the flow are skipped.
+pub struct Traverse<'a, F: FlowBase + ?Sized> {
+    flow: &'a F,
+    order: TraverseOrder,
+    mode: TraverseMode,
+    /// a stack in depth-first orders and a queue in breadth-first order;
+    /// the flag marks whether the children are already scheduled.
+    pending: VecDeque<(Visit<F::Id>, bool)>,
+    visited: HashSet<F::Id>,
+}
+
+impl<'a, F: FlowBase + ?Sized> Traverse<'a, F> {
+    pub fn new(
+        flow: &'a F,
+        roots: Vec<F::Id>,
+        order: TraverseOrder,
+        mode: TraverseMode,
+    ) -> Self {
+        let mut visited = HashSet::new();
+        let roots =
+            roots
+                .into_iter()
+                .filter(|id| flow.contains_node(id))
+                .map(|id| {
+                    (
+                        Visit {
+                            id,
+                            depth: 0,
+                            parent: None,
+                        },
+                        false,
+                    )
+                });
+        let pending = match order {
+            TraverseOrder::BreadthFirst => roots
+                .filter(|(visit, _)| visited.insert(visit.id.clone()))
+                .collect(),
+            // the first root goes on top of the stack
+            TraverseOrder::PreOrder | TraverseOrder::PostOrder => {
+                roots.rev().collect()
+            }
+        };
+        Self {
+            flow,
+            order,
+            mode,
+            pending,
+            visited,
+        }
+    }
+
+    /// whether obj is followed from owner, one of its linkers.
+    fn follows(&self, obj: &F::Id, owner: &F::Id) -> bool {
+        match self.mode {
+            TraverseMode::Owned => {
+                self.flow.parent(obj).as_ref() == Some(owner)
+            }
+            TraverseMode::Linked => true,
+        }
+    }
+
+    fn visit_children(&self, visit: &Visit<F::Id>) -> Vec<Visit<F::Id>> {
+        let children = self
+            .flow
+            .node(&visit.id)
+            .map_or(&[][..], |node| node.children());
+        children
+            .iter()
+            .filter(|id| !self.visited.contains(*id))
+            .filter(|id| self.follows(id, &visit.id))
+            .map(|id| Visit {
+                id: id.clone(),
+                depth: visit.depth + 1,
+                parent: Some(visit.id.clone()),
+            })
+            .collect()
+    }
+}
+
+impl<'a, F: FlowBase + ?Sized> Iterator for Traverse<'a, F> {
+    type Item = Visit<F::Id>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use TraverseOrder::*;
+        match self.order {
+            PreOrder => loop {
+                let (visit, _) = self.pending.pop_back()?;
+                if !self.visited.insert(visit.id.clone()) {
+                    continue;
+                }
+                let children = self.visit_children(&visit);
+                self.pending
+                    .extend(children.into_iter().rev().map(|x| (x, false)));
+                return Some(visit);
+            },
+            PostOrder => loop {
+                let (visit, scheduled) = self.pending.pop_back()?;
+                if scheduled {
+                    return Some(visit);
+                }
+                if !self.visited.insert(visit.id.clone()) {
+                    continue;
+                }
+                let children = self.visit_children(&visit);
+                self.pending.push_back((visit, true));
+                self.pending
+                    .extend(children.into_iter().rev().map(|x| (x, false)));
+            },
+            BreadthFirst => {
+                let (visit, _) = self.pending.pop_front()?;
+                for child in self.visit_children(&visit) {
+                    if self.visited.insert(child.id.clone()) {
+                        self.pending.push_back((child, false));
+                    }
+                }
+                Some(visit)
+            }
+        }
+    }
+}
+
+/// A lazy iteration over the owners of a node, created by `FlowBase::ancestors`.
+///
+/// Stops early if the ownership loops back.
+pub struct Ancestors<'a, F: FlowBase + ?Sized> {
+    flow: &'a F,
+    current: Option<F::Id>,
+    visited: HashSet<F::Id>,
+}
+
+impl<'a, F: FlowBase + ?Sized> Ancestors<'a, F> {
+    pub fn new(flow: &'a F, obj: &F::Id) -> Self {
+        let current = flow.parent(obj);
+        let visited = Some(obj.clone()).into_iter().collect();
+        Self {
+            flow,
+            current,
+            visited,
+        }
+    }
+}
+
+impl<'a, F: FlowBase + ?Sized> Iterator for Ancestors<'a, F> {
+    type Item = F::Id;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.current.take()?;
+        if !self.visited.insert(id.clone()) {
+            return None;
+        }
+        self.current = self.flow.parent(&id);
+        Some(id)
+    }
+}
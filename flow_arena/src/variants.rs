@@ -1,16 +1,34 @@
-use super::{FlowArena, FlowNode, Node};
+#[cfg(feature = "serde_impl")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{FlowArena, FlowNode, Node, Slab};
 use std::fmt::{self, Debug};
 
 pub type NodePure<Id> = FlowNode<Id, ()>;
 
 pub type FlowPure<Id> = FlowArena<Id, FlowNode<Id, ()>>;
 
+/// A node without ownership; only links.
+///
+/// Serialized the same way as a `FlowNode` without parent, so the data can
+/// be read by either; parents are dropped on reading.
+#[derive(Clone, Default, PartialEq)]
 pub struct GraphNode<Id, Entity> {
     id: Id,
-    entity: Entity,
+    pub entity: Entity,
     children: Vec<Id>,
 }
 
+impl<Id, Entity> GraphNode<Id, Entity> {
+    pub fn from_id(id: Id, entity: Entity) -> Self {
+        GraphNode {
+            id,
+            entity,
+            children: Vec::new(),
+        }
+    }
+}
+
 impl<Id, Entity> Debug for GraphNode<Id, Entity>
 where
     Id: Debug + Clone,
@@ -39,8 +57,8 @@ where
     fn parent_set(&mut self, _: Id) {}
     fn parent_set_none(&mut self) {}
 
-    fn children(&self) -> Vec<Id> {
-        self.children.clone()
+    fn children(&self) -> &[Id] {
+        &self.children
     }
 
     fn children_ref_mut(&mut self) -> &mut Vec<Id> {
@@ -48,4 +66,216 @@ where
     }
 }
 
+#[cfg(feature = "serde_impl")]
+impl<Id, Entity> Serialize for GraphNode<Id, Entity>
+where
+    Id: Serialize + Clone,
+    Entity: Serialize,
+{
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut node = FlowNode::from_id(self.id.clone(), &self.entity);
+        *node.children_ref_mut() = self.children.clone();
+        node.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde_impl")]
+impl<'de, Id, Entity> Deserialize<'de> for GraphNode<Id, Entity>
+where
+    Id: Deserialize<'de> + Clone,
+    Entity: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let node = FlowNode::<Id, Entity>::deserialize(deserializer)?;
+        let children = node.children().to_vec();
+        Ok(GraphNode {
+            id: node.id().clone(),
+            entity: node.entity,
+            children,
+        })
+    }
+}
+
+/// A pure graph: a `FlowArena` whose nodes never own each other.
+///
+/// All flow operations work, with ownership taken away:
+/// 1. every node is an orphan; `devote` merely links and `decay` does nothing.
+/// 2. `erase` removes only the node itself, and cleans up all links to it.
+/// 3. `dock` must link all the nodes in the docked flow to the owner, for
+///    they're all orphans.
+/// 4. `undock` cuts all the nodes reachable from the node, iff none of them
+///    is linked from outside and none links back to the node;
+///    `undock_owned` cuts nothing but clears the links from the node.
 pub type GraphArena<Id, Entity> = FlowArena<Id, GraphNode<Id, Entity>>;
+
+/// A `FlowArena` keeping its nodes in a `Slab` instead of a map; walking
+/// the whole flow, e.g. to check or save it, goes through a `Vec`.
+pub type SlabArena<Id, Entity> =
+    FlowArena<Id, FlowNode<Id, Entity>, Slab<Id, FlowNode<Id, Entity>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Flow, FlowBase, FlowCheck, FlowDevote, FlowDock, FlowLink, FlowMap,
+        NodeStore, TraverseMode, TraverseOrder,
+    };
+    type Graph = GraphArena<u64, String>;
+
+    /// 0 -> {1 -> {3}, 2 -> {3 -> {0}}}
+    fn make_graph() -> Graph {
+        let mut graph = Graph::new();
+        for i in 0..4 {
+            graph
+                .grow(GraphNode::from_id(i, format!("node {}", i)))
+                .expect("grow error");
+        }
+        for (obj, owner) in [(1, 0), (2, 0), (3, 1), (3, 2), (0, 3)] {
+            graph.link_push(&obj, &owner).expect("link error");
+        }
+        graph
+    }
+
+    #[test]
+    fn ownership() {
+        let mut graph = make_graph();
+        let mut orphan = graph.orphan();
+        orphan.sort();
+        assert_eq!(orphan, vec![0, 1, 2, 3]);
+        // devote only links; decay does nothing
+        graph.devote(&3, &0, 0).expect("devote error");
+        assert_eq!(graph.parent(&3), None);
+        assert_eq!(graph.children(&0), vec![3, 1, 2]);
+        graph.decay(&3).expect("decay error");
+        assert_eq!(graph.children(&0), vec![3, 1, 2]);
+        let visits: Vec<u64> = graph
+            .traverse(&0, TraverseOrder::PreOrder, TraverseMode::Linked)
+            .map(|visit| visit.id)
+            .collect();
+        assert_eq!(visits, vec![0, 3, 1, 2]);
+        let visits: Vec<u64> = graph
+            .traverse(&0, TraverseOrder::PreOrder, TraverseMode::Owned)
+            .map(|visit| visit.id)
+            .collect();
+        assert_eq!(visits, vec![0]);
+    }
+
+    #[test]
+    fn erase() {
+        let mut graph = make_graph();
+        graph.erase(&3).expect("erase error");
+        assert_eq!(graph.node_map.len(), 3);
+        assert!(graph.children(&1).is_empty());
+        assert!(graph.children(&2).is_empty());
+        assert!(graph.linked_from(&0).is_empty());
+    }
+
+    #[test]
+    fn dock() {
+        let mut graph = make_graph();
+        // 3 links back to 0, and 0 back to 1
+        assert!(graph.undock(&0).is_err());
+        assert!(graph.undock(&1).is_err());
+        graph.detach(&0, &3).expect("detach error");
+        let graph_ = graph.clone();
+        let (sub, vec) = graph.undock(&0).expect("undock error");
+        assert_eq!(vec, vec![1, 2]);
+        assert_eq!(sub.node_map.len(), 3);
+        assert_eq!(graph.node_map.len(), 1);
+        // all nodes are orphans, so all of them get linked
+        assert!(graph.dock(&0, vec, sub.clone()).is_err());
+        graph.dock(&0, vec![1, 2, 3], sub).expect("dock error");
+        assert_eq!(graph.children(&0), vec![1, 2, 3]);
+        graph.detach(&3, &0).expect("detach error");
+        assert_eq!(graph, graph_);
+        // nothing owned, nothing cut
+        let (sub, vec) = graph.undock_owned(&0).expect("undock error");
+        assert!(sub.node_map.is_empty());
+        assert_eq!(vec, vec![1, 2]);
+        assert!(graph.children(&0).is_empty());
+        assert_eq!(graph.node_map.len(), 4);
+    }
+
+    #[test]
+    fn serde() {
+        let graph = make_graph();
+        let json = serde_json::to_string(&graph).expect("ser error");
+        let graph_: Graph = serde_json::from_str(&json).expect("de error");
+        assert_eq!(graph, graph_);
+        // the same format as a flow
+        let flow: FlowArena<u64, FlowNode<u64, String>> =
+            serde_json::from_str(&json).expect("de error");
+        assert_eq!(flow.children(&2), vec![3]);
+        assert_eq!(flow.parent(&2), None);
+        let mut flow = flow;
+        flow.decay(&3).ok();
+        flow.devote_push(&1, &0).ok();
+        let json = serde_json::to_string(&flow).expect("ser error");
+        let graph_: Graph = serde_json::from_str(&json).expect("de error");
+        assert_eq!(graph, graph_);
+    }
+
+    /// the same operations, whatever the store.
+    fn shape<F>(flow: &mut F)
+    where
+        F: Flow<Id = u64, Node = FlowNode<u64, String>>,
+    {
+        for i in 0..8 {
+            flow.grow(FlowNode::from_id(i, format!("node {}", i)))
+                .expect("grow error");
+        }
+        for (obj, owner) in [(1, 0), (2, 0), (3, 0), (4, 1), (5, 1), (6, 5)] {
+            flow.devote_push(&obj, &owner).expect("devote error");
+        }
+        flow.link_push(&5, &2).expect("link error");
+        // a cycle rolls back as a whole
+        let res = flow.transact(|flow| {
+            flow.decay(&0)?;
+            flow.devote(&0, &6, 0)
+        });
+        assert!(res.is_err());
+        flow.erase(&2).expect("erase error");
+        let (sub, vec) = flow.undock(&1).expect("undock error");
+        flow.dock(&3, vec, sub).expect("dock error");
+        flow.devote_loyal(&7, &3, 0).expect("devote error");
+        flow.erase(&4).expect("erase error");
+        // into the freed slots
+        for i in 8..10 {
+            flow.grow(FlowNode::from_id(i, String::new()))
+                .expect("grow error");
+            flow.link_push(&i, &5).expect("link error");
+        }
+    }
+
+    #[test]
+    fn slab() {
+        let mut flow: FlowArena<u64, FlowNode<u64, String>> = FlowArena::new();
+        let mut slab: SlabArena<u64, String> = SlabArena::new();
+        shape(&mut flow);
+        shape(&mut slab);
+        slab.check().expect("check error");
+        assert_eq!(slab.children(&3), vec![7, 5]);
+        assert_eq!(slab.children(&5), vec![6, 8, 9]);
+        assert_eq!(slab.linked_from(&5), vec![3]);
+        assert_eq!(slab.store().len(), 8);
+        let key = slab.store().key(&6).expect("key error");
+        assert_eq!(slab.store().get_by_key(key), slab.node(&6));
+        // both write the same, and read back either way
+        let json = serde_json::to_string(&slab).expect("ser error");
+        assert_eq!(json, serde_json::to_string(&flow).expect("ser error"));
+        let slab_: SlabArena<u64, String> =
+            serde_json::from_str(&json).expect("de error");
+        assert_eq!(slab, slab_);
+        // a stale key misses, even in a reused slot
+        slab.erase(&5).expect("erase error");
+        assert_eq!(slab.store().get_by_key(key), None);
+        slab.grow(FlowNode::from_id(10, String::new()))
+            .expect("grow error");
+        assert_eq!(slab.store().get_by_key(key), None);
+    }
+}
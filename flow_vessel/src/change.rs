@@ -0,0 +1,299 @@
+use flow_arena::Node;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+use super::{CubeId, EntityField, EntityId, EntityNode, Vessel};
+
+/// A single effect of a tube on the vessel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    EntityCreated {
+        id: EntityId,
+    },
+    /// the field is given when made by `Tube::EntityUpdate`, and None when
+    /// the entity is only known to differ, e.g. on undo.
+    EntityUpdated {
+        id: EntityId,
+        field: Option<EntityField>,
+    },
+    /// the owner changed; from None on creation or to None on becoming
+    /// an orphan.
+    ParentChanged {
+        id: EntityId,
+        from: Option<EntityId>,
+        to: Option<EntityId>,
+    },
+    /// the same children in a different order.
+    ChildrenReordered {
+        id: EntityId,
+    },
+    /// children added or removed, owned or linked.
+    ChildrenChanged {
+        id: EntityId,
+    },
+    /// all the entities erased at once, e.g. a whole subtree.
+    EntityErased {
+        ids: Vec<EntityId>,
+    },
+    CubeAdded {
+        id: CubeId,
+    },
+    CubeRemoved {
+        id: CubeId,
+    },
+}
+
+/// All the changes made by a tube, entities first and in order of id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSet {
+    pub changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter()
+    }
+    /// returns all entities to be redrawn, including the erased ones and
+    /// the owners left or joined.
+    pub fn affected(&self) -> BTreeSet<EntityId> {
+        let mut ids = BTreeSet::new();
+        for change in self.changes.iter() {
+            use Change::*;
+            match change {
+                EntityCreated { id }
+                | EntityUpdated { id, .. }
+                | ChildrenReordered { id }
+                | ChildrenChanged { id } => {
+                    ids.insert(*id);
+                }
+                ParentChanged { id, from, to } => {
+                    ids.insert(*id);
+                    ids.extend(from.iter().chain(to.iter()));
+                }
+                EntityErased { ids: erased } => ids.extend(erased.iter()),
+                CubeAdded { .. } | CubeRemoved { .. } => (),
+            }
+        }
+        ids
+    }
+    /// tells the changes between node states (before, after); field is
+    /// the one known to be updated, if any.
+    pub(crate) fn from_nodes<'a>(
+        nodes: impl Iterator<
+            Item = (
+                &'a EntityId,
+                &'a Option<EntityNode>,
+                &'a Option<EntityNode>,
+            ),
+        >,
+        field: Option<(EntityId, EntityField)>,
+    ) -> Self {
+        let mut nodes: Vec<_> = nodes.collect();
+        nodes.sort_by_key(|&(id, _, _)| *id);
+        let mut changes = Vec::new();
+        let mut erased = Vec::new();
+        for (&id, before, after) in nodes {
+            match (before, after) {
+                (None, Some(after)) => {
+                    changes.push(Change::EntityCreated { id });
+                    if let Some(to) = after.parent() {
+                        changes.push(Change::ParentChanged {
+                            id,
+                            from: None,
+                            to: Some(to),
+                        });
+                    }
+                }
+                (Some(_), None) => erased.push(id),
+                (Some(before), Some(after)) => {
+                    if before.entity != after.entity {
+                        let field = match &field {
+                            Some((obj, field)) if obj == &id => {
+                                Some(field.clone())
+                            }
+                            _ => None,
+                        };
+                        changes.push(Change::EntityUpdated { id, field });
+                    }
+                    if before.parent() != after.parent() {
+                        changes.push(Change::ParentChanged {
+                            id,
+                            from: before.parent(),
+                            to: after.parent(),
+                        });
+                    }
+                    let (from, to) = (before.children(), after.children());
+                    if from != to {
+                        let same: bool = from.len() == to.len()
+                            && from.iter().collect::<HashSet<_>>()
+                                == to.iter().collect::<HashSet<_>>();
+                        changes.push(if same {
+                            Change::ChildrenReordered { id }
+                        } else {
+                            Change::ChildrenChanged { id }
+                        });
+                    }
+                }
+                (None, None) => (),
+            }
+        }
+        if !erased.is_empty() {
+            changes.push(Change::EntityErased { ids: erased });
+        }
+        Self { changes }
+    }
+    /// appends the cubes added and removed between the cube sets.
+    pub(crate) fn cubes(
+        &mut self,
+        before: &BTreeSet<CubeId>,
+        after: &BTreeSet<CubeId>,
+    ) {
+        let added = after.difference(before);
+        let removed = before.difference(after);
+        self.changes.extend(
+            added
+                .map(|&id| Change::CubeAdded { id })
+                .chain(removed.map(|&id| Change::CubeRemoved { id })),
+        );
+    }
+}
+
+pub type Hook = Arc<dyn Fn(&ChangeSet) + Send + Sync>;
+
+/// The subscribers of a vessel, called after every tube that changed
+/// anything; shared among the clones of a vessel.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    next: usize,
+    hooks: BTreeMap<usize, Hook>,
+}
+
+impl Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("count", &self.hooks.len())
+            .finish()
+    }
+}
+
+/// change notification
+impl Vessel {
+    /// calls hook with the changes of every tube from now on; returns the
+    /// key to unsubscribe with.
+    pub fn subscribe(
+        &mut self,
+        hook: impl Fn(&ChangeSet) + Send + Sync + 'static,
+    ) -> usize {
+        let key = self.hooks.next;
+        self.hooks.next += 1;
+        self.hooks.hooks.insert(key, Arc::new(hook));
+        key
+    }
+    /// returns false if no hook is under the key.
+    pub fn unsubscribe(&mut self, key: usize) -> bool {
+        self.hooks.hooks.remove(&key).is_some()
+    }
+    /// calls the hooks in the order subscribed.
+    pub(crate) fn broadcast(&self, changes: &ChangeSet) {
+        if changes.is_empty() {
+            return;
+        }
+        for hook in self.hooks.hooks.values() {
+            hook(changes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cube, CubeMeta, CubeType, Router, Tube};
+    use std::sync::Mutex;
+
+    #[test]
+    fn notify() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let heard = Arc::new(Mutex::new(Vec::new()));
+        let key = {
+            let heard = heard.clone();
+            vessel.subscribe(move |changes| {
+                heard.lock().unwrap().push(changes.clone())
+            })
+        };
+
+        let (_, changes) = vessel.update_tube_changes(Tube::EntityAdd {
+            dude: None,
+            owner: root,
+            idx: 0,
+        });
+        let obj = vessel.entity_id_direct(&root)[0];
+        assert!(changes.changes.contains(&Change::EntityCreated { id: obj }));
+        assert!(changes.changes.contains(&Change::ParentChanged {
+            id: obj,
+            from: None,
+            to: Some(root)
+        }));
+        assert!(changes
+            .changes
+            .contains(&Change::ChildrenChanged { id: root }));
+        assert_eq!(changes.affected(), [root, obj].into_iter().collect());
+
+        let field = EntityField::Face("face".to_string());
+        let (_, changes) = vessel.update_tube_changes(Tube::EntityUpdate {
+            id: obj,
+            field: field.clone(),
+        });
+        assert_eq!(
+            changes.changes,
+            vec![Change::EntityUpdated {
+                id: obj,
+                field: Some(field)
+            }]
+        );
+        // undo refreshes the glass, which may replace fallback cubes
+        let (_, changes) = vessel.update_tube_changes(Tube::Undo);
+        assert_eq!(
+            changes.changes[0],
+            Change::EntityUpdated {
+                id: obj,
+                field: None
+            }
+        );
+        assert_eq!(changes.affected(), [obj].into_iter().collect());
+
+        let (_, changes) = vessel.update_tube_changes(Tube::OpenVM {
+            cube: Cube::new(CubeType::ClauseTree).with_obj(obj),
+            meta: CubeMeta {
+                router: Router::Workspace,
+                idx: 0,
+            },
+        });
+        let cube = match changes.changes.as_slice() {
+            [Change::CubeAdded { id }] => *id,
+            _ => panic!("{:?}", changes),
+        };
+        let (_, changes) =
+            vessel.update_tube_changes(Tube::EntityDelete { id: obj });
+        assert_eq!(
+            changes.changes[..2],
+            [
+                Change::ChildrenChanged { id: root },
+                Change::EntityErased { ids: vec![obj] },
+            ]
+        );
+        assert!(changes.changes.contains(&Change::CubeRemoved { id: cube }));
+        assert_eq!(heard.lock().unwrap().len(), 5);
+
+        assert!(vessel.unsubscribe(key));
+        vessel.update_tube(Tube::Undo);
+        assert_eq!(heard.lock().unwrap().len(), 5);
+        assert!(vessel.entity(&obj).is_some());
+    }
+}
@@ -1,13 +1,16 @@
+use flow_arena::Patch;
 use serde::{Deserialize, Serialize};
 
 use crate::{Filter, Identity};
 
 pub mod identity;
+pub mod relation;
 pub mod symbol;
 pub mod tag;
 pub mod timenote;
 
 use identity::*;
+use relation::*;
 use symbol::*;
 use tag::*;
 use timenote::*;
@@ -24,6 +27,9 @@ pub struct Entity {
     pub tags: TagSet,
     #[serde(default)]
     pub blocked: bool,
+    /// labels of the links to the children; plain links if absent.
+    #[serde(default, skip_serializing_if = "Relations::is_empty")]
+    pub relations: Relations,
     #[serde(skip)]
     pub symbol_toggle: bool,
 }
@@ -47,6 +53,7 @@ impl Entity {
             symbol: Symbol::default(),
             tags: TagSet::default(),
             blocked: false,
+            relations: Relations::default(),
             symbol_toggle: false,
         }
     }
@@ -56,6 +63,13 @@ impl Entity {
     pub fn duplicate_from(&mut self, dude: &Self) {
         self.symbol = dude.symbol.clone();
     }
+    /// a full copy of the entity, under a fresh id.
+    pub fn duplicate_rotate(&self, id_factory: &mut EntityIdFactory) -> Self {
+        Entity {
+            id: id_factory.rotate_id(),
+            ..self.clone()
+        }
+    }
 
     pub fn matched(&self, filter: &Filter) -> bool {
         match filter {
@@ -102,10 +116,20 @@ impl Entity {
             SymbolToggle => {
                 self.symbol_toggle = !self.symbol_toggle;
             }
+            SetBlocked(b) => self.blocked = b,
+            SetSymbolToggle(t) => self.symbol_toggle = t,
         }
     }
 }
 
+/// edits to an entity replicated by field, e.g. in a `flow_arena::Replica`;
+/// see `EntityField::absolute`.
+impl Patch<Entity> for EntityField {
+    fn patch(&self, entity: &mut Entity) {
+        entity.update_entity(self.clone())
+    }
+}
+
 impl Default for Entity {
     fn default() -> Self {
         Self::new_id(&EntityId::default())
@@ -115,13 +139,79 @@ impl Default for Entity {
 pub type Face = String;
 pub type Bubble = String;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EntityField {
     TimeNote(TimeNote),
     Face(Face),
     Bubble(Bubble),
     Symbol(Symbol),
     TagSet(TagSetField),
+    /// flips `blocked`.
     Blocked,
+    /// flips `symbol_toggle`.
     SymbolToggle,
+    SetBlocked(bool),
+    SetSymbolToggle(bool),
+}
+
+impl EntityField {
+    /// the field with the flips turned into the values they give entity,
+    /// e.g. before a replica op is built from it; replayed twice or
+    /// concurrently, it leaves the entity as once.
+    pub fn absolute(self, entity: &Entity) -> Self {
+        match self {
+            EntityField::Blocked => EntityField::SetBlocked(!entity.blocked),
+            EntityField::SymbolToggle => {
+                EntityField::SetSymbolToggle(!entity.symbol_toggle)
+            }
+            field => field,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_json, to_json};
+    use flow_arena::{FlowBase, Op, Replica};
+
+    #[test]
+    fn replicated_fields() {
+        let mut ours: Replica<EntityId, Entity, EntityField> = Replica::new(1);
+        let mut theirs: Replica<EntityId, Entity, EntityField> =
+            Replica::new(2);
+        let id = EntityIdFactory::default().incr_id();
+        let mut ops = vec![ours.grow(id, Entity::new_id(&id)).expect("grow")];
+        for field in [
+            EntityField::Face("face".into()),
+            EntityField::TagSet(TagSetField::AddTag("tag".into())),
+            EntityField::SetBlocked(true),
+        ] {
+            ops.push(ours.update(id, field).expect("update"));
+        }
+        // the ops travel as they are
+        for op in ops {
+            let json = to_json(&op);
+            let op_: Op<EntityId, Entity, EntityField> = from_json(&json);
+            assert_eq!(op_, op);
+            theirs.apply(op_);
+        }
+        // both unblock it at once, and it stays unblocked
+        let toggle = |replica: &Replica<EntityId, Entity, EntityField>| {
+            let entity = &replica.flow().node(&id).expect("node").entity;
+            EntityField::Blocked.absolute(entity)
+        };
+        let (field, field_) = (toggle(&ours), toggle(&theirs));
+        assert_eq!(field, EntityField::SetBlocked(false));
+        let op = ours.update(id, field).expect("update");
+        let op_ = theirs.update(id, field_).expect("update");
+        ours.apply(op_);
+        theirs.apply(op);
+        for replica in [&ours, &theirs] {
+            let entity = &replica.flow().node(&id).expect("node").entity;
+            assert!(!entity.blocked);
+            assert_eq!(entity.face, "face");
+        }
+        assert_eq!(ours.flow(), theirs.flow());
+    }
 }
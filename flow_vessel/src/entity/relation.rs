@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::identity::EntityId;
+
+/// The meaning of a link from an entity to one of its children; a child
+/// without one is a plain link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Relation {
+    Blocks,
+    RelatesTo,
+    DuplicateOf,
+    SeeAlso,
+}
+
+impl Relation {
+    pub fn type_str(&self) -> &'static str {
+        use Relation::*;
+        match self {
+            Blocks => "blocks",
+            RelatesTo => "relates to",
+            DuplicateOf => "duplicate of",
+            SeeAlso => "see also",
+        }
+    }
+    pub fn vec_all() -> Vec<Self> {
+        use Relation::*;
+        vec![Blocks, RelatesTo, DuplicateOf, SeeAlso]
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.type_str())
+    }
+}
+
+/// The relations of an entity to its children, by child id.
+///
+/// Kept apart from the children list itself; the vessel drops the entry
+/// of a child unlinked by a tube, and ignores any left behind otherwise.
+#[derive(Default, Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Relations {
+    data: Vec<(EntityId, Relation)>,
+}
+
+impl Relations {
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn get(&self, obj: &EntityId) -> Option<Relation> {
+        self.data
+            .iter()
+            .find(|(id, _)| id == obj)
+            .map(|&(_, relation)| relation)
+    }
+    /// labels obj, or makes it a plain link if None.
+    pub fn set(&mut self, obj: EntityId, relation: Option<Relation>) {
+        self.data.retain(|(id, _)| id != &obj);
+        if let Some(relation) = relation {
+            self.data.push((obj, relation));
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(EntityId, Relation)> {
+        self.data.iter()
+    }
+    /// drops the entries whose child fails the predicate.
+    pub fn retain(&mut self, mut f: impl FnMut(&EntityId) -> bool) {
+        self.data.retain(|(id, _)| f(id))
+    }
+    /// renames the children, e.g. after copying under fresh ids; the
+    /// ones left out of the map are dropped.
+    pub fn remap(&mut self, map: impl Fn(&EntityId) -> Option<EntityId>) {
+        self.data = self
+            .data
+            .iter()
+            .filter_map(|(id, relation)| Some((map(id)?, *relation)))
+            .collect();
+    }
+}
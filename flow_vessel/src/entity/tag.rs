@@ -67,7 +67,7 @@ impl TagSet {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TagSetField {
     AddTag(Tag),
     DelTag(Tag),
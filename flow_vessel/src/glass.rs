@@ -1,6 +1,6 @@
 use crate::{settings::WorkspaceMode, EntityFlow};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 pub mod cube;
 mod ser_de;
@@ -12,7 +12,8 @@ use cube::{
 };
 
 /// Describes the app router.
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Deserialize, Serialize)]
 pub enum Router {
     Birdsight,
     Workspace,
@@ -125,6 +126,12 @@ impl Glass {
     pub fn get_cube(&self, id: CubeId) -> Option<Cube> {
         self.cube_map.get(&id).cloned()
     }
+    pub fn get_cube_mut(&mut self, id: CubeId) -> Option<&mut Cube> {
+        self.cube_map.get_mut(&id)
+    }
+    pub(crate) fn cube_ids(&self) -> BTreeSet<CubeId> {
+        self.cube_map.keys().cloned().collect()
+    }
     pub fn locate_cube_meta(&self, id: CubeId) -> Vec<CubeMeta> {
         self.router_map
             .iter()
@@ -384,4 +391,11 @@ mod tests {
     fn glass_all() {
         println!("{:#?}", make_glass());
     }
+    #[test]
+    fn glass_serde() {
+        let (_, _, glass) = make_glass();
+        let json = crate::to_json(&glass);
+        let glass: Glass = crate::from_json(&json);
+        assert_eq!(crate::to_json(&glass), json);
+    }
 }
@@ -1,7 +1,7 @@
 use crate::{EntityFlow, EntityId, Router};
 use flow_arena::FlowBase;
 use serde::{Deserialize, Serialize};
-use std::time::SystemTime;
+use std::{collections::BTreeSet, time::SystemTime};
 
 pub mod filter;
 pub mod identity;
@@ -69,6 +69,9 @@ pub struct Cube {
     /// if more info is needed, profile is then used.
     pub profile: Option<Profile>,
     pub filters: Vec<Filter>,
+    /// the entities whose children are hidden in outlines.
+    #[serde(default)]
+    pub folded: BTreeSet<EntityId>,
 }
 
 /// new Cube
@@ -135,6 +138,7 @@ impl Cube {
         if !self.current.map_or(false, |cur| flow.contains_node(&cur)) {
             self.clear_current();
         }
+        self.folded.retain(|id| flow.contains_node(id));
         // obj = Some(<Not Exist>) -> clean
         // obj = None | Some(<Exist>) -> keep
         if self.is_valid_cube(flow) {
@@ -185,6 +189,24 @@ impl Cube {
     }
 }
 
+/// fold state
+impl Cube {
+    pub fn with_folded(mut self, obj: EntityId) -> Self {
+        self.folded.insert(obj);
+        self
+    }
+    pub fn is_folded(&self, obj: &EntityId) -> bool {
+        self.folded.contains(obj)
+    }
+    /// folds obj if unfolded, and vice versa; returns whether it's folded.
+    pub fn toggle_fold(&mut self, obj: EntityId) -> bool {
+        if !self.folded.remove(&obj) {
+            self.folded.insert(obj);
+        }
+        self.is_folded(&obj)
+    }
+}
+
 /// generated on site; isn't contained in a Vec<Cube>
 #[derive(Default, Debug, Clone, Copy)]
 pub struct CubeMeta {
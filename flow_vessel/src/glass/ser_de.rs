@@ -1,16 +1,22 @@
 use super::*;
 
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::BTreeMap;
+/// Both maps are written sorted by key, so that an unchanged glass always
+/// serializes to the same bytes.
 impl Serialize for Glass {
     fn serialize<S: Serializer>(
         &self,
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
-        let mut flow = serializer.serialize_struct("Flow", 2)?;
-        let seq: Vec<(&CubeId, &Cube)> = self.cube_map.iter().collect();
+        let mut flow = serializer.serialize_struct("Flow", 4)?;
+        let router_map: BTreeMap<&Router, &Vec<CubeId>> =
+            self.router_map.iter().collect();
+        let mut seq: Vec<(&CubeId, &Cube)> = self.cube_map.iter().collect();
+        seq.sort_by_key(|&(id, _)| id);
         flow.serialize_field("router", &self.router)?;
         flow.serialize_field("factory", &self.factory)?;
-        flow.serialize_field("router_map", &self.router_map)?;
+        flow.serialize_field("router_map", &router_map)?;
         flow.serialize_field("cube_map", &seq)?;
         flow.end()
     }
@@ -100,7 +106,7 @@ impl<'de> Deserialize<'de> for Glass {
                                     "router",
                                 ));
                             }
-                            router = map.next_value()?;
+                            router = Some(map.next_value()?);
                         }
                         Field::Factory => {
                             if factory.is_some() {
@@ -108,7 +114,7 @@ impl<'de> Deserialize<'de> for Glass {
                                     "factory",
                                 ));
                             }
-                            factory = map.next_value()?;
+                            factory = Some(map.next_value()?);
                         }
                         Field::RouterMap => {
                             if router_map.is_some() {
@@ -116,7 +122,7 @@ impl<'de> Deserialize<'de> for Glass {
                                     "router_map",
                                 ));
                             }
-                            router_map = map.next_value()?;
+                            router_map = Some(map.next_value()?);
                         }
                         Field::CubeMap => {
                             if cube_map.is_some() {
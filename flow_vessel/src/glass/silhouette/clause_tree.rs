@@ -1,5 +1,7 @@
-use flow_arena::{FlowBase, FlowDock, FlowMap, Node};
+use flow_arena::{FlowBase, Node};
+use std::collections::BTreeSet;
 
+use super::outline::Outline;
 use crate::{Cube, CubeId, CubeMeta, EntityFlow, EntityId, EntityNode, Vessel};
 
 #[derive(Clone)]
@@ -7,6 +9,8 @@ pub struct ClauseTreeCore {
     pub cube_id: CubeId,
     pub head: EntityId,
     pub current: Option<EntityId>,
+    pub folded: BTreeSet<EntityId>,
+    /// a snapshot of the whole flow; structurally shared, so it's cheap.
     pub flow: EntityFlow,
 }
 
@@ -24,17 +28,23 @@ impl ClauseTreeCore {
     ) -> Option<Self> {
         let head = cube.obj?;
         let current = cube.current;
-        let (mut flow, vec) = vessel.flow.snap_owned(&head).ok()?;
-        let mut node = vessel.node(&head).cloned()?;
-        *node.children_ref_mut() = vec;
-        flow.grow(node).ok()?;
+        let folded = cube.folded;
+        if !vessel.flow.contains_node(&head) {
+            return None;
+        }
+        let flow = vessel.flow.clone();
         Some(Self {
             cube_id,
             head,
             current,
+            folded,
             flow,
         })
     }
+    /// the visible rows under head, in display order.
+    pub fn outline(&self) -> Outline {
+        Outline::new(&self.flow, self.head, &self.folded, None)
+    }
     pub fn head(&self) -> &EntityNode {
         self.flow.node(&self.head).expect("head exists")
     }
@@ -60,11 +70,14 @@ impl ClauseTreeCore {
         Args: Clone,
         FnArgs: Clone + Fn(Args) -> Args,
     {
-        self.node_view_impl(self.head, f, combinator, args, fn_args)
+        let mut path = vec![self.head];
+        self.node_view_impl(&mut path, f, combinator, args, fn_args)
     }
+    /// the views under the last of path; a child already on the path is
+    /// a link back up, and left out like `Outline` does.
     fn node_view_impl<F, View, Combinator, Args, FnArgs>(
         &self,
-        id: EntityId,
+        path: &mut Vec<EntityId>,
         f: F,
         combinator: Combinator,
         args: Args,
@@ -77,22 +90,24 @@ impl ClauseTreeCore {
         FnArgs: Clone + Fn(Args) -> Args,
     {
         let args = f_args(args);
-        self.node(&id)
-            .children()
-            .iter()
-            .map(|id| {
-                combinator(
-                    f(self.node(id), args.clone()),
-                    self.node_view_impl(
-                        id.clone(),
-                        f.clone(),
-                        combinator.clone(),
-                        args.clone(),
-                        f_args.clone(),
-                    ),
-                )
-            })
-            .collect()
+        let owner = *path.last().expect("head in path");
+        let mut views = Vec::new();
+        for &id in self.node(&owner).children() {
+            if path.contains(&id) {
+                continue;
+            }
+            path.push(id);
+            let children = self.node_view_impl(
+                path,
+                f.clone(),
+                combinator.clone(),
+                args.clone(),
+                f_args.clone(),
+            );
+            path.pop();
+            views.push(combinator(f(self.node(&id), args.clone()), children));
+        }
+        views
     }
 }
 
@@ -140,3 +155,34 @@ fn node_view(
     }
 }
  */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CubeType;
+    use flow_arena::FlowLink;
+
+    #[test]
+    fn link_back() {
+        let mut vessel = Vessel::new();
+        let head = vessel.entity_grow().expect("grow");
+        let obj = vessel.entity_grow_devote(head, 0).expect("grow");
+        let leaf = vessel.entity_grow_devote(obj, 0).expect("grow");
+        // leaf -> head, back up to the head of the cube
+        vessel.flow.link_push(&head, &leaf).expect("link");
+        let cube = Cube::new(CubeType::ClauseTree).with_obj(head);
+        let core = ClauseTreeCore::from_cube(&vessel, CubeId::default(), cube)
+            .expect("core");
+        let views = core.node_view(
+            |node, depth: usize| vec![(*node.id(), depth)],
+            |mut view, children: Vec<Vec<(EntityId, usize)>>| {
+                view.extend(children.into_iter().flatten());
+                view
+            },
+            0,
+            |depth| depth + 1,
+        );
+        let views: Vec<_> = views.into_iter().flatten().collect();
+        assert_eq!(views, vec![(obj, 1), (leaf, 2)]);
+    }
+}
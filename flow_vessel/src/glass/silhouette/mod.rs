@@ -1,2 +1,3 @@
 pub mod clause_tree;
 pub mod node_view;
+pub mod outline;
@@ -9,6 +9,8 @@ pub struct NodeViewCore {
     pub cube_id: CubeId,
     pub obj: Option<EntityId>,
     pub children: Vec<EntityId>,
+    /// entities linking to obj, its owner included
+    pub linked_from: Vec<EntityId>,
     pub current: Option<EntityId>,
     pub entity_map: HashMap<EntityId, (Own, Entity)>,
 }
@@ -35,6 +37,7 @@ impl NodeViewCore {
         let mut entity_map = HashMap::new();
         let obj = cube.obj;
         let mut children: Vec<EntityId>;
+        let mut linked_from = Vec::new();
         if let Some(obj) = obj {
             entity_map.insert(
                 obj,
@@ -52,6 +55,15 @@ impl NodeViewCore {
                     (own, vessel.flow.node(c)?.entity.clone()),
                 );
             }
+            linked_from = vessel.entity_linked_from(&obj);
+            for l in linked_from.iter() {
+                if !entity_map.contains_key(l) {
+                    entity_map.insert(
+                        *l,
+                        (Own::No, vessel.flow.node(l)?.entity.clone()),
+                    );
+                }
+            }
         } else {
             children = vessel.flow.orphan();
             children.sort_by_key(|i| i.clone());
@@ -67,6 +79,7 @@ impl NodeViewCore {
             cube_id,
             obj,
             children,
+            linked_from,
             current: None,
             entity_map,
         })
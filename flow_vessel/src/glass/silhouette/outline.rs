@@ -0,0 +1,218 @@
+use flow_arena::{FlowBase, Node};
+use std::collections::BTreeSet;
+
+use crate::{Cube, EntityFlow, EntityId, Vessel};
+
+/// A visible line of an outline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutlineRow {
+    pub id: EntityId,
+    /// 0 for the children of head.
+    pub depth: usize,
+    /// position among the children of the row it's under.
+    pub index: usize,
+    /// owned by the row it's under, rather than purely linked.
+    pub is_owned: bool,
+    pub has_children: bool,
+    /// children hidden by the fold state.
+    pub is_folded: bool,
+}
+
+/// The nodes under head flattened in display order, i.e. pre-order;
+/// the children of folded nodes are skipped.
+///
+/// A node linked from several places shows up in each of them, so the
+/// cursor is a row rather than an id; `position` finds the first row of
+/// an id. A link back to any row above on the same branch isn't followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outline {
+    pub head: EntityId,
+    pub rows: Vec<OutlineRow>,
+}
+
+impl Outline {
+    /// projects the flow under head; no rows deeper than max_depth if given.
+    pub fn new(
+        flow: &EntityFlow,
+        head: EntityId,
+        folded: &BTreeSet<EntityId>,
+        max_depth: Option<usize>,
+    ) -> Self {
+        let mut outline = Self {
+            head,
+            rows: Vec::new(),
+        };
+        let mut path = vec![head];
+        outline.project(flow, folded, max_depth, &mut path);
+        outline
+    }
+    /// projects the cube's obj with the cube's fold state.
+    pub fn from_cube(vessel: &Vessel, cube: &Cube) -> Option<Self> {
+        let head = cube.obj?;
+        if !vessel.flow.contains_node(&head) {
+            return None;
+        }
+        Some(Self::new(&vessel.flow, head, &cube.folded, None))
+    }
+    fn project(
+        &mut self,
+        flow: &EntityFlow,
+        folded: &BTreeSet<EntityId>,
+        max_depth: Option<usize>,
+        path: &mut Vec<EntityId>,
+    ) {
+        let owner = *path.last().expect("head in path");
+        let depth = path.len() - 1;
+        if matches!(max_depth, Some(max) if depth > max) {
+            return;
+        }
+        for (index, id) in flow.children(&owner).into_iter().enumerate() {
+            if path.contains(&id) {
+                continue;
+            }
+            let node = match flow.node(&id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let has_children = !node.children().is_empty();
+            let is_folded = has_children && folded.contains(&id);
+            self.rows.push(OutlineRow {
+                id,
+                depth,
+                index,
+                is_owned: node.parent() == Some(owner),
+                has_children,
+                is_folded,
+            });
+            if !is_folded {
+                path.push(id);
+                self.project(flow, folded, max_depth, path);
+                path.pop();
+            }
+        }
+    }
+}
+
+/// cursor
+impl Outline {
+    /// the first row of id.
+    pub fn position(&self, id: &EntityId) -> Option<usize> {
+        self.rows.iter().position(|row| &row.id == id)
+    }
+    /// the row above current, whatever its depth; the last row if current
+    /// isn't shown, and None at the top.
+    pub fn up(&self, current: Option<usize>) -> Option<usize> {
+        match current.filter(|&row| row < self.rows.len()) {
+            Some(row) => row.checked_sub(1),
+            None => self.rows.len().checked_sub(1),
+        }
+    }
+    /// the row below current, whatever its depth; the first row if current
+    /// isn't shown, and None at the bottom.
+    pub fn down(&self, current: Option<usize>) -> Option<usize> {
+        let row = match current.filter(|&row| row < self.rows.len()) {
+            Some(row) => row + 1,
+            None => 0,
+        };
+        (row < self.rows.len()).then_some(row)
+    }
+    /// the row current is under; None at depth 0.
+    pub fn owner(&self, current: usize) -> Option<usize> {
+        let depth = self.rows.get(current)?.depth.checked_sub(1)?;
+        self.rows[..current]
+            .iter()
+            .rposition(|row| row.depth == depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flow_arena::{FlowDevote, FlowLink};
+
+    /// 0 -> {1 -> {2 -> {3}}, 4 -> {(1)}}
+    fn make_vessel() -> (Vec<EntityId>, Vessel) {
+        let mut vessel = Vessel::new();
+        let id: Vec<EntityId> =
+            (0..5).filter_map(|_| vessel.entity_grow().ok()).collect();
+        for (obj, owner) in [(1, 0), (2, 1), (3, 2), (4, 0)] {
+            vessel
+                .flow
+                .devote_push(&id[obj], &id[owner])
+                .expect("devote");
+        }
+        vessel.flow.link_push(&id[1], &id[4]).expect("link");
+        (id, vessel)
+    }
+
+    #[test]
+    fn outline() {
+        let (id, mut vessel) = make_vessel();
+        let outline = Outline::new(&vessel.flow, id[0], &BTreeSet::new(), None);
+        let rows: Vec<(EntityId, usize, usize, bool)> = outline
+            .rows
+            .iter()
+            .map(|row| (row.id, row.depth, row.index, row.is_owned))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (id[1], 0, 0, true),
+                (id[2], 1, 0, true),
+                (id[3], 2, 0, true),
+                (id[4], 0, 1, true),
+                (id[1], 1, 0, false),
+                (id[2], 2, 0, true),
+                (id[3], 3, 0, true),
+            ]
+        );
+        let outline =
+            Outline::new(&vessel.flow, id[0], &BTreeSet::new(), Some(1));
+        assert_eq!(outline.rows.len(), 4);
+        // a link back to the branch isn't followed
+        vessel.flow.link_push(&id[0], &id[3]).expect("link");
+        vessel.flow.link_push(&id[2], &id[3]).expect("link");
+        let outline = Outline::new(&vessel.flow, id[0], &BTreeSet::new(), None);
+        assert_eq!(outline.rows.len(), 7);
+
+        let cube = Cube::new(crate::CubeType::ClauseTree)
+            .with_obj(id[0])
+            .with_folded(id[2])
+            .with_folded(id[4]);
+        let outline = Outline::from_cube(&vessel, &cube).expect("outline");
+        let rows: Vec<EntityId> =
+            outline.rows.iter().map(|row| row.id).collect();
+        assert_eq!(rows, vec![id[1], id[2], id[4]]);
+        assert!(outline.rows[1].is_folded && outline.rows[1].has_children);
+    }
+
+    #[test]
+    fn cursor() {
+        let (id, vessel) = make_vessel();
+        let outline = Outline::new(&vessel.flow, id[0], &BTreeSet::new(), None);
+        assert_eq!(outline.down(None), Some(0));
+        assert_eq!(outline.up(None), Some(6));
+        assert_eq!(outline.up(Some(0)), None);
+        assert_eq!(outline.down(Some(6)), None);
+        assert_eq!(outline.position(&id[1]), Some(0));
+        // rows 1, 2, 3, 4, 1', 2', 3'
+        assert_eq!(outline.owner(2), Some(1));
+        assert_eq!(outline.owner(3), None);
+        assert_eq!(outline.owner(4), Some(3));
+        assert_eq!(outline.owner(6), Some(5));
+
+        let walk = |step: &dyn Fn(Option<usize>) -> Option<usize>| {
+            let mut rows = Vec::new();
+            let mut current = None;
+            while let Some(row) = step(current) {
+                rows.push(outline.rows[row].id);
+                current = Some(row);
+            }
+            rows
+        };
+        let mut down = vec![id[1], id[2], id[3], id[4], id[1], id[2], id[3]];
+        assert_eq!(walk(&|row| outline.down(row)), down);
+        down.reverse();
+        assert_eq!(walk(&|row| outline.up(row)), down);
+    }
+}
@@ -0,0 +1,425 @@
+use flow_arena::{FlowBase, FlowError};
+use im::Vector;
+use std::collections::HashMap;
+
+use super::{
+    ChangeSet, Echo, EntityId, EntityNode, Glass, Settings, Tube, Vessel,
+};
+
+/// An invertible change on the vessel, made by a single `Tube`.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    /// node states (before, after); None if not existing.
+    ///
+    /// An erased subtree leaves all its nodes here.
+    nodes: HashMap<EntityId, (Option<EntityNode>, Option<EntityNode>)>,
+    /// (before, after); None if untouched. An undo or redo dropping cubes
+    /// keeps them here, so that stepping back brings them back.
+    glass: Option<(Glass, Glass)>,
+    /// (before, after); None if untouched.
+    settings: Option<(Settings, Settings)>,
+}
+
+impl Revision {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.glass.is_none() && self.settings.is_none()
+    }
+    /// returns all entities altered.
+    pub fn entities(&self) -> Vec<EntityId> {
+        self.nodes.keys().cloned().collect()
+    }
+    /// the changes made by reverting (backward) or reapplying it.
+    fn changes(&self, backward: bool) -> ChangeSet {
+        ChangeSet::from_nodes(
+            self.nodes.iter().map(|(id, (before, after))| {
+                if backward {
+                    (id, after, before)
+                } else {
+                    (id, before, after)
+                }
+            }),
+            None,
+        )
+    }
+}
+
+/// Bounded undo / redo stacks of `Revision`; structurally shared,
+/// so cloning a vessel doesn't copy the history.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: Vector<Revision>,
+    redo: Vector<Revision>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_capacity(128)
+    }
+}
+
+impl History {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            undo: Vector::new(),
+            redo: Vector::new(),
+            capacity,
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// drops the oldest revisions if more than capacity.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.undo.len() > capacity {
+            self.undo.pop_front();
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+    /// pushes a new revision and forgets all redos.
+    fn push(&mut self, revision: Revision) {
+        if revision.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(revision);
+        self.set_capacity(self.capacity)
+    }
+}
+
+impl Tube {
+    /// judge whether the tube may drop cubes from the glass; navigation,
+    /// e.g. switching routers or folding, is never recorded
+    fn alters_glass(&self) -> bool {
+        matches!(self, Tube::EntityDelete { .. })
+    }
+    fn alters_settings(&self) -> bool {
+        matches!(self, Tube::SettingUpdate { .. })
+    }
+}
+
+/// history operation
+impl Vessel {
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+    /// applies the tube and records it as a revision; returns the changes
+    /// made, yet to be broadcast.
+    pub(crate) fn update_tube_recorded(
+        &mut self,
+        tube: Tube,
+        update: impl FnOnce(&mut Self, Tube) -> Echo,
+    ) -> (Echo, ChangeSet) {
+        let glass = tube.alters_glass().then(|| self.glass.clone());
+        let settings = tube.alters_settings().then(|| self.settings.clone());
+        let field = match &tube {
+            Tube::EntityUpdate { id, field } => Some((*id, field.clone())),
+            _ => None,
+        };
+        let cubes = self.glass.cube_ids();
+        self.flow.record_begin();
+        let echo = update(self, tube);
+        let record = self.flow.record_end();
+        self.relations_tidy(record.keys());
+        let nodes: HashMap<_, _> = record
+            .into_iter()
+            .filter_map(|(id, before)| {
+                let after = self.flow.node(&id).cloned();
+                (before != after).then_some((id, (before, after)))
+            })
+            .collect();
+        let mut changes = ChangeSet::from_nodes(
+            nodes
+                .iter()
+                .map(|(id, (before, after))| (id, before, after)),
+            field,
+        );
+        changes.cubes(&cubes, &self.glass.cube_ids());
+        // a failed tube leaves glass and settings as they were, and the
+        // glass is only worth keeping along with the nodes deleted
+        let (glass, settings) = match echo {
+            Echo::FlowError(_) => (None, None),
+            _ if nodes.is_empty() => (None, settings),
+            _ => (glass, settings),
+        };
+        let revision = Revision {
+            nodes,
+            glass: glass.map(|glass| (glass, self.glass.clone())),
+            settings: settings
+                .map(|settings| (settings, self.settings.clone())),
+        };
+        self.history.push(revision);
+        (echo, changes)
+    }
+    /// reverts the latest revision; returns the entities altered.
+    ///
+    /// Does nothing if there's nothing to undo. On failure the revision
+    /// stays in history and nothing changes.
+    pub fn undo(&mut self) -> Result<Vec<EntityId>, FlowError<EntityId>> {
+        let (ids, changes) = self.step(true)?;
+        self.broadcast(&changes);
+        Ok(ids)
+    }
+    /// reapplies the latest undone revision; returns the entities altered.
+    ///
+    /// Does nothing if there's nothing to redo. On failure the revision
+    /// stays in history and nothing changes.
+    pub fn redo(&mut self) -> Result<Vec<EntityId>, FlowError<EntityId>> {
+        let (ids, changes) = self.step(false)?;
+        self.broadcast(&changes);
+        Ok(ids)
+    }
+    /// undoes (backward) or redoes; returns the entities altered and the
+    /// changes made, yet to be broadcast.
+    pub(crate) fn step(
+        &mut self,
+        backward: bool,
+    ) -> Result<(Vec<EntityId>, ChangeSet), FlowError<EntityId>> {
+        let revision = match backward {
+            true => self.history.undo.pop_back(),
+            false => self.history.redo.pop_back(),
+        };
+        let mut revision = match revision {
+            Some(revision) => revision,
+            None => return Ok((Vec::new(), ChangeSet::default())),
+        };
+        let cubes = self.glass.cube_ids();
+        let glass = self.glass.clone();
+        let result = self.revise(&revision, backward);
+        if result.is_ok()
+            && revision.glass.is_none()
+            && self.glass.cube_ids() != cubes
+        {
+            // the refresh dropped cubes; the step back restores them
+            let refreshed = self.glass.clone();
+            revision.glass = Some(match backward {
+                true => (refreshed, glass),
+                false => (glass, refreshed),
+            });
+        }
+        // back to where it was on failure, or onto the other stack
+        let stack = match backward == result.is_ok() {
+            true => &mut self.history.redo,
+            false => &mut self.history.undo,
+        };
+        let ids = revision.entities();
+        let mut changes = revision.changes(backward);
+        stack.push_back(revision);
+        result?;
+        changes.cubes(&cubes, &self.glass.cube_ids());
+        Ok((ids, changes))
+    }
+    /// puts back the states before (backward) or after the revision
+    fn revise(
+        &mut self,
+        revision: &Revision,
+        backward: bool,
+    ) -> Result<(), FlowError<EntityId>> {
+        fn pick<T>((before, after): &(T, T), backward: bool) -> &T {
+            if backward {
+                before
+            } else {
+                after
+            }
+        }
+        let nodes = revision
+            .nodes
+            .iter()
+            .map(|(id, pair)| (*id, pick(pair, backward).clone()))
+            .collect();
+        self.flow.restore(nodes)?;
+        if let Some(pair) = revision.settings.as_ref() {
+            self.settings = pick(pair, backward).clone();
+        }
+        match revision.glass.as_ref() {
+            Some(pair) => self.glass = pick(pair, backward).clone(),
+            None => self.glass_refresh(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cube, CubeMeta, CubeType, EntityField, Router};
+
+    fn add(vessel: &mut Vessel, owner: EntityId) -> EntityId {
+        let echo = vessel.update_tube(Tube::EntityAdd {
+            dude: None,
+            owner,
+            idx: 0,
+        });
+        assert!(!matches!(echo, Echo::FlowError(_)));
+        vessel.entity_id_direct(&owner)[0]
+    }
+
+    #[test]
+    fn undo_remove() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let obj = add(&mut vessel, root);
+        let leaf = add(&mut vessel, obj);
+        vessel.update_tube(Tube::OpenVM {
+            cube: Cube {
+                obj: Some(obj),
+                ..Cube::new(CubeType::ClauseTree)
+            },
+            meta: CubeMeta {
+                router: Router::Workspace,
+                idx: 0,
+            },
+        });
+        let cubes = vessel.glass.show_cubes(Router::Workspace).len();
+        let flow = vessel.flow.clone();
+
+        vessel.update_tube(Tube::EntityDelete { id: obj });
+        assert!(vessel.node(&leaf).is_none());
+        let has_cube = |vessel: &Vessel| {
+            vessel
+                .glass
+                .show_cubes(Router::Workspace)
+                .iter()
+                .any(|(_, _, cube)| cube.obj == Some(obj))
+        };
+        assert!(!has_cube(&vessel));
+
+        let ids = vessel.undo().expect("undo");
+        assert!(ids.contains(&obj) && ids.contains(&leaf));
+        assert_eq!(vessel.flow, flow);
+        assert_eq!(vessel.glass.show_cubes(Router::Workspace).len(), cubes);
+        assert!(has_cube(&vessel));
+
+        vessel.redo().expect("redo");
+        assert!(vessel.node(&obj).is_none());
+        assert!(!vessel.history().can_redo());
+        while vessel.history().can_undo() {
+            vessel.undo().expect("undo");
+        }
+        assert_eq!(vessel.orphan(), vec![root]);
+        assert!(vessel.entity_id_direct(&root).is_empty());
+    }
+
+    #[test]
+    fn redo_cubes() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let obj = add(&mut vessel, root);
+        vessel.update_tube(Tube::OpenVM {
+            cube: Cube {
+                obj: Some(obj),
+                ..Cube::new(CubeType::ClauseTree)
+            },
+            meta: CubeMeta {
+                router: Router::Workspace,
+                idx: 0,
+            },
+        });
+        let has_cube = |vessel: &Vessel| {
+            vessel
+                .glass
+                .show_cubes(Router::Workspace)
+                .iter()
+                .any(|(_, _, cube)| cube.obj == Some(obj))
+        };
+        assert!(has_cube(&vessel));
+
+        // undoing the add drops the cube; redoing brings it back
+        vessel.undo().expect("undo");
+        assert!(vessel.node(&obj).is_none());
+        assert!(!has_cube(&vessel));
+        vessel.redo().expect("redo");
+        assert!(vessel.node(&obj).is_some());
+        assert!(has_cube(&vessel));
+        vessel.undo().expect("undo");
+        assert!(!has_cube(&vessel));
+        vessel.redo().expect("redo");
+        assert!(has_cube(&vessel));
+    }
+
+    #[test]
+    fn undo_update() {
+        let mut vessel = Vessel::new();
+        let obj = vessel.entity_grow().expect("grow");
+        for face in ["a", "b", "c"] {
+            vessel.update_tube(Tube::EntityUpdate {
+                id: obj,
+                field: EntityField::Face(face.to_string()),
+            });
+        }
+        vessel.undo().expect("undo");
+        assert_eq!(vessel.entity(&obj).unwrap().face, "b");
+        // a new change forgets the redos
+        vessel.update_tube(Tube::EntityUpdate {
+            id: obj,
+            field: EntityField::Face("d".to_string()),
+        });
+        assert!(!vessel.history().can_redo());
+        vessel.update_tube(Tube::Undo);
+        vessel.update_tube(Tube::Undo);
+        assert_eq!(vessel.entity(&obj).unwrap().face, "a");
+        // failed tubes record nothing
+        let ghost = vessel.entity_grow().expect("grow");
+        vessel.entity_erase(ghost).expect("erase");
+        vessel.history_mut().clear();
+        vessel.update_tube(Tube::EntityDelete { id: ghost });
+        assert!(!vessel.history().can_undo());
+    }
+
+    #[test]
+    fn navigation() {
+        let mut vessel = Vessel::new();
+        let obj = vessel.entity_grow().expect("grow");
+        let cube = vessel.glass.add_cube(Cube::new(CubeType::ClauseTree));
+        for face in ["a", "b"] {
+            vessel.update_tube(Tube::EntityUpdate {
+                id: obj,
+                field: EntityField::Face(face.to_string()),
+            });
+        }
+        vessel.undo().expect("undo");
+        // navigation is neither recorded nor forgets the redos
+        vessel.update_tube(Tube::SwitchRouter {
+            router: Router::Settings,
+        });
+        vessel.update_tube(Tube::FoldToggle { cube_id: cube, obj });
+        assert!(vessel.history().can_redo());
+        vessel.undo().expect("undo");
+        assert_eq!(vessel.entity(&obj).unwrap().face, "");
+        assert_eq!(vessel.glass.router, Router::Settings);
+        assert!(!vessel.history().can_undo());
+        vessel.redo().expect("redo");
+        vessel.redo().expect("redo");
+        assert_eq!(vessel.entity(&obj).unwrap().face, "b");
+    }
+
+    #[test]
+    fn bounded() {
+        let mut vessel = Vessel::new();
+        vessel.history_mut().set_capacity(4);
+        let obj = vessel.entity_grow().expect("grow");
+        for _ in 0..8 {
+            add(&mut vessel, obj);
+        }
+        let mut count = 0;
+        while vessel.history().can_undo() {
+            vessel.undo().expect("undo");
+            count += 1;
+        }
+        assert_eq!(count, 4);
+        assert_eq!(vessel.entity_id_direct(&obj).len(), 4);
+    }
+}
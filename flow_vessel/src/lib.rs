@@ -1,14 +1,24 @@
+mod change;
 mod entity;
 mod glass;
+mod history;
 mod identity;
+mod merge;
+mod paged;
 mod settings;
+mod shared;
+mod stats;
 mod tube;
 mod util;
 mod vessel;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::util::pager::FilePager;
 pub use self::{
+    change::{Change, ChangeSet, Hook, Hooks},
     entity::{
         identity::{EntityId, EntityIdFactory},
+        relation::{Relation, Relations},
         symbol::{AlphaBet, Babel, Lint, Process, Symbol, BABEL},
         tag::{Tag, TagSet, TagSetField},
         timenote::TimeNote,
@@ -23,11 +33,21 @@ pub use self::{
         silhouette::*,
         {Glass, Router},
     },
+    history::{History, Revision},
     identity::{IdFactory, Identity, TimeUnique},
-    settings::{Bridge, Settings, ViewMode},
+    settings::{Bridge, Format, Settings, ViewMode},
+    shared::SharedVessel,
+    stats::VesselStats,
     tube::{Echo, Tube},
     util::{
+        binary::{
+            from_binary, is_binary, read_binary, to_binary, write_binary,
+            BinaryError, BINARY_MAGIC, BINARY_VERSION,
+        },
+        diagram::{Diagram, DiagramOptions},
         json::{export_json, from_json, to_json},
+        saveload::{LoadError, SaveError},
+        stream::{LoadProgress, LoadScope},
         time::{display, now, TimeRep},
     },
     vessel::{EntityFlow, EntityNode, Vessel},
@@ -0,0 +1,80 @@
+use flow_arena::{Conflict, FlowArena, FlowDiff};
+
+use super::{EntityId, Vessel};
+
+/// whole notebook comparison
+impl Vessel {
+    /// compares the vessel with a newer one, e.g. loaded from another file.
+    pub fn diff(&self, other: &Vessel) -> FlowDiff<EntityId> {
+        self.flow.diff(&other.flow)
+    }
+    /// merges two copies of a notebook, edited apart since their common
+    /// base; returns the merged vessel along with the conflicts.
+    ///
+    /// Where both sides changed the same thing, ours is taken and a
+    /// conflict reported. The glass and settings are also taken from ours,
+    /// refreshed against the merged flow; the history starts anew.
+    pub fn merge(
+        base: &Vessel,
+        ours: &Vessel,
+        theirs: &Vessel,
+    ) -> (Vessel, Vec<Conflict<EntityId>>) {
+        let merge = FlowArena::merge(&base.flow, &ours.flow, &theirs.flow);
+        let mut vessel = ours.clone();
+        vessel.flow = merge.flow;
+        vessel.history.clear();
+        vessel.glass_refresh();
+        (vessel, merge.conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_json, to_json, EntityField, Tube};
+
+    #[test]
+    fn merge_files() {
+        let mut base = Vessel::new();
+        let root = base.entity_grow().expect("grow");
+        let obj = base.entity_grow_devote(root, 0).expect("grow");
+        let base_json = to_json(&base);
+
+        let mut ours: Vessel = from_json(&base_json);
+        let added = ours.entity_grow_devote(root, 0).expect("grow");
+        ours.update_tube(Tube::EntityUpdate {
+            id: obj,
+            field: EntityField::Face("ours".to_string()),
+        });
+        let mut theirs: Vessel = from_json(&base_json);
+        theirs.update_tube(Tube::EntityUpdate {
+            id: root,
+            field: EntityField::Face("theirs".to_string()),
+        });
+        let ours_json = to_json(&ours);
+        let theirs_json = to_json(&theirs);
+
+        let diff = base.diff(&ours);
+        assert_eq!(diff.added, vec![added]);
+        assert_eq!(diff.changed, vec![obj]);
+        assert_eq!(diff.relinked, vec![root]);
+
+        let (merged, conflicts) = Vessel::merge(
+            &from_json(&base_json),
+            &from_json(&ours_json),
+            &from_json(&theirs_json),
+        );
+        assert!(conflicts.is_empty());
+        assert_eq!(merged.entity(&obj).unwrap().face, "ours");
+        assert_eq!(merged.entity(&root).unwrap().face, "theirs");
+        assert!(merged.entity_id_direct(&root).contains(&added));
+
+        theirs.update_tube(Tube::EntityUpdate {
+            id: obj,
+            field: EntityField::Face("theirs".to_string()),
+        });
+        let (merged, conflicts) = Vessel::merge(&base, &ours, &theirs);
+        assert_eq!(conflicts, vec![Conflict::Entity { obj }]);
+        assert_eq!(merged.entity(&obj).unwrap().face, "ours");
+    }
+}
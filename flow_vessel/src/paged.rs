@@ -0,0 +1,87 @@
+use flow_arena::{FlowBase, FlowError, PageError, PagedFlow, Pager};
+
+use super::{EntityFlow, EntityId, Vessel};
+
+type PageResult<T, P> =
+    Result<T, PageError<EntityId, <P as Pager<EntityFlow>>::Error>>;
+
+/// paging
+impl Vessel {
+    pub fn is_stub(&self, obj: &EntityId) -> bool {
+        self.pages.is_stub(obj)
+    }
+    pub fn stubs(&self) -> impl Iterator<Item = &EntityId> {
+        self.pages.stubs()
+    }
+    /// moves the entities owned by obj into the pager, e.g. a `FilePager`,
+    /// leaving obj a stub without children till it's loaded again.
+    ///
+    /// The stubs are saved along with the vessel, so the pages are found
+    /// again by the same pager after a restart. The history is cleared,
+    /// for it may hold the entities unloaded.
+    ///
+    /// Only a subtree linked from nowhere outside can be unloaded. Load a
+    /// stub before editing it; erasing it, or anything owning it, is
+    /// refused till then.
+    pub fn entity_unload<P: Pager<EntityFlow>>(
+        &mut self,
+        obj: &EntityId,
+        pager: &mut P,
+    ) -> PageResult<(), P> {
+        self.paged(pager, |paged| paged.unload(obj))
+    }
+    /// loads obj, with the pages on the way to it and the one under it if
+    /// it's a stub.
+    pub fn entity_load<P: Pager<EntityFlow>>(
+        &mut self,
+        obj: &EntityId,
+        pager: &mut P,
+    ) -> PageResult<(), P> {
+        self.paged(pager, |paged| paged.load(obj))
+    }
+    /// loads every page, till no stub is left.
+    pub fn entity_load_all<P: Pager<EntityFlow>>(
+        &mut self,
+        pager: &mut P,
+    ) -> PageResult<(), P> {
+        self.paged(pager, |paged| paged.load_all())
+    }
+    /// fails if obj owns a stub, itself included; erasing it would leave
+    /// its page behind.
+    pub(crate) fn stub_check(
+        &self,
+        obj: &EntityId,
+    ) -> Result<(), FlowError<EntityId>> {
+        if self.pages.is_empty() {
+            return Ok(());
+        }
+        let stub = self
+            .flow
+            .node_ownership_set(obj)
+            .into_iter()
+            .find(|id| self.pages.is_stub(id));
+        match stub {
+            Some(stub) => Err(FlowError::StubErase { obj: *obj, stub }),
+            None => Ok(()),
+        }
+    }
+    /// applies f to the flow paged by the pager; the history is cleared
+    /// if it succeeds.
+    fn paged<P: Pager<EntityFlow>, T>(
+        &mut self,
+        pager: &mut P,
+        f: impl FnOnce(&mut PagedFlow<EntityFlow, &mut P>) -> PageResult<T, P>,
+    ) -> PageResult<T, P> {
+        let flow = std::mem::take(&mut self.flow);
+        let pages = std::mem::take(&mut self.pages);
+        let mut paged = PagedFlow::with_index(flow, pager, pages);
+        let result = f(&mut paged);
+        let (flow, _, pages) = paged.into_parts();
+        self.flow = flow;
+        self.pages = pages;
+        if result.is_ok() {
+            self.history.clear();
+        }
+        result
+    }
+}
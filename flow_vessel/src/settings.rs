@@ -12,6 +12,8 @@ pub struct Settings {
     pub view_mode: ViewMode,
     #[serde(default)]
     pub workspace_mode: WorkspaceMode,
+    #[serde(default)]
+    pub format: Format,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +28,35 @@ impl Default for Bridge {
     }
 }
 
+/// How the vessel is saved; a vessel saved in either format loads.
+///
+/// Json is the default, stable to diff and keep in git; binary is smaller
+/// and opted into. Local storage on the web holds text, so a vessel is
+/// always saved as json there.
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Format {
+    Binary,
+    #[default]
+    Json,
+}
+
+impl Format {
+    pub fn switch(self) -> Self {
+        use Format::*;
+        match self {
+            Binary => Json,
+            Json => Binary,
+        }
+    }
+    pub fn display(&self) -> &str {
+        use Format::*;
+        match self {
+            Binary => "Binary",
+            Json => "Json",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ViewMode {
     Desktop,
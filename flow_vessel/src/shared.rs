@@ -0,0 +1,212 @@
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use super::{ChangeSet, Echo, LoadError, SaveError, Tube, Vessel};
+
+/// A vessel shared among threads: any number of readers, one writer at
+/// a time. Cloning the handle shares the same vessel.
+///
+/// Reads take a snapshot, which stays as it is however long it's kept.
+/// A write works on a copy of the latest snapshot and publishes it once
+/// done, so readers never wait for a write and never see one half made;
+/// the copy is cheap, since the flow and history are persistent.
+#[derive(Clone)]
+pub struct SharedVessel {
+    /// the latest published state.
+    current: Arc<RwLock<Arc<Vessel>>>,
+    /// held throughout a write, to keep them in line.
+    writer: Arc<Mutex<()>>,
+}
+
+impl SharedVessel {
+    pub fn new(vessel: Vessel) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(vessel))),
+            writer: Arc::new(Mutex::new(())),
+        }
+    }
+    /// the vessel as of the latest finished write.
+    pub fn snapshot(&self) -> Arc<Vessel> {
+        // a panicking writer never publishes, so the state is still whole
+        let current =
+            self.current.read().unwrap_or_else(PoisonError::into_inner);
+        current.clone()
+    }
+    pub fn read<T>(&self, f: impl FnOnce(&Vessel) -> T) -> T {
+        f(&self.snapshot())
+    }
+    /// applies f to a copy of the vessel and publishes it; waits for the
+    /// other writes. Nothing is published if f panics.
+    ///
+    /// The hooks called by f itself, e.g. by `Vessel::update_tube`, see
+    /// the state before; `SharedVessel::update_tube` calls them after.
+    pub fn write<T>(&self, f: impl FnOnce(&mut Vessel) -> T) -> T {
+        self.write_published(f).0
+    }
+    /// writes as `write` does; returns the vessel published as well.
+    fn write_published<T>(
+        &self,
+        f: impl FnOnce(&mut Vessel) -> T,
+    ) -> (T, Arc<Vessel>) {
+        let _writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut vessel = Vessel::clone(&self.snapshot());
+        let result = f(&mut vessel);
+        let vessel = Arc::new(vessel);
+        let mut current =
+            self.current.write().unwrap_or_else(PoisonError::into_inner);
+        *current = vessel.clone();
+        (result, vessel)
+    }
+    /// applies the tube as a single write.
+    pub fn update_tube(&self, tube: Tube) -> Echo {
+        self.update_tube_changes(tube).0
+    }
+    /// applies the tube as a single write; returns the changes made.
+    ///
+    /// The hooks are called once it's published and the next write may
+    /// begin, so they can read the snapshot or even write themselves.
+    pub fn update_tube_changes(&self, tube: Tube) -> (Echo, ChangeSet) {
+        let ((echo, changes), vessel) =
+            self.write_published(|vessel| vessel.update_tube_quiet(tube));
+        vessel.broadcast(&changes);
+        (echo, changes)
+    }
+    pub async fn load() -> Result<Self, LoadError> {
+        Vessel::load().await.map(Self::new)
+    }
+    /// saves the latest snapshot; writes go on meanwhile.
+    pub async fn save(&self) -> Result<(), SaveError> {
+        self.snapshot().save().await
+    }
+}
+
+impl From<Vessel> for SharedVessel {
+    fn from(vessel: Vessel) -> Self {
+        Self::new(vessel)
+    }
+}
+
+impl Default for SharedVessel {
+    fn default() -> Self {
+        Self::new(Vessel::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_json, to_json, EntityField, EntityId};
+    use flow_arena::FlowCheck;
+    use std::thread;
+
+    #[test]
+    fn readers_and_writers() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let shared = SharedVessel::new(vessel);
+        let (writers, adds) = (4, 50);
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut seen = 0;
+                    while seen < writers * adds {
+                        let snapshot = shared.snapshot();
+                        snapshot.flow.check().expect("consistent");
+                        let children = snapshot.entity_id_direct(&root);
+                        // a snapshot stays as it is
+                        assert!(children.len() >= seen);
+                        assert_eq!(snapshot.entity_id_direct(&root), children);
+                        // a face is written right after its entity is added
+                        let faced = children
+                            .iter()
+                            .filter(|id| {
+                                !snapshot.entity(id).unwrap().face.is_empty()
+                            })
+                            .count();
+                        assert!(children.len() - faced <= writers);
+                        seen = children.len();
+                        // saving needs no more than a snapshot
+                        let _json = to_json(&*snapshot);
+                    }
+                })
+            })
+            .collect();
+        let writing: Vec<_> = (0..writers)
+            .map(|i| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for j in 0..adds {
+                        let (_, changes) =
+                            shared.update_tube_changes(Tube::EntityAdd {
+                                dude: None,
+                                owner: root,
+                                idx: 0,
+                            });
+                        let obj: Vec<EntityId> = changes
+                            .affected()
+                            .into_iter()
+                            .filter(|id| id != &root)
+                            .collect();
+                        assert_eq!(obj.len(), 1);
+                        shared.update_tube(Tube::EntityUpdate {
+                            id: obj[0],
+                            field: EntityField::Face(format!("{}-{}", i, j)),
+                        });
+                    }
+                })
+            })
+            .collect();
+        for handle in writing.into_iter().chain(readers) {
+            handle.join().expect("join");
+        }
+
+        let snapshot = shared.snapshot();
+        assert_eq!(snapshot.entity_id_direct(&root).len(), writers * adds);
+        let loaded: Vessel = from_json(&to_json(&*snapshot));
+        assert_eq!(loaded.flow, snapshot.flow);
+    }
+
+    #[test]
+    fn hooks_after_publish() {
+        let shared = SharedVessel::default();
+        let obj = shared.write(|vessel| vessel.entity_grow()).expect("grow");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (hooked, log) = (shared.clone(), seen.clone());
+        shared.write(|vessel| {
+            vessel.subscribe(move |_| {
+                let face = hooked.snapshot().entity(&obj).unwrap().face.clone();
+                log.lock().unwrap().push(face);
+                // writing from a hook doesn't wait for itself
+                hooked.write(|vessel| vessel.entity_grow()).expect("grow");
+            })
+        });
+        shared.update_tube(Tube::EntityUpdate {
+            id: obj,
+            field: EntityField::Face("new".into()),
+        });
+        assert_eq!(*seen.lock().unwrap(), vec!["new".to_string()]);
+        assert_eq!(shared.snapshot().entity_id_all().len(), 2);
+    }
+
+    #[test]
+    fn poisoned_write() {
+        let shared = SharedVessel::default();
+        let before = shared.snapshot();
+        let panicking = shared.clone();
+        let result = thread::spawn(move || {
+            panicking.write(|vessel| {
+                vessel.entity_grow().expect("grow");
+                panic!("half way");
+            })
+        })
+        .join();
+        assert!(result.is_err());
+        // nothing published, and still writable
+        assert!(shared.snapshot().entity_id_all().is_empty());
+        shared.write(|vessel| vessel.entity_grow()).expect("grow");
+        assert_eq!(shared.snapshot().entity_id_all().len(), 1);
+        assert!(before.entity_id_all().is_empty());
+    }
+}
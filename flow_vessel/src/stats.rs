@@ -0,0 +1,176 @@
+use flow_arena::{FlowBase, FlowStats, Node, TraverseMode, TraverseOrder};
+use std::{collections::HashMap, fmt};
+
+use super::{EntityId, Process, Symbol, Tag, Vessel};
+
+/// Metrics of the vessel, or of the entities owned by one; see
+/// `Vessel::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VesselStats {
+    /// the structure of the flow.
+    pub flow: FlowStats<EntityId>,
+    /// entities by process, in the order of `Process::vec_all`; linted
+    /// ones aside.
+    pub processes: Vec<(Process, usize)>,
+    /// entities by tag, the most used first.
+    pub tags: Vec<(Tag, usize)>,
+    pub blocked: usize,
+    /// relations labeling a child no longer linked.
+    pub dangling_relations: usize,
+}
+
+impl VesselStats {
+    pub fn entities(&self) -> usize {
+        self.flow.nodes
+    }
+    /// links to children gone, and relations to children unlinked.
+    pub fn dangling(&self) -> usize {
+        self.flow.dangling_links + self.dangling_relations
+    }
+}
+
+/// e.g. "3,412 entries, max depth 9, 42 orphans, 117 pure links, 12
+/// dangling".
+impl fmt::Display for VesselStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entries, max depth {}, {} orphans, {} pure links, {} dangling",
+            grouped(self.entities()),
+            grouped(self.flow.max_depth()),
+            grouped(self.flow.orphans),
+            grouped(self.flow.pure_links),
+            grouped(self.dangling())
+        )
+    }
+}
+
+/// the number with its thousands apart, e.g. "3,412".
+fn grouped(n: usize) -> String {
+    let digits = n.to_string();
+    let mut s = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && i % 3 == digits.len() % 3 {
+            s.push(',');
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// statistics
+impl Vessel {
+    /// measures the whole vessel, keeping the top largest subtrees.
+    pub fn stats(&self, top: usize) -> VesselStats {
+        self.stats_from(self.flow.stats(top), self.flow.orphan())
+    }
+    /// measures obj and all it owns; None if obj not found.
+    pub fn stats_under(
+        &self,
+        obj: &EntityId,
+        top: usize,
+    ) -> Option<VesselStats> {
+        let flow = self.flow.stats_under(obj, top)?;
+        Some(self.stats_from(flow, vec![*obj]))
+    }
+    fn stats_from(
+        &self,
+        flow: FlowStats<EntityId>,
+        roots: Vec<EntityId>,
+    ) -> VesselStats {
+        let mut processes: Vec<(Process, usize)> =
+            Process::vec_all().into_iter().map(|p| (p, 0)).collect();
+        let mut tags: HashMap<Tag, usize> = HashMap::new();
+        let mut blocked = 0;
+        let mut dangling_relations = 0;
+        for root in roots {
+            let visits = self.flow.traverse(
+                &root,
+                TraverseOrder::PreOrder,
+                TraverseMode::Owned,
+            );
+            for visit in visits {
+                let node = self.flow.node(&visit.id).expect("visited");
+                let entity = &node.entity;
+                if let Symbol::Processing(process) = entity.symbol {
+                    if let Some((_, count)) =
+                        processes.iter_mut().find(|(p, _)| p == &process)
+                    {
+                        *count += 1;
+                    }
+                }
+                for tag in entity.tags.into_iter() {
+                    *tags.entry(tag).or_insert(0) += 1;
+                }
+                if entity.blocked {
+                    blocked += 1;
+                }
+                dangling_relations += entity
+                    .relations
+                    .iter()
+                    .filter(|(id, _)| !node.children().contains(id))
+                    .count();
+            }
+        }
+        let mut tags: Vec<(Tag, usize)> = tags.into_iter().collect();
+        tags.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+        VesselStats {
+            flow,
+            processes,
+            tags,
+            blocked,
+            dangling_relations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Relation;
+
+    #[test]
+    fn stats() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let obj = vessel.entity_grow_devote(root, 0).expect("grow");
+        let dude = vessel.entity_grow_devote(obj, 0).expect("grow");
+        let other = vessel.entity_grow().expect("grow");
+        vessel
+            .entity_link_as(dude, other, 0, Relation::SeeAlso)
+            .expect("link");
+        for (id, tag) in [(obj, "a"), (dude, "a"), (dude, "b"), (other, "b")] {
+            let entity = vessel.entity_mut(&id).expect("entity");
+            entity.tags.push(tag.into()).expect("tag");
+        }
+        for id in [obj, dude] {
+            vessel.entity_mut(&id).expect("entity").symbol =
+                Symbol::Processing(Process::Done);
+        }
+        vessel.entity_mut(&obj).expect("entity").blocked = true;
+        // dude isn't a child of root
+        let relations =
+            &mut vessel.entity_mut(&root).expect("entity").relations;
+        relations.set(dude, Some(Relation::Blocks));
+
+        let stats = vessel.stats(1);
+        assert_eq!(stats.entities(), 4);
+        assert_eq!(stats.flow.largest, vec![(root, 3)]);
+        assert!(stats.processes.contains(&(Process::Done, 2)));
+        assert_eq!(stats.tags, vec![("a".into(), 2), ("b".into(), 2)]);
+        assert_eq!(stats.blocked, 1);
+        assert_eq!(stats.dangling_relations, 1);
+        assert_eq!(
+            stats.to_string(),
+            "4 entries, max depth 2, 2 orphans, 1 pure links, 1 dangling"
+        );
+
+        let stats = vessel.stats_under(&obj, 3).expect("found");
+        assert_eq!(stats.entities(), 2);
+        assert_eq!(stats.tags, vec![("a".into(), 2), ("b".into(), 1)]);
+        assert_eq!(stats.dangling(), 0);
+        assert_eq!(grouped(3412), "3,412");
+        assert_eq!(grouped(1_000_000), "1,000,000");
+        assert_eq!(grouped(12), "12");
+    }
+}
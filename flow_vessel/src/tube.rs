@@ -1,6 +1,9 @@
-use flow_arena::{Direction, FlowError};
+use flow_arena::{Direction, FlowError, OuterLinks};
 
-use super::{Cube, CubeMeta, EntityField, EntityId, Router, Settings, Vessel};
+use super::{
+    ChangeSet, Cube, CubeId, CubeMeta, EntityField, EntityId, Relation, Router,
+    Settings, Vessel,
+};
 
 /// Tube is the message operating vessel, similar to EntityField
 #[derive(Debug, Clone)]
@@ -21,6 +24,11 @@ pub enum Tube {
     CloseVM {
         meta: CubeMeta,
     },
+    /// folds obj in the cube's outlines, or unfolds it
+    FoldToggle {
+        cube_id: CubeId,
+        obj: EntityId,
+    },
 
     // entity level
     EntityAdd {
@@ -28,6 +36,12 @@ pub enum Tube {
         owner: EntityId,
         idx: usize,
     },
+    /// adds a new entity right after `after`, or first if None; unlike
+    /// idx, the place holds when merged with edits made apart
+    EntityAddAfter {
+        owner: EntityId,
+        after: Option<EntityId>,
+    },
     EntityUpdate {
         id: EntityId,
         field: EntityField,
@@ -35,6 +49,11 @@ pub enum Tube {
     EntityDelete {
         id: EntityId,
     },
+    /// copies the entity and all it owns right after it
+    EntityDuplicate {
+        id: EntityId,
+        outer: OuterLinks,
+    },
     EntityMigrate {
         id: EntityId,
         dir: Direction,
@@ -46,30 +65,84 @@ pub enum Tube {
         owner: EntityId,
         nth: usize,
     },
+    /// links obj to owner with a meaning
+    EntityLinkAs {
+        obj: EntityId,
+        owner: EntityId,
+        nth: usize,
+        relation: Relation,
+    },
+    /// relabels the link from owner to obj; None for a plain link
+    EntityRelabel {
+        obj: EntityId,
+        owner: EntityId,
+        relation: Option<Relation>,
+    },
     EntityDevote {
         obj: EntityId,
         owner: EntityId,
         nth: usize,
     },
+    /// devotes obj right after `after`, or first if None
+    EntityDevoteAfter {
+        obj: EntityId,
+        owner: EntityId,
+        after: Option<EntityId>,
+    },
     EntityDecay {
         obj: EntityId,
     },
     EntityErase {
         obj: EntityId,
     },
+
+    // history level
+    Undo,
+    Redo,
 }
 
 /// Echo implies the side-effect after the Tube update.
+#[derive(Debug)]
 pub enum Echo {
     RebuildVM,
     RebuildRef,
-    FlowError(FlowError),
+    FlowError(FlowError<EntityId>),
     SendObj(EntityId),
     Standby,
 }
 
 impl Vessel {
+    /// applies the tube; all but the navigation and the history level
+    /// ones are recorded and can be undone.
     pub fn update_tube(&mut self, tube: Tube) -> Echo {
+        self.update_tube_changes(tube).0
+    }
+    /// applies the tube as `update_tube` does; returns the changes made
+    /// as well, which are also broadcast to the subscribers.
+    pub fn update_tube_changes(&mut self, tube: Tube) -> (Echo, ChangeSet) {
+        let (echo, changes) = self.update_tube_quiet(tube);
+        self.broadcast(&changes);
+        (echo, changes)
+    }
+    /// applies the tube as `update_tube_changes` does, but leaves the
+    /// broadcast to the caller.
+    pub(crate) fn update_tube_quiet(
+        &mut self,
+        tube: Tube,
+    ) -> (Echo, ChangeSet) {
+        let backward = match tube {
+            Tube::Undo => true,
+            Tube::Redo => false,
+            tube => {
+                return self.update_tube_recorded(tube, Self::update_tube_impl)
+            }
+        };
+        match self.step(backward) {
+            Ok((_, changes)) => (Echo::RebuildVM, changes),
+            Err(e) => (Echo::FlowError(e), ChangeSet::default()),
+        }
+    }
+    fn update_tube_impl(&mut self, tube: Tube) -> Echo {
         use Tube::*;
         match tube {
             SwitchRouter { router } => {
@@ -90,6 +163,12 @@ impl Vessel {
                 self.glass.remove_cube(meta).ok();
                 Echo::RebuildVM
             }
+            FoldToggle { cube_id, obj } => {
+                if let Some(cube) = self.glass.get_cube_mut(cube_id) {
+                    cube.toggle_fold(obj);
+                }
+                Echo::RebuildVM
+            }
 
             EntityAdd { dude, owner, idx } => dude
                 .map_or(self.entity_grow_devote(owner, idx), |dude| {
@@ -97,14 +176,22 @@ impl Vessel {
                 })
                 .err()
                 .map_or(Echo::RebuildRef, |e| Echo::FlowError(e)),
-            EntityUpdate { id, field } => {
-                self.entity_mut(&id)
-                    .map(|entity| entity.update_entity(field));
-                Echo::RebuildRef
-            }
+            EntityAddAfter { owner, after } => self
+                .entity_grow_devote_after(owner, after)
+                .map_or_else(Echo::FlowError, Echo::SendObj),
+            EntityUpdate { id, field } => self
+                .entity_mut(&id)
+                .map(|entity| entity.update_entity(field))
+                .map_or(
+                    Echo::FlowError(FlowError::NotExistObj { obj: id }),
+                    |_| Echo::RebuildRef,
+                ),
             EntityDelete { id } => self
                 .entity_remove(id)
                 .map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef),
+            EntityDuplicate { id, outer } => self
+                .entity_duplicate_subtree(id, outer)
+                .map_or_else(Echo::FlowError, Echo::SendObj),
             EntityMigrate { id, dir } => self
                 .entity_migrate(&id, dir)
                 .map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef),
@@ -116,10 +203,31 @@ impl Vessel {
                 let obj = self.entity_link(obj, owner, nth);
                 obj.map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef)
             }
+            EntityLinkAs {
+                obj,
+                owner,
+                nth,
+                relation,
+            } => {
+                let obj = self.entity_link_as(obj, owner, nth, relation);
+                obj.map_or_else(Echo::FlowError, |_| Echo::RebuildRef)
+            }
+            EntityRelabel {
+                obj,
+                owner,
+                relation,
+            } => {
+                let obj = self.entity_relabel(obj, owner, relation);
+                obj.map_or_else(Echo::FlowError, |_| Echo::RebuildRef)
+            }
             EntityDevote { obj, owner, nth } => {
                 let obj = self.entity_devote(obj, owner, nth);
                 obj.map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef)
             }
+            EntityDevoteAfter { obj, owner, after } => {
+                let obj = self.entity_devote_after(obj, owner, after);
+                obj.map_or_else(Echo::FlowError, |_| Echo::RebuildRef)
+            }
             EntityDecay { obj } => {
                 let obj = self.entity_decay(obj);
                 obj.map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef)
@@ -128,6 +236,7 @@ impl Vessel {
                 let obj = self.entity_erase(obj);
                 obj.map_or_else(|e| Echo::FlowError(e), |_| Echo::RebuildRef)
             }
+            Undo | Redo => Echo::Standby,
         }
     }
 }
@@ -0,0 +1,1029 @@
+//! A compact binary format for anything serde handles, e.g. a `Vessel`,
+//! an `EntityFlow` or a `Glass`.
+//!
+//! The bytes start with the header, `BINARY_MAGIC` and the format version,
+//! followed by the value:
+//! 1. integers are varints (LEB128, zigzag if signed); floats are raw.
+//! 2. strings, bytes, sequences and maps are prefixed by their length.
+//! 3. strings, tags and field names alike, are interned: the first one is
+//!    written in full and the rest refer to it by a varint.
+//! 4. structs are the number of fields written, then each field by name,
+//!    so that skipped and defaulted fields keep working.
+//! 5. ids, i.e. `TimeUnique`s, are interned as well; after the first time
+//!    an id is written, it takes no more than a varint.
+//! 6. enum variants are written by index.
+//!
+//! The format doesn't describe itself, so a field it doesn't know can't be
+//! skipped when reading.
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    ser::{self, Serialize},
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    io::{self, Read},
+};
+
+/// the first bytes of a binary encoded value; never the start of a json.
+pub const BINARY_MAGIC: &[u8; 4] = b"FLOW";
+/// bumped whenever the format changes.
+pub const BINARY_VERSION: u64 = 1;
+
+/// the structs interned as a whole; they must be plain, i.e. with all
+/// fields always written.
+const INTERNED: &[&str] = &["TimeUnique"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryError {
+    Message(String),
+    /// no `BINARY_MAGIC` at the start.
+    Header,
+    /// written in a version this build can't read.
+    Version(u64),
+    Eof,
+    /// a length that's unknown or broken.
+    Length,
+    /// a reference to an interned value never written.
+    Reference(u64),
+    Invalid,
+    /// asked to read without knowing what; the format isn't self-describing.
+    Unsupported,
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryError::*;
+        match self {
+            Message(msg) => write!(f, "{}", msg),
+            Header => write!(f, "not a binary encoded value"),
+            Version(v) => write!(f, "unknown format version {}", v),
+            Eof => write!(f, "unexpected end of input"),
+            Length => write!(f, "unknown or broken length"),
+            Reference(r) => write!(f, "unknown reference {}", r),
+            Invalid => write!(f, "invalid value"),
+            Unsupported => write!(f, "can't read a value without its type"),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+impl From<io::Error> for BinaryError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => BinaryError::Eof,
+            _ => BinaryError::Message(e.to_string()),
+        }
+    }
+}
+
+impl ser::Error for BinaryError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        BinaryError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for BinaryError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        BinaryError::Message(msg.to_string())
+    }
+}
+
+/// Whether the bytes are binary encoded, i.e. start with the header.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(BINARY_MAGIC)
+}
+
+/// Fallible to_binary ( T -> Binary ).
+pub fn write_binary<T: Serialize + ?Sized>(
+    v: &T,
+) -> Result<Vec<u8>, BinaryError> {
+    let mut ser = BinarySerializer::new(false);
+    ser.output.extend_from_slice(BINARY_MAGIC);
+    ser.varint(BINARY_VERSION);
+    v.serialize(&mut ser)?;
+    Ok(ser.output)
+}
+
+/// Fallible from_binary ( Binary -> T ); all of the bytes must be used.
+pub fn read_binary<T: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<T, BinaryError> {
+    let mut de = BinaryDeserializer::from_reader(bytes)?;
+    let v = T::deserialize(&mut de)?;
+    match de.finished()? {
+        true => Ok(v),
+        false => Err(BinaryError::Length),
+    }
+}
+
+/// Basic to_binary ( T -> Binary ).
+pub fn to_binary<T: Serialize>(v: &T) -> Vec<u8> {
+    write_binary(v).expect("binary serialization failed.")
+}
+
+/// Basic from_binary ( Binary -> T ).
+pub fn from_binary<T: DeserializeOwned>(bytes: &[u8]) -> T {
+    read_binary(bytes).expect("binary deserialization failed.")
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// A plain serializer interns nothing and writes structs by position; it
+/// writes an interned struct the first time, and the result is the key
+/// looked up the times after.
+struct BinarySerializer {
+    output: Vec<u8>,
+    plain: bool,
+    strings: HashMap<String, u64>,
+    values: HashMap<Vec<u8>, u64>,
+}
+
+impl BinarySerializer {
+    fn new(plain: bool) -> Self {
+        Self {
+            output: Vec::new(),
+            plain,
+            strings: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.output.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.output.push(v as u8);
+    }
+    fn len(&mut self, len: Option<usize>) -> Result<(), BinaryError> {
+        let len = len.ok_or(BinaryError::Length)?;
+        self.varint(len as u64);
+        Ok(())
+    }
+    /// 0 and the string for a new one, or 1 + the index of a known one.
+    fn string(&mut self, v: &str) {
+        if let Some(&idx) = self.strings.get(v) {
+            self.varint(idx + 1);
+            return;
+        }
+        self.varint(0);
+        self.varint(v.len() as u64);
+        self.output.extend_from_slice(v.as_bytes());
+        if !self.plain {
+            let idx = self.strings.len() as u64;
+            self.strings.insert(v.to_owned(), idx);
+        }
+    }
+    fn structure(
+        &mut self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'_>, BinaryError> {
+        if !self.plain && INTERNED.contains(&name) {
+            let mut value = BinarySerializer::new(true);
+            value.varint(len as u64);
+            return Ok(Compound::Interned {
+                ser: self,
+                value: Box::new(value),
+            });
+        }
+        self.varint(len as u64);
+        if self.plain {
+            Ok(Compound::Plain(self))
+        } else {
+            Ok(Compound::Keyed {
+                ser: self,
+                left: len,
+            })
+        }
+    }
+}
+
+enum Compound<'a> {
+    /// elements written one after another.
+    Plain(&'a mut BinarySerializer),
+    /// fields written with their names; as many as announced.
+    Keyed {
+        ser: &'a mut BinarySerializer,
+        left: usize,
+    },
+    /// written plain aside, then either in full or as a reference.
+    Interned {
+        ser: &'a mut BinarySerializer,
+        value: Box<BinarySerializer>,
+    },
+}
+
+impl Compound<'_> {
+    fn element<T: Serialize + ?Sized>(
+        &mut self,
+        v: &T,
+    ) -> Result<(), BinaryError> {
+        match self {
+            Compound::Plain(ser) => v.serialize(&mut **ser),
+            Compound::Keyed { ser, .. } => v.serialize(&mut **ser),
+            Compound::Interned { value, .. } => v.serialize(&mut **value),
+        }
+    }
+    fn field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        v: &T,
+    ) -> Result<(), BinaryError> {
+        if let Compound::Keyed { ser, left } = self {
+            *left = left.checked_sub(1).ok_or(BinaryError::Length)?;
+            ser.string(key);
+        }
+        self.element(v)
+    }
+    fn finish(self) -> Result<(), BinaryError> {
+        match self {
+            Compound::Plain(_) => Ok(()),
+            Compound::Keyed { left, .. } => match left {
+                0 => Ok(()),
+                _ => Err(BinaryError::Length),
+            },
+            Compound::Interned { ser, value } => {
+                if let Some(&idx) = ser.values.get(&value.output) {
+                    ser.varint(idx + 1);
+                } else {
+                    ser.varint(0);
+                    ser.varint(value.output.len() as u64);
+                    ser.output.extend_from_slice(&value.output);
+                    let idx = ser.values.len() as u64;
+                    ser.values.insert(value.output, idx);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut BinarySerializer {
+    type Ok = ();
+    type Error = BinaryError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), BinaryError> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), BinaryError> {
+        self.serialize_i64(v as i64)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), BinaryError> {
+        self.serialize_i64(v as i64)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), BinaryError> {
+        self.serialize_i64(v as i64)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), BinaryError> {
+        self.varint(zigzag(v));
+        Ok(())
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), BinaryError> {
+        self.serialize_u64(v as u64)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), BinaryError> {
+        self.serialize_u64(v as u64)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), BinaryError> {
+        self.serialize_u64(v as u64)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), BinaryError> {
+        self.varint(v);
+        Ok(())
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), BinaryError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), BinaryError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+    fn serialize_char(self, v: char) -> Result<(), BinaryError> {
+        self.serialize_u64(v as u64)
+    }
+    fn serialize_str(self, v: &str) -> Result<(), BinaryError> {
+        self.string(v);
+        Ok(())
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), BinaryError> {
+        self.varint(v.len() as u64);
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+    fn serialize_none(self) -> Result<(), BinaryError> {
+        self.output.push(0);
+        Ok(())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), BinaryError> {
+        Ok(())
+    }
+    fn serialize_unit_struct(
+        self,
+        _name: &'static str,
+    ) -> Result<(), BinaryError> {
+        Ok(())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), BinaryError> {
+        self.serialize_u32(variant_index)
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.varint(variant_index as u64);
+        value.serialize(self)
+    }
+    fn serialize_seq(
+        self,
+        len: Option<usize>,
+    ) -> Result<Compound<'a>, BinaryError> {
+        self.len(len)?;
+        Ok(Compound::Plain(self))
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, BinaryError> {
+        Ok(Compound::Plain(self))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, BinaryError> {
+        Ok(Compound::Plain(self))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, BinaryError> {
+        self.varint(variant_index as u64);
+        Ok(Compound::Plain(self))
+    }
+    fn serialize_map(
+        self,
+        len: Option<usize>,
+    ) -> Result<Compound<'a>, BinaryError> {
+        self.len(len)?;
+        Ok(Compound::Plain(self))
+    }
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, BinaryError> {
+        self.structure(name, len)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, BinaryError> {
+        self.varint(variant_index as u64);
+        self.structure("", len)
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_key<T: Serialize + ?Sized>(
+        &mut self,
+        key: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(key)
+    }
+    fn serialize_value<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.field(key, value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = BinaryError;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BinaryError> {
+        self.field(key, value)
+    }
+    fn end(self) -> Result<(), BinaryError> {
+        self.finish()
+    }
+}
+
+/// Reads what `BinarySerializer` writes; an interned struct is read by a
+/// plain deserializer of its own, as many times as it's referred to.
+pub(crate) struct BinaryDeserializer<R> {
+    input: R,
+    plain: bool,
+    strings: Vec<String>,
+    values: Vec<Vec<u8>>,
+}
+
+impl<R: Read> BinaryDeserializer<R> {
+    fn new(input: R, plain: bool) -> Self {
+        Self {
+            input,
+            plain,
+            strings: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+    /// reads the header, ready to read the value after.
+    pub(crate) fn from_reader(input: R) -> Result<Self, BinaryError> {
+        let mut de = Self::new(input, false);
+        if !is_binary(&de.take(BINARY_MAGIC.len())?) {
+            return Err(BinaryError::Header);
+        }
+        let version = de.varint()?;
+        if version != BINARY_VERSION {
+            return Err(BinaryError::Version(version));
+        }
+        Ok(de)
+    }
+    /// whether the input has run out.
+    pub(crate) fn finished(&mut self) -> Result<bool, BinaryError> {
+        Ok(self.input.read(&mut [0])? == 0)
+    }
+    fn take(&mut self, len: usize) -> Result<Vec<u8>, BinaryError> {
+        let mut buf = Vec::new();
+        (&mut self.input).take(len as u64).read_to_end(&mut buf)?;
+        match buf.len() == len {
+            true => Ok(buf),
+            false => Err(BinaryError::Eof),
+        }
+    }
+    fn byte(&mut self) -> Result<u8, BinaryError> {
+        let mut buf = [0];
+        self.input.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+    fn varint(&mut self) -> Result<u64, BinaryError> {
+        let mut v = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(BinaryError::Invalid)
+    }
+    fn len(&mut self) -> Result<usize, BinaryError> {
+        usize::try_from(self.varint()?).map_err(|_| BinaryError::Length)
+    }
+    fn int<T: TryFrom<u64>>(&mut self) -> Result<T, BinaryError> {
+        T::try_from(self.varint()?).map_err(|_| BinaryError::Invalid)
+    }
+    fn signed<T: TryFrom<i64>>(&mut self) -> Result<T, BinaryError> {
+        T::try_from(unzigzag(self.varint()?)).map_err(|_| BinaryError::Invalid)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BinaryError> {
+        let mut buf = [0; N];
+        self.input.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+    fn string(&mut self) -> Result<Cow<'_, str>, BinaryError> {
+        match self.varint()? {
+            0 => {
+                let len = self.len()?;
+                let s = String::from_utf8(self.take(len)?)
+                    .map_err(|_| BinaryError::Invalid)?;
+                if self.plain {
+                    return Ok(Cow::Owned(s));
+                }
+                self.strings.push(s);
+                Ok(Cow::Borrowed(self.strings.last().expect("pushed")))
+            }
+            r => self
+                .strings
+                .get(r as usize - 1)
+                .map(|s| Cow::Borrowed(s.as_str()))
+                .ok_or(BinaryError::Reference(r)),
+        }
+    }
+    fn access(&mut self, left: usize) -> Access<'_, R> {
+        Access { de: self, left }
+    }
+}
+
+struct Access<'a, R> {
+    de: &'a mut BinaryDeserializer<R>,
+    left: usize,
+}
+
+impl<'de, R: Read> SeqAccess<'de> for Access<'_, R> {
+    type Error = BinaryError;
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, BinaryError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de, R: Read> MapAccess<'de> for Access<'_, R> {
+    type Error = BinaryError;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, BinaryError> {
+        self.next_element_seed(seed)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, BinaryError> {
+        seed.deserialize(&mut *self.de)
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de, R: Read> de::Deserializer<'de> for &mut BinaryDeserializer<R> {
+    type Error = BinaryError;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        Err(BinaryError::Unsupported)
+    }
+    fn deserialize_bool<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        match self.byte()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(BinaryError::Invalid),
+        }
+    }
+    fn deserialize_i8<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_i8(self.signed()?)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_i16(self.signed()?)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_i32(self.signed()?)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_i64(self.signed()?)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_u8(self.int()?)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_u16(self.int()?)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_u32(self.int()?)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_u64(self.varint()?)
+    }
+    fn deserialize_f32<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_f32(f32::from_le_bytes(self.array()?))
+    }
+    fn deserialize_f64<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_f64(f64::from_le_bytes(self.array()?))
+    }
+    fn deserialize_char<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        let c = char::from_u32(self.int()?).ok_or(BinaryError::Invalid)?;
+        visitor.visit_char(c)
+    }
+    fn deserialize_str<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        match self.string()? {
+            Cow::Borrowed(s) => visitor.visit_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
+    }
+    fn deserialize_string<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        let len = self.len()?;
+        visitor.visit_byte_buf(self.take(len)?)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        self.deserialize_bytes(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        match self.byte()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(BinaryError::Invalid),
+        }
+    }
+    fn deserialize_unit<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_unit()
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        let len = self.len()?;
+        visitor.visit_seq(self.access(len))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_seq(self.access(len))
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_seq(self.access(len))
+    }
+    fn deserialize_map<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        let len = self.len()?;
+        visitor.visit_map(self.access(len))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        if !self.plain && INTERNED.contains(&name) {
+            let bytes = match self.varint()? {
+                0 => {
+                    let len = self.len()?;
+                    let bytes = self.take(len)?;
+                    self.values.push(bytes.clone());
+                    bytes
+                }
+                r => self
+                    .values
+                    .get(r as usize - 1)
+                    .cloned()
+                    .ok_or(BinaryError::Reference(r))?,
+            };
+            let mut value = BinaryDeserializer::new(bytes.as_slice(), true);
+            let v = value.deserialize_struct(name, fields, visitor)?;
+            return match value.finished()? {
+                true => Ok(v),
+                false => Err(BinaryError::Length),
+            };
+        }
+        let len = self.len()?;
+        if self.plain {
+            visitor.visit_seq(self.access(len))
+        } else {
+            visitor.visit_map(self.access(len))
+        }
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        visitor.visit_enum(self)
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        self.deserialize_str(visitor)
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        Err(BinaryError::Unsupported)
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<'de, R: Read> EnumAccess<'de> for &mut BinaryDeserializer<R> {
+    type Error = BinaryError;
+    type Variant = Self;
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), BinaryError> {
+        let idx: u32 = self.int()?;
+        let idx = IntoDeserializer::<BinaryError>::into_deserializer(idx);
+        let v = seed.deserialize(idx)?;
+        Ok((v, self))
+    }
+}
+
+impl<'de, R: Read> VariantAccess<'de> for &mut BinaryDeserializer<R> {
+    type Error = BinaryError;
+    fn unit_variant(self) -> Result<(), BinaryError> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, BinaryError> {
+        seed.deserialize(self)
+    }
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BinaryError> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn vessel() -> Vessel {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let mut ids = vec![root];
+        for i in 0..40 {
+            let owner = ids[i / 4];
+            let id = vessel.entity_grow_devote(owner, 0).expect("grow");
+            let entity = vessel.entity_mut(&id).unwrap();
+            entity.update_entity(EntityField::Face(format!("entity {}", i)));
+            let tag = TagSetField::AddTag(format!("tag {}", i % 3));
+            entity.update_entity(EntityField::TagSet(tag));
+            ids.push(id);
+        }
+        vessel
+            .entity_link_as(ids[40], root, 0, Relation::Blocks)
+            .expect("link");
+        let cube = Cube::new(CubeType::FlowView).with_obj(ids[1]);
+        let cube = vessel.glass.add_cube(cube);
+        let meta = CubeMeta {
+            router: Router::Workspace,
+            idx: 0,
+        };
+        vessel
+            .glass
+            .place_cube(cube, meta)
+            .expect("place_cube failed");
+        vessel.settings.bridge = Bridge::Linked {
+            addr: "localhost".into(),
+            port: 8080,
+        };
+        vessel
+    }
+
+    #[test]
+    fn round_trip() {
+        let vessel = vessel();
+        let json = to_json(&vessel);
+        let binary = to_binary(&vessel);
+        assert!(is_binary(&binary));
+        assert!(binary.len() * 3 < json.len());
+
+        // both formats read back the same
+        let from_bin: Vessel = from_binary(&binary);
+        let from_js: Vessel = from_json(&json);
+        assert_eq!(to_json(&from_bin), json);
+        assert_eq!(to_json(&from_js), json);
+        assert_eq!(to_binary(&from_bin), binary);
+        assert_eq!(from_bin.flow, vessel.flow);
+
+        // and so do the parts alone
+        let flow: EntityFlow = from_binary(&to_binary(&vessel.flow));
+        assert_eq!(flow, vessel.flow);
+        let glass: Glass = from_binary(&to_binary(&vessel.glass));
+        assert_eq!(to_json(&glass), to_json(&vessel.glass));
+    }
+
+    #[test]
+    fn header() {
+        let binary = to_binary(&vessel());
+        assert_eq!(
+            read_binary::<Vessel>(&binary[1..]).unwrap_err(),
+            BinaryError::Header
+        );
+        let mut newer = binary.clone();
+        newer[BINARY_MAGIC.len()] = BINARY_VERSION as u8 + 1;
+        assert_eq!(
+            read_binary::<Vessel>(&newer).unwrap_err(),
+            BinaryError::Version(BINARY_VERSION + 1)
+        );
+        let cut = &binary[..binary.len() - 1];
+        assert!(read_binary::<Vessel>(cut).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use flow_arena::{FlowBase, Node, TraverseMode, TraverseOrder};
+use std::collections::{HashMap, HashSet};
+
+use crate::{Entity, EntityId, Filter, Symbol, Vessel};
+
+/// The text formats a flow can be drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagram {
+    /// Graphviz; pure links are dashed, and labeled by their relations.
+    Dot,
+    /// Mermaid flowchart; pure links are dotted, and labeled by their
+    /// relations.
+    Flowchart,
+    /// Mermaid mindmap; a pure tree, so pure links are left out.
+    Mindmap,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagramOptions {
+    /// the subtree to draw; all the orphans if None.
+    pub root: Option<EntityId>,
+    /// no entities deeper than it if given; the roots are at 0.
+    pub max_depth: Option<usize>,
+    /// only the entities matching all filters are drawn, along with their
+    /// owners to keep them in place.
+    pub filters: Vec<Filter>,
+    /// whether to draw pure links.
+    pub links: bool,
+}
+
+impl Default for DiagramOptions {
+    fn default() -> Self {
+        Self {
+            root: None,
+            max_depth: None,
+            filters: Vec::new(),
+            links: true,
+        }
+    }
+}
+
+/// an entity to draw; its owner drawn before.
+struct Shape<'a> {
+    id: EntityId,
+    depth: usize,
+    owner: Option<EntityId>,
+    entity: &'a Entity,
+}
+
+/// diagram export
+impl Vessel {
+    /// draws the flow as diagram text, in the order of ownership.
+    ///
+    /// A label is made of the face, the process state if any and the tags.
+    pub fn export_diagram(
+        &self,
+        diagram: Diagram,
+        options: &DiagramOptions,
+    ) -> String {
+        let shapes = self.diagram_shapes(options);
+        match diagram {
+            Diagram::Dot => self.export_dot(&shapes, options),
+            Diagram::Flowchart => self.export_flowchart(&shapes, options),
+            Diagram::Mindmap => Self::export_mindmap(&shapes),
+        }
+    }
+    fn diagram_shapes(&self, options: &DiagramOptions) -> Vec<Shape<'_>> {
+        let roots = match options.root {
+            Some(root) => vec![root],
+            None => {
+                let mut orphan = self.orphan();
+                orphan.sort();
+                orphan
+            }
+        };
+        let shapes: Vec<Shape> = flow_arena::Traverse::new(
+            &self.flow,
+            roots,
+            TraverseOrder::PreOrder,
+            TraverseMode::Owned,
+        )
+        .filter(|visit| !matches!(options.max_depth, Some(max) if visit.depth > max))
+        .filter_map(|visit| {
+            let entity = self.entity(&visit.id)?;
+            Some(Shape {
+                id: visit.id,
+                depth: visit.depth,
+                owner: visit.parent,
+                entity,
+            })
+        })
+        .collect();
+        if options.filters.is_empty() {
+            return shapes;
+        }
+        // the matched and all their owners
+        let owners: HashMap<EntityId, Option<EntityId>> =
+            shapes.iter().map(|shape| (shape.id, shape.owner)).collect();
+        let mut kept = HashSet::new();
+        for shape in shapes.iter() {
+            if !shape.entity.matched_all(&options.filters) {
+                continue;
+            }
+            let mut current = Some(shape.id);
+            while let Some(id) = current {
+                if !kept.insert(id) {
+                    break;
+                }
+                current = owners.get(&id).cloned().flatten();
+            }
+        }
+        shapes
+            .into_iter()
+            .filter(|shape| kept.contains(&shape.id))
+            .collect()
+    }
+    /// pure links among the shapes, as (linker, linked).
+    fn diagram_links(&self, shapes: &[Shape]) -> Vec<(EntityId, EntityId)> {
+        let drawn: HashSet<EntityId> =
+            shapes.iter().map(|shape| shape.id).collect();
+        shapes
+            .iter()
+            .flat_map(|shape| {
+                self.flow
+                    .children(&shape.id)
+                    .into_iter()
+                    .filter(|id| drawn.contains(id))
+                    .filter(|id| {
+                        self.node(id).and_then(|node| node.parent())
+                            != Some(shape.id)
+                    })
+                    .map(move |id| (shape.id, id))
+            })
+            .collect()
+    }
+    fn export_dot(&self, shapes: &[Shape], options: &DiagramOptions) -> String {
+        let names = diagram_names(shapes);
+        let mut text = String::from("digraph flow {\n    node [shape=box];\n");
+        for shape in shapes {
+            let label = diagram_label(shape.entity, "\n")
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            text +=
+                &format!("    {} [label=\"{}\"];\n", names[&shape.id], label);
+        }
+        for shape in shapes {
+            if let Some(owner) = shape.owner.and_then(|id| names.get(&id)) {
+                text += &format!("    {} -> {};\n", owner, names[&shape.id]);
+            }
+        }
+        if options.links {
+            for (linker, linked) in self.diagram_links(shapes) {
+                let label = self
+                    .entity_relation(&linker, &linked)
+                    .map_or(String::new(), |relation| {
+                        format!(", label=\"{}\"", relation)
+                    });
+                text += &format!(
+                    "    {} -> {} [style=dashed{}];\n",
+                    names[&linker], names[&linked], label
+                );
+            }
+        }
+        text + "}\n"
+    }
+    fn export_flowchart(
+        &self,
+        shapes: &[Shape],
+        options: &DiagramOptions,
+    ) -> String {
+        let names = diagram_names(shapes);
+        let mut text = String::from("flowchart TD\n");
+        for shape in shapes {
+            let label =
+                mermaid_escape(&diagram_label(shape.entity, "<br/>"), "<br/>");
+            text += &format!("    {}[\"{}\"]\n", names[&shape.id], label);
+        }
+        for shape in shapes {
+            if let Some(owner) = shape.owner.and_then(|id| names.get(&id)) {
+                text += &format!("    {} --> {}\n", owner, names[&shape.id]);
+            }
+        }
+        if options.links {
+            for (linker, linked) in self.diagram_links(shapes) {
+                let label = self
+                    .entity_relation(&linker, &linked)
+                    .map_or(String::new(), |relation| {
+                        format!("|{}|", relation)
+                    });
+                text += &format!(
+                    "    {} -.->{} {}\n",
+                    names[&linker], label, names[&linked]
+                );
+            }
+        }
+        text
+    }
+    fn export_mindmap(shapes: &[Shape]) -> String {
+        let names = diagram_names(shapes);
+        let mut text = String::from("mindmap\n");
+        // a mindmap has a single root
+        let roots = shapes.iter().filter(|shape| shape.depth == 0).count();
+        let indent = if roots > 1 {
+            text += "  root((flow))\n";
+            2
+        } else {
+            1
+        };
+        for shape in shapes {
+            let label = mermaid_escape(&diagram_label(shape.entity, " "), " ");
+            text += &format!(
+                "{}{}[\"{}\"]\n",
+                "  ".repeat(shape.depth + indent),
+                names[&shape.id],
+                label
+            );
+        }
+        text
+    }
+}
+
+/// names the shapes by their order, as n0, n1, ...
+fn diagram_names(shapes: &[Shape]) -> HashMap<EntityId, String> {
+    shapes
+        .iter()
+        .enumerate()
+        .map(|(idx, shape)| (shape.id, format!("n{}", idx)))
+        .collect()
+}
+
+/// face, and then the process and tags after a break if any.
+fn diagram_label(entity: &Entity, br: &str) -> String {
+    let mut marks = Vec::new();
+    if let Symbol::Processing(process) = entity.symbol {
+        marks.push(format!("[{}]", process.type_str()));
+    }
+    marks.extend(entity.tags.into_iter().map(|tag| format!("#{}", tag)));
+    if marks.is_empty() {
+        entity.face.clone()
+    } else {
+        format!("{}{}{}", entity.face, br, marks.join(" "))
+    }
+}
+
+/// quotes escaped, and line breaks in the face replaced by br, as a bare
+/// one ends the node.
+fn mermaid_escape(label: &str, br: &str) -> String {
+    label
+        .replace('"', "#quot;")
+        .replace("\r\n", br)
+        .replace(['\r', '\n'], br)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityField, Process, TagSetField};
+    use flow_arena::{FlowDevote, FlowLink};
+
+    /// 0 -> {1 -> {2}, 3 -> {(2)}}; 4
+    fn make_vessel() -> (Vec<EntityId>, Vessel) {
+        let mut vessel = Vessel::new();
+        let id: Vec<EntityId> =
+            (0..5).filter_map(|_| vessel.entity_grow().ok()).collect();
+        for (obj, owner) in [(1, 0), (2, 1), (3, 0)] {
+            vessel
+                .flow
+                .devote_push(&id[obj], &id[owner])
+                .expect("devote");
+        }
+        vessel.flow.link_push(&id[2], &id[3]).expect("link");
+        vessel
+            .entity_relabel(id[2], id[3], Some(crate::Relation::Blocks))
+            .expect("relabel");
+        for (i, id) in id.iter().enumerate() {
+            let entity = vessel.entity_mut(id).unwrap();
+            entity.update_entity(EntityField::Face(format!("node \"{}\"", i)));
+        }
+        let entity = vessel.entity_mut(&id[2]).unwrap();
+        entity.update_entity(EntityField::Symbol(Symbol::Processing(
+            Process::Done,
+        )));
+        entity.update_entity(EntityField::TagSet(TagSetField::AddTag(
+            "work".into(),
+        )));
+        (id, vessel)
+    }
+
+    #[test]
+    fn dot() {
+        let (id, vessel) = make_vessel();
+        let options = DiagramOptions {
+            root: Some(id[0]),
+            ..Default::default()
+        };
+        let dot = vessel.export_diagram(Diagram::Dot, &options);
+        assert_eq!(
+            dot,
+            "digraph flow {\n    node [shape=box];\n\
+             \x20   n0 [label=\"node \\\"0\\\"\"];\n\
+             \x20   n1 [label=\"node \\\"1\\\"\"];\n\
+             \x20   n2 [label=\"node \\\"2\\\"\\n[Done] #work\"];\n\
+             \x20   n3 [label=\"node \\\"3\\\"\"];\n\
+             \x20   n0 -> n1;\n\
+             \x20   n1 -> n2;\n\
+             \x20   n0 -> n3;\n\
+             \x20   n3 -> n2 [style=dashed, label=\"blocks\"];\n\
+             }\n"
+        );
+        // the whole vessel
+        let dot = vessel.export_diagram(Diagram::Dot, &Default::default());
+        assert_eq!(dot.matches("[label=").count(), 5);
+    }
+
+    #[test]
+    fn mermaid() {
+        let (id, vessel) = make_vessel();
+        let options = DiagramOptions {
+            root: Some(id[0]),
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let flowchart = vessel.export_diagram(Diagram::Flowchart, &options);
+        assert_eq!(
+            flowchart,
+            "flowchart TD\n\
+             \x20   n0[\"node #quot;0#quot;\"]\n\
+             \x20   n1[\"node #quot;1#quot;\"]\n\
+             \x20   n2[\"node #quot;3#quot;\"]\n\
+             \x20   n0 --> n1\n\
+             \x20   n0 --> n2\n"
+        );
+        let options = DiagramOptions {
+            filters: vec![Filter::Tag("work".into())],
+            ..Default::default()
+        };
+        let mindmap = vessel.export_diagram(Diagram::Mindmap, &options);
+        assert_eq!(
+            mindmap,
+            "mindmap\n\
+             \x20 n0[\"node #quot;0#quot;\"]\n\
+             \x20   n1[\"node #quot;1#quot;\"]\n\
+             \x20     n2[\"node #quot;2#quot; [Done] #work\"]\n"
+        );
+        let flowchart = vessel.export_diagram(Diagram::Flowchart, &options);
+        assert!(!flowchart.contains("-.->"));
+    }
+
+    #[test]
+    fn mermaid_newline() {
+        let (id, mut vessel) = make_vessel();
+        let entity = vessel.entity_mut(&id[1]).unwrap();
+        entity.update_entity(EntityField::Face("two\nlines\r\n".into()));
+        let options = DiagramOptions {
+            root: Some(id[1]),
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        let flowchart = vessel.export_diagram(Diagram::Flowchart, &options);
+        assert_eq!(flowchart, "flowchart TD\n    n0[\"two<br/>lines<br/>\"]\n");
+        let mindmap = vessel.export_diagram(Diagram::Mindmap, &options);
+        assert_eq!(mindmap, "mindmap\n  n0[\"two lines \"]\n");
+    }
+}
@@ -1,3 +1,8 @@
+pub mod binary;
+pub mod diagram;
 pub mod json;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pager;
 pub mod saveload;
+pub mod stream;
 pub mod time;
@@ -0,0 +1,147 @@
+use flow_arena::Pager;
+use std::{
+    fmt::Write,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use crate::{read_binary, write_binary, EntityFlow, EntityId, BINARY_MAGIC};
+
+/// Keeps the pages of a `PagedFlow` of entities as files in a directory,
+/// binary encoded, one per stub.
+#[derive(Debug, Clone)]
+pub struct FilePager {
+    dir: PathBuf,
+}
+
+impl FilePager {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+    /// the file of the page, named after the stub.
+    pub fn path(&self, stub: &EntityId) -> io::Result<PathBuf> {
+        let bytes = write_binary(stub).map_err(invalid)?;
+        let mut name = String::new();
+        for byte in &bytes[BINARY_MAGIC.len()..] {
+            write!(name, "{:02x}", byte).expect("write to string");
+        }
+        Ok(self.dir.join(name).with_extension("flow"))
+    }
+}
+
+impl Pager<EntityFlow> for FilePager {
+    type Error = io::Error;
+    fn store(&mut self, stub: &EntityId, page: &EntityFlow) -> io::Result<()> {
+        let bytes = write_binary(page).map_err(invalid)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(stub)?, bytes)
+    }
+    fn fetch(&mut self, stub: &EntityId) -> io::Result<EntityFlow> {
+        let bytes = fs::read(self.path(stub)?)?;
+        read_binary(&bytes).map_err(invalid)
+    }
+    fn discard(&mut self, stub: &EntityId) -> io::Result<()> {
+        match fs::remove_file(self.path(stub)?) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn invalid(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Echo, LoadScope, Tube, Vessel};
+    use flow_arena::{FlowBase, FlowError, PageError, PagedFlow};
+
+    #[test]
+    fn pages_on_disk() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let obj = vessel.entity_grow_devote(root, 0).expect("grow");
+        let mut leaves = Vec::new();
+        for i in 0..3 {
+            let id = vessel.entity_grow_devote(obj, i).expect("grow");
+            vessel.entity_mut(&id).expect("entity").face =
+                format!("leaf {}", i);
+            leaves.push(id);
+        }
+        let dir = std::env::temp_dir()
+            .join(format!("flow_pager_{}", std::process::id()));
+        let pager = FilePager::new(&dir);
+        let mut paged = PagedFlow::new(vessel.flow.clone(), pager);
+
+        paged.unload(&obj).expect("unload");
+        let path = paged.pager().path(&obj).expect("path");
+        assert!(path.exists());
+        assert!(!paged.is_loaded(&leaves[0]));
+        assert_eq!(paged.children(&obj).expect("children"), leaves);
+        let set = paged.node_ownership_set(&root).expect("set");
+        assert_eq!(set.len(), 5);
+
+        let node = paged.node(&leaves[1]).expect("node");
+        assert_eq!(node.entity.face, "leaf 1");
+        assert!(!path.exists());
+        assert_eq!(paged.flow(), &vessel.flow);
+
+        paged.unload(&obj).expect("unload");
+        paged.erase(&obj).expect("erase");
+        assert!(!path.exists());
+        assert_eq!(paged.flow().node_ownership_set(&root).len(), 1);
+        assert!(matches!(paged.load(&leaves[2]), Err(PageError::Flow(_))));
+        fs::remove_dir_all(&dir).expect("clean up");
+    }
+
+    #[test]
+    fn vessel_pages() {
+        let mut vessel = Vessel::new();
+        let root = vessel.entity_grow().expect("grow");
+        let obj = vessel.entity_grow_devote(root, 0).expect("grow");
+        let leaf = vessel.entity_grow_devote(obj, 0).expect("grow");
+        vessel.entity_mut(&leaf).expect("entity").face = "leaf".into();
+        let whole = vessel.flow.clone();
+        let dir = std::env::temp_dir()
+            .join(format!("flow_vessel_pages_{}", std::process::id()));
+
+        vessel
+            .entity_unload(&obj, &mut FilePager::new(&dir))
+            .expect("unload");
+        assert!(vessel.is_stub(&obj));
+        assert!(vessel.entity(&leaf).is_none());
+        assert!(!vessel.history().can_undo());
+        // nothing owning a stub is erased, lest its page be left behind
+        let echo = vessel.update_tube(Tube::EntityDelete { id: root });
+        assert!(matches!(
+            echo,
+            Echo::FlowError(FlowError::StubErase { obj: o, stub })
+                if o == root && stub == obj
+        ));
+        assert!(vessel.entity_erase(obj).is_err());
+        assert!(vessel.is_stub(&obj));
+
+        // saved and loaded back, as if after a restart
+        let bytes = vessel.encode(crate::Format::Binary).expect("encode");
+        let streamed =
+            Vessel::load_from(bytes.as_slice(), LoadScope::All, |_| {})
+                .expect("load");
+        assert!(streamed.is_stub(&obj));
+        let mut vessel = Vessel::decode(&bytes).expect("decode");
+        assert_eq!(vessel.stubs().collect::<Vec<_>>(), vec![&obj]);
+        vessel
+            .entity_load(&leaf, &mut FilePager::new(&dir))
+            .expect("load");
+        assert_eq!(vessel.entity(&leaf).expect("entity").face, "leaf");
+        assert!(vessel.stubs().next().is_none());
+        assert_eq!(vessel.flow, whole);
+        vessel.entity_remove(root).expect("remove");
+        fs::remove_dir_all(&dir).expect("clean up");
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{Bridge, Vessel};
+use crate::{
+    is_binary, read_binary, write_binary, Bridge, EntityId, Format,
+    LoadProgress, LoadScope, Vessel,
+};
 
 #[derive(Debug, Clone)]
 pub enum LoadError {
     FileError,
     WebError,
     FormatError,
+    /// the root of a `LoadScope::Subtree` isn't in the flow.
+    NotExistRoot(EntityId),
 }
 
 #[derive(Debug, Clone)]
@@ -13,11 +18,38 @@ pub enum SaveError {
     WebError,
     WriteError,
     FormatError,
+    /// the vessel holds only part of the flow; see `Vessel::is_partial`.
+    Partial,
+}
+
+impl Vessel {
+    /// the vessel as saved in the format given; a partial vessel is
+    /// refused.
+    pub fn encode(&self, format: Format) -> Result<Vec<u8>, SaveError> {
+        if self.partial {
+            return Err(SaveError::Partial);
+        }
+        match format {
+            Format::Binary => {
+                write_binary(self).map_err(|_| SaveError::FormatError)
+            }
+            Format::Json => serde_json::to_vec_pretty(self)
+                .map_err(|_| SaveError::FormatError),
+        }
+    }
+    /// a saved vessel in either format, told apart by the binary header.
+    pub fn decode(bytes: &[u8]) -> Result<Vessel, LoadError> {
+        if is_binary(bytes) {
+            read_binary(bytes).map_err(|_| LoadError::FormatError)
+        } else {
+            serde_json::from_slice(bytes).map_err(|_| LoadError::FormatError)
+        }
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl Vessel {
-    fn path() -> std::path::PathBuf {
+    fn path(format: Format) -> std::path::PathBuf {
         let mut path = if let Some(project_dirs) =
             directories_next::ProjectDirs::from("", "LighghtEeloo", "flow.er")
         {
@@ -26,26 +58,45 @@ impl Vessel {
             std::env::current_dir().unwrap_or(std::path::PathBuf::new())
         };
 
-        path.push("flow_data.json");
+        path.push(match format {
+            Format::Binary => "flow_data.flow",
+            Format::Json => "flow_data.json",
+        });
 
         path
     }
 
+    /// loads the vessel saved last, whichever the format.
     pub async fn load() -> Result<Vessel, LoadError> {
-        use async_std::prelude::*;
-
-        let mut contents = String::new();
-
-        let mut file = async_std::fs::File::open(Self::path())
-            .await
-            .map_err(|_| LoadError::FileError)?;
+        Self::load_with(LoadScope::All, |_| {}).await
+    }
 
-        file.read_to_string(&mut contents)
-            .await
-            .map_err(|_| LoadError::FileError)?;
+    /// loads the vessel saved last as `Vessel::load_from` does, streaming
+    /// the file off the current task.
+    pub async fn load_with(
+        scope: LoadScope,
+        progress: impl FnMut(&LoadProgress) + Send + 'static,
+    ) -> Result<Vessel, LoadError> {
+        let mut latest = None;
+        for format in [Format::Json, Format::Binary] {
+            let path = Self::path(format);
+            let modified = async_std::fs::metadata(&path)
+                .await
+                .and_then(|meta| meta.modified());
+            if let Ok(modified) = modified {
+                if latest.as_ref().is_none_or(|(time, _)| &modified >= time) {
+                    latest = Some((modified, path));
+                }
+            }
+        }
+        let (_, path) = latest.ok_or(LoadError::FileError)?;
 
-        let vessel: Vessel = serde_json::from_str(&contents)
-            .map_err(|_| LoadError::FormatError)?;
+        let vessel = async_std::task::spawn_blocking(move || {
+            let file =
+                std::fs::File::open(path).map_err(|_| LoadError::FileError)?;
+            Self::load_from(file, scope, progress)
+        })
+        .await?;
 
         if let Bridge::Linked { .. } = vessel.settings.bridge {
             // Todo: link and save.
@@ -54,13 +105,12 @@ impl Vessel {
         Ok(vessel)
     }
 
-    pub async fn save(self) -> Result<(), SaveError> {
+    pub async fn save(&self) -> Result<(), SaveError> {
         use async_std::prelude::*;
 
-        let json = serde_json::to_string_pretty(&self)
-            .map_err(|_| SaveError::FormatError)?;
+        let contents = self.encode(self.settings.format)?;
 
-        let path = Self::path();
+        let path = Self::path(self.settings.format);
 
         if let Some(dir) = path.parent() {
             async_std::fs::create_dir_all(dir)
@@ -73,7 +123,7 @@ impl Vessel {
                 .await
                 .map_err(|_| SaveError::FileError)?;
 
-            file.write_all(json.as_bytes())
+            file.write_all(&contents)
                 .await
                 .map_err(|_| SaveError::WriteError)?;
         }
@@ -142,8 +192,11 @@ impl Vessel {
         Ok(vessel)
     }
 
-    pub async fn save(self) -> Result<(), SaveError> {
+    pub async fn save(&self) -> Result<(), SaveError> {
         log::debug!("saving...");
+        if self.partial {
+            return Err(SaveError::Partial);
+        }
         let storage = Self::storage().ok_or(SaveError::FileError)?;
 
         let json = serde_json::to_string_pretty(&self)
@@ -163,6 +216,39 @@ impl Vessel {
 mod tests {
     use crate::*;
 
+    #[test]
+    fn formats() {
+        let mut vessel = Vessel::new();
+        let obj = vessel.entity_grow().expect("grow");
+        vessel.entity_grow_devote(obj, 0).expect("grow");
+        let json = to_json(&vessel);
+        for format in [Format::Binary, Format::Json] {
+            let bytes = vessel.encode(format).expect("encode");
+            assert_eq!(is_binary(&bytes), format == Format::Binary);
+            let decoded = Vessel::decode(&bytes).expect("decode");
+            assert_eq!(to_json(&decoded), json);
+        }
+        assert!(Vessel::decode(b"FLOW").is_err());
+    }
+
+    #[test]
+    fn legacy_settings() {
+        let mut vessel = Vessel::new();
+        vessel.entity_grow().expect("grow");
+        // saved before the format was a setting
+        let mut legacy: serde_json::Value =
+            serde_json::from_str(&to_json(&vessel)).unwrap();
+        legacy["settings"].as_object_mut().unwrap().remove("format");
+        let legacy = legacy.to_string().into_bytes();
+
+        let vessel = Vessel::decode(&legacy).expect("decode");
+        assert_eq!(vessel.settings.format, Format::Json);
+        let bytes = vessel.encode(vessel.settings.format).expect("encode");
+        assert!(!is_binary(&bytes));
+        let loaded = Vessel::decode(&bytes).expect("decode");
+        assert_eq!(loaded.settings.format, Format::Json);
+    }
+
     #[test]
     fn saveload() -> Result<(), &'static str> {
         let f = Vessel::load();
@@ -0,0 +1,526 @@
+use flow_arena::{FlowBase, Node, PageIndex};
+use serde::de::{
+    self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess,
+    Visitor,
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Read},
+    rc::Rc,
+};
+
+use super::binary::BinaryDeserializer;
+use crate::{
+    is_binary, BinaryError, EntityFlow, EntityId, EntityIdFactory, EntityNode,
+    Glass, LoadError, Settings, Vessel, BINARY_MAGIC,
+};
+
+/// What of the flow a streaming load keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadScope {
+    All,
+    /// the entity and all it owns, with the entity as a root.
+    Subtree(EntityId),
+    /// the roots and as many levels below, e.g. `Levels(0)` for the roots.
+    Levels(usize),
+}
+
+/// How far a streaming load has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadProgress {
+    /// bytes read so far.
+    pub bytes: u64,
+    /// nodes read so far, kept or not.
+    pub nodes: usize,
+    /// nodes kept so far.
+    pub kept: usize,
+    /// nodes in the whole flow, if the file tells.
+    pub total: Option<usize>,
+}
+
+impl Vessel {
+    /// Loads a vessel in either format from a reader, node by node. Only
+    /// the nodes in scope are kept, and the reading stops as soon as all of
+    /// them are read; progress is told after every node, and once done.
+    ///
+    /// The nodes come level by level in a file saved by now, so a scope is
+    /// soon read. A file saved before is read through, and the scope cut
+    /// out at the end.
+    ///
+    /// A vessel loaded in part is meant for a preview; it's marked as
+    /// partial, and refused when saved. A subtree whose root isn't found
+    /// fails to load.
+    pub fn load_from<R: Read>(
+        reader: R,
+        scope: LoadScope,
+        mut progress: impl FnMut(&LoadProgress),
+    ) -> Result<Vessel, LoadError> {
+        let bytes = Rc::new(Cell::new(0));
+        let mut reader = Counted {
+            inner: io::BufReader::new(reader),
+            bytes: bytes.clone(),
+        };
+        let mut head = Vec::new();
+        (&mut reader)
+            .take(BINARY_MAGIC.len() as u64)
+            .read_to_end(&mut head)
+            .map_err(|_| LoadError::FileError)?;
+        let reader = head.as_slice().chain(reader);
+
+        let mut streamer = Streamer::new(scope, bytes, &mut progress);
+        let result = if is_binary(&head) {
+            let mut de = BinaryDeserializer::from_reader(reader)
+                .map_err(|_| LoadError::FormatError)?;
+            VesselSeed(&mut streamer)
+                .deserialize(&mut de)
+                .and_then(|()| match de.finished()? {
+                    true => Ok(()),
+                    false => Err(BinaryError::Length),
+                })
+                .map_err(|_| ())
+        } else {
+            let mut de = serde_json::Deserializer::from_reader(reader);
+            VesselSeed(&mut streamer)
+                .deserialize(&mut de)
+                .and_then(|()| de.end())
+                .map_err(|_| ())
+        };
+        if result.is_err() && !streamer.stopped {
+            return Err(LoadError::FormatError);
+        }
+        streamer.finish()
+    }
+}
+
+/// counts the bytes read through it.
+struct Counted<R> {
+    inner: R,
+    bytes: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.set(self.bytes.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Takes the parts of a vessel as they're read, and decides which nodes
+/// to keep and when to stop.
+struct Streamer<'a> {
+    scope: LoadScope,
+    bytes: Rc<Cell<u64>>,
+    progress: &'a mut dyn FnMut(&LoadProgress),
+    state: LoadProgress,
+    /// the number of nodes on each level, while the nodes come level by
+    /// level.
+    levels: Option<Vec<usize>>,
+    seen: HashSet<EntityId>,
+    kept: HashSet<EntityId>,
+    /// the children of the nodes kept, not seen yet.
+    pending: HashSet<EntityId>,
+    nodes: Vec<EntityNode>,
+    factory: Option<EntityIdFactory>,
+    glass: Option<Glass>,
+    settings: Option<Settings>,
+    pages: Option<PageIndex<EntityId>>,
+    /// whether the reading stopped before the end.
+    stopped: bool,
+}
+
+impl<'a> Streamer<'a> {
+    fn new(
+        scope: LoadScope,
+        bytes: Rc<Cell<u64>>,
+        progress: &'a mut dyn FnMut(&LoadProgress),
+    ) -> Self {
+        Self {
+            scope,
+            bytes,
+            progress,
+            state: LoadProgress::default(),
+            levels: None,
+            seen: HashSet::new(),
+            kept: HashSet::new(),
+            pending: HashSet::new(),
+            nodes: Vec::new(),
+            factory: None,
+            glass: None,
+            settings: None,
+            pages: None,
+            stopped: false,
+        }
+    }
+    fn levels(&mut self, levels: Vec<usize>) {
+        self.state.total = Some(levels.iter().sum());
+        self.levels = Some(levels);
+    }
+    /// takes a node in; returns whether to read on.
+    fn push(&mut self, node: EntityNode) -> bool {
+        let id = *node.id();
+        // a node before its owner means the levels were a lie; then keep
+        // everything from here on, which is safe as nothing in the scope
+        // can have been passed by yet.
+        if let Some(parent) = node.parent() {
+            if !self.seen.contains(&parent) {
+                self.levels = None;
+            }
+        }
+        self.seen.insert(id);
+        self.pending.remove(&id);
+        self.state.nodes += 1;
+
+        let keep = match (self.scope, &self.levels) {
+            (LoadScope::Subtree(root), Some(_)) => {
+                id == root
+                    || node.parent().is_some_and(|p| self.kept.contains(&p))
+            }
+            _ => true,
+        };
+        if keep {
+            for child in node.children() {
+                if !self.seen.contains(child) {
+                    self.pending.insert(*child);
+                }
+            }
+            self.kept.insert(id);
+            self.nodes.push(node);
+            self.state.kept += 1;
+        }
+        self.state.bytes = self.bytes.get();
+        (self.progress)(&self.state);
+
+        let levels = match &self.levels {
+            Some(levels) => levels,
+            None => return true,
+        };
+        match self.scope {
+            LoadScope::All => true,
+            LoadScope::Subtree(root) => {
+                !self.kept.contains(&root) || !self.pending.is_empty()
+            }
+            LoadScope::Levels(depth) => {
+                self.state.nodes < levels.iter().take(depth + 1).sum()
+            }
+        }
+    }
+    /// the vessel of all read, with the flow cut to the scope.
+    fn finish(mut self) -> Result<Vessel, LoadError> {
+        self.state.bytes = self.bytes.get();
+        (self.progress)(&self.state);
+        let mut nodes: HashMap<EntityId, EntityNode> = self
+            .nodes
+            .into_iter()
+            .map(|node| (*node.id(), node))
+            .collect();
+        let keep = match self.scope {
+            LoadScope::All => None,
+            LoadScope::Subtree(root) => {
+                if !nodes.contains_key(&root) {
+                    return Err(LoadError::NotExistRoot(root));
+                }
+                Some(owned(&nodes, vec![root], usize::MAX))
+            }
+            LoadScope::Levels(depth) => {
+                let roots = nodes
+                    .values()
+                    .filter(|node| node.parent().is_none())
+                    .map(|node| *node.id())
+                    .collect();
+                Some(owned(&nodes, roots, depth))
+            }
+        };
+        if let Some(keep) = keep {
+            nodes.retain(|id, _| keep.contains(id));
+            for node in nodes.values_mut() {
+                if !node.parent().is_some_and(|p| keep.contains(&p)) {
+                    node.parent_set_none();
+                }
+                node.children_ref_mut().retain(|id| keep.contains(id));
+                node.entity.relations.retain(|id| keep.contains(id));
+            }
+        }
+        let mut vessel = Vessel::new();
+        vessel.factory = self.factory.unwrap_or_default();
+        vessel.glass = self.glass.unwrap_or_default();
+        vessel.settings = self.settings.unwrap_or_default();
+        vessel.pages = self.pages.unwrap_or_default();
+        vessel.flow = nodes.into_values().collect::<EntityFlow>();
+        vessel.partial = self.scope != LoadScope::All;
+        if vessel.partial {
+            let flow = &vessel.flow;
+            vessel.pages.retain(|stub| flow.contains_node(stub));
+        }
+        Ok(vessel)
+    }
+}
+
+/// the nodes owned from start, start included, down to depth levels.
+fn owned(
+    nodes: &HashMap<EntityId, EntityNode>,
+    start: Vec<EntityId>,
+    depth: usize,
+) -> HashSet<EntityId> {
+    let mut owned = HashSet::new();
+    let mut level = start;
+    for _ in 0..=depth {
+        if level.is_empty() {
+            break;
+        }
+        let next = level
+            .iter()
+            .flat_map(|owner| {
+                nodes[owner].children().iter().filter(move |child| {
+                    nodes
+                        .get(child)
+                        .is_some_and(|node| node.parent() == Some(*owner))
+                })
+            })
+            .cloned()
+            .collect();
+        owned.extend(level);
+        level = next;
+    }
+    owned
+}
+
+struct VesselSeed<'s, 'a>(&'s mut Streamer<'a>);
+
+impl<'de> DeserializeSeed<'de> for VesselSeed<'_, '_> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        const FIELDS: &[&str] =
+            &["factory", "glass", "settings", "pages", "flow"];
+        deserializer.deserialize_struct("Vessel", FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for VesselSeed<'_, '_> {
+    type Value = ();
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct Vessel")
+    }
+    fn visit_map<V: MapAccess<'de>>(self, mut map: V) -> Result<(), V::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "factory" => self.0.factory = Some(map.next_value()?),
+                "glass" => self.0.glass = Some(map.next_value()?),
+                "settings" => self.0.settings = Some(map.next_value()?),
+                "pages" => self.0.pages = Some(map.next_value()?),
+                "flow" => map.next_value_seed(FlowSeed(&mut *self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FlowSeed<'s, 'a>(&'s mut Streamer<'a>);
+
+impl<'de> DeserializeSeed<'de> for FlowSeed<'_, '_> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        const FIELDS: &[&str] = &["levels", "node_map"];
+        deserializer.deserialize_struct("Flow", FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for FlowSeed<'_, '_> {
+    type Value = ();
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct FlowArena")
+    }
+    fn visit_map<V: MapAccess<'de>>(self, mut map: V) -> Result<(), V::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "levels" => self.0.levels(map.next_value()?),
+                "node_map" => map.next_value_seed(NodesSeed(&mut *self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct NodesSeed<'s, 'a>(&'s mut Streamer<'a>);
+
+impl<'de> DeserializeSeed<'de> for NodesSeed<'_, '_> {
+    type Value = ();
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodesSeed<'_, '_> {
+    type Value = ();
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of nodes")
+    }
+    fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<(), V::Error> {
+        while let Some(node) = seq.next_element::<EntityNode>()? {
+            if !self.0.push(node) {
+                // nothing else is needed; cut the reading short
+                self.0.stopped = true;
+                return Err(de::Error::custom("stopped"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use flow_arena::{FlowBase, FlowCheck};
+
+    /// two roots, each owning 3 children owning 3 each; one link across.
+    fn vessel() -> (Vessel, Vec<EntityId>) {
+        let mut vessel = Vessel::new();
+        let mut roots = Vec::new();
+        for _ in 0..2 {
+            let root = vessel.entity_grow().expect("grow");
+            for _ in 0..3 {
+                let child = vessel.entity_grow_devote(root, 0).expect("grow");
+                for _ in 0..3 {
+                    vessel.entity_grow_devote(child, 0).expect("grow");
+                }
+            }
+            roots.push(root);
+        }
+        let far = vessel.entity_id_direct(&roots[1])[0];
+        let near = vessel.entity_id_direct(&roots[0])[1];
+        vessel
+            .entity_link_as(far, near, 0, Relation::SeeAlso)
+            .expect("link");
+        (vessel, roots)
+    }
+
+    fn load(bytes: &[u8], scope: LoadScope) -> (Vessel, LoadProgress) {
+        let mut last = LoadProgress::default();
+        let vessel = Vessel::load_from(bytes, scope, |progress| {
+            assert!(progress.nodes >= last.nodes);
+            last = *progress;
+        })
+        .expect("load");
+        vessel.flow.check().expect("whole");
+        (vessel, last)
+    }
+
+    fn ids(vessel: &Vessel) -> HashSet<EntityId> {
+        vessel.entity_id_all().into_iter().collect()
+    }
+
+    #[test]
+    fn scopes() {
+        let (vessel, roots) = vessel();
+        let total = vessel.entity_id_all().len();
+        let sub = vessel.entity_id_direct(&roots[0])[1];
+        // the same flow, saved before the nodes came level by level
+        let mut unordered: serde_json::Value =
+            serde_json::from_str(&to_json(&vessel)).unwrap();
+        let flow = unordered["flow"].as_object_mut().unwrap();
+        flow.remove("levels");
+        flow["node_map"].as_array_mut().unwrap().reverse();
+        let unordered = unordered.to_string().into_bytes();
+
+        let files = [
+            (vessel.encode(Format::Binary).unwrap(), true),
+            (vessel.encode(Format::Json).unwrap(), true),
+            (unordered, false),
+        ];
+        for (bytes, ordered) in files {
+            let (all, progress) = load(&bytes, LoadScope::All);
+            assert_eq!(all.flow, vessel.flow);
+            assert_eq!(to_json(&all.glass), to_json(&vessel.glass));
+            assert_eq!(progress.nodes, total);
+            assert_eq!(progress.bytes, bytes.len() as u64);
+            assert_eq!(progress.total.is_some(), ordered);
+
+            let (top, progress) = load(&bytes, LoadScope::Levels(1));
+            let expected: HashSet<EntityId> = roots
+                .iter()
+                .flat_map(|root| vessel.entity_id_direct(root))
+                .chain(roots.iter().cloned())
+                .collect();
+            assert_eq!(ids(&top), expected);
+            // the link across is kept, with its relation
+            let near = top.entity(&vessel.entity_id_direct(&roots[0])[1]);
+            assert!(!near.unwrap().relations.is_empty());
+            assert_eq!(progress.nodes < total, ordered);
+
+            let (part, progress) = load(&bytes, LoadScope::Subtree(sub));
+            assert_eq!(ids(&part), vessel.entity_ownership(&sub));
+            assert_eq!(part.flow.parent(&sub), None);
+            assert_eq!(progress.nodes < total, ordered);
+            assert_eq!(progress.kept, if ordered { 4 } else { total });
+
+            // only the whole is saved
+            assert!(!all.is_partial());
+            all.encode(Format::Binary).expect("encode");
+            for part in [top, part] {
+                assert!(part.is_partial());
+                assert!(matches!(
+                    part.encode(Format::Json),
+                    Err(SaveError::Partial)
+                ));
+                assert!(part.clone().is_partial());
+            }
+            let gone = EntityIdFactory::default().time_id();
+            let scope = LoadScope::Subtree(gone);
+            assert!(matches!(
+                Vessel::load_from(bytes.as_slice(), scope, |_| {}),
+                Err(LoadError::NotExistRoot(id)) if id == gone
+            ));
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let (mut vessel, roots) = vessel();
+        let mut pager = HashMap::new();
+        let stubs: Vec<EntityId> = roots
+            .iter()
+            .map(|root| vessel.entity_id_direct(root)[2])
+            .collect();
+        for stub in stubs.iter() {
+            vessel.entity_unload(stub, &mut pager).expect("unload");
+        }
+        let bytes = vessel.encode(Format::Binary).unwrap();
+
+        let (all, _) = load(&bytes, LoadScope::All);
+        assert_eq!(all.stubs().count(), 2);
+        // only the stubs kept in the flow cut short
+        let (part, _) = load(&bytes, LoadScope::Subtree(roots[0]));
+        assert_eq!(part.stubs().collect::<Vec<_>>(), vec![&stubs[0]]);
+        let (top, _) = load(&bytes, LoadScope::Levels(0));
+        assert!(top.stubs().next().is_none());
+    }
+
+    #[test]
+    fn broken() {
+        let (vessel, _) = vessel();
+        let bytes = vessel.encode(Format::Binary).unwrap();
+        let cut = &bytes[..bytes.len() / 2];
+        assert!(Vessel::load_from(cut, LoadScope::All, |_| {}).is_err());
+        let json = vessel.encode(Format::Json).unwrap();
+        let cut = &json[..json.len() / 2];
+        assert!(Vessel::load_from(cut, LoadScope::All, |_| {}).is_err());
+    }
+}
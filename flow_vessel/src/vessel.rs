@@ -1,25 +1,44 @@
 use flow_arena::{
     Direction, FlowArena, FlowBase, FlowDevote, FlowError, FlowLink, FlowMap,
-    FlowNode, FlowShift, Node,
+    FlowNode, FlowShift, FlowTransact, Node, OuterLinks, PageIndex, Traverse,
+    TraverseMode, TraverseOrder,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fmt::Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
 
 use crate::Filter;
 
-use super::{Entity, EntityId, EntityIdFactory, Glass, Settings};
+use super::{
+    Entity, EntityId, EntityIdFactory, Glass, History, Hooks, Relation,
+    Settings,
+};
 
 pub type EntityNode = FlowNode<EntityId, Entity>;
 pub type EntityFlow = FlowArena<EntityId, FlowNode<EntityId, Entity>>;
 
 #[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Vessel {
-    pub(crate) flow: EntityFlow,
-    factory: EntityIdFactory,
+    pub(crate) factory: EntityIdFactory,
     #[serde(default)]
     pub glass: Glass,
     #[serde(default)]
     pub settings: Settings,
+    /// the stubs of the subtrees unloaded; see `Vessel::entity_unload`.
+    #[serde(default, skip_serializing_if = "PageIndex::is_empty")]
+    pub(crate) pages: PageIndex<EntityId>,
+    /// written last, so that a streaming load has all the rest by the
+    /// time it stops half way through the nodes.
+    pub(crate) flow: EntityFlow,
+    #[serde(skip)]
+    pub(crate) history: History,
+    #[serde(skip)]
+    pub(crate) hooks: Hooks,
+    /// whether the flow was loaded in part; see `Vessel::is_partial`.
+    #[serde(skip)]
+    pub(crate) partial: bool,
 }
 
 impl Vessel {
@@ -29,8 +48,18 @@ impl Vessel {
             factory: EntityIdFactory::default(),
             glass: Glass::default(),
             settings: Settings::default(),
+            pages: PageIndex::default(),
+            history: History::default(),
+            hooks: Hooks::default(),
+            partial: false,
         }
     }
+    /// whether the flow was loaded in part, e.g. by `Vessel::load_from`
+    /// with a narrower scope than `LoadScope::All`. Such a vessel is for a
+    /// preview, and is refused when saved, lest it replace the whole.
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
 }
 
 impl Debug for Vessel {
@@ -57,7 +86,9 @@ impl Vessel {
     }
     /// get all entity_ids under id directly
     pub fn entity_id_direct(&self, obj: &EntityId) -> Vec<EntityId> {
-        self.flow.node(obj).map_or(Vec::new(), |x| x.children())
+        self.flow
+            .node(obj)
+            .map_or(Vec::new(), |x| x.children().to_vec())
     }
     /// get all entity_ids under id recrusively
     pub fn entity_offspring(&self, obj: &EntityId) -> HashSet<EntityId> {
@@ -66,6 +97,47 @@ impl Vessel {
     pub fn entity_ownership(&self, obj: &EntityId) -> HashSet<EntityId> {
         self.flow.node_ownership_set(obj)
     }
+    /// get all entity_ids linking to id, including its owner; sorted
+    pub fn entity_linked_from(&self, obj: &EntityId) -> Vec<EntityId> {
+        let mut vec = self.flow.linked_from(obj);
+        vec.sort();
+        vec
+    }
+    /// get the owners from the orphan down to id, including itself
+    pub fn entity_breadcrumb(&self, obj: &EntityId) -> Vec<EntityId> {
+        if !self.flow.contains_node(obj) {
+            return Vec::new();
+        }
+        let mut vec: Vec<EntityId> = self.flow.ancestors(obj).collect();
+        vec.reverse();
+        vec.push(*obj);
+        vec
+    }
+    /// get the nearest entity owning both recursively
+    pub fn entity_common_owner(
+        &self,
+        obj: &EntityId,
+        dude: &EntityId,
+    ) -> Option<EntityId> {
+        self.flow.lowest_common_owner(obj, dude)
+    }
+    /// traverse all entities under id in order, including itself
+    pub fn entity_traverse(
+        &self,
+        obj: &EntityId,
+        order: TraverseOrder,
+        mode: TraverseMode,
+    ) -> Traverse<'_, EntityFlow> {
+        self.flow.traverse(obj, order, mode)
+    }
+    /// traverse all entities in order, starting from the orphans
+    pub fn entity_traverse_all(
+        &self,
+        order: TraverseOrder,
+        mode: TraverseMode,
+    ) -> Traverse<'_, EntityFlow> {
+        self.flow.traverse_orphan(order, mode)
+    }
     /// pick entities with all filters satisfied
     pub fn entity_matched_all(&self, filters: &Vec<Filter>) -> Vec<EntityId> {
         self.flow
@@ -108,14 +180,14 @@ impl Vessel {
     /// let id = vessel.entity_grow().unwrap();
     /// println!("{:?}", vessel.entity_mut(&id));
     /// ```
-    pub fn entity_grow(&mut self) -> Result<EntityId, FlowError> {
+    pub fn entity_grow(&mut self) -> Result<EntityId, FlowError<EntityId>> {
         let entity = Entity::new_rotate(&mut self.factory);
         self.entity_flow_grow(entity)
     }
     fn entity_flow_grow(
         &mut self,
         entity: Entity,
-    ) -> Result<EntityId, FlowError> {
+    ) -> Result<EntityId, FlowError<EntityId>> {
         let id = entity.id().clone();
         self.flow.grow(FlowNode::from_id(id.clone(), entity))?;
         Ok(id)
@@ -133,66 +205,305 @@ impl Vessel {
         obj: EntityId,
         owner: EntityId,
         idx: usize,
-    ) -> Result<(), FlowError> {
-        self.flow.link(&obj, &owner, idx)
+    ) -> Result<(), FlowError<EntityId>> {
+        let linked = self.flow.children(&owner).contains(&obj);
+        self.flow.link(&obj, &owner, idx)?;
+        // a new link is plain, whatever the label of a link gone before;
+        // a child linked already keeps its label
+        if !linked {
+            if let Some(node) = self.flow.node_mut(&owner) {
+                node.entity.relations.set(obj, None);
+            }
+        }
+        Ok(())
     }
     pub fn entity_devote(
         &mut self,
         obj: EntityId,
         owner: EntityId,
         idx: usize,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<EntityId>> {
         self.flow.devote(&obj, &owner, idx)
     }
+    /// grows and devotes in a transaction; nothing is grown if devote fails.
     pub fn entity_grow_devote(
         &mut self,
         owner: EntityId,
         idx: usize,
-    ) -> Result<EntityId, FlowError> {
-        let obj = self.entity_grow()?;
-        self.entity_devote(obj, owner, idx)?;
-        Ok(obj)
+    ) -> Result<EntityId, FlowError<EntityId>> {
+        let entity = Entity::new_rotate(&mut self.factory);
+        self.entity_flow_grow_devote(entity, owner, idx)
     }
-    fn entity_duplicate(&mut self, obj: EntityId, dude: EntityId) {
-        let dude = self.entity(&dude).cloned().unwrap_or_default();
-        self.entity_mut(&obj).map(|obj| obj.duplicate_from(&dude));
+    fn entity_flow_grow_devote(
+        &mut self,
+        entity: Entity,
+        owner: EntityId,
+        idx: usize,
+    ) -> Result<EntityId, FlowError<EntityId>> {
+        let id = *entity.id();
+        self.flow.transact(|flow| {
+            flow.grow(FlowNode::from_id(id, entity))?;
+            flow.devote(&id, &owner, idx)
+        })?;
+        Ok(id)
+    }
+    /// grows an entity right after `after` among the children of owner, or
+    /// first if None, under a stable position key; all or nothing.
+    ///
+    /// Unlike an index, the key keeps the entity in place when merged
+    /// with edits made apart.
+    pub fn entity_grow_devote_after(
+        &mut self,
+        owner: EntityId,
+        after: Option<EntityId>,
+    ) -> Result<EntityId, FlowError<EntityId>> {
+        let entity = Entity::new_rotate(&mut self.factory);
+        let id = *entity.id();
+        self.flow.transact(|flow| {
+            let key = flow.position_after(&owner, after.as_ref())?;
+            flow.grow(FlowNode::from_id(id, entity))?;
+            flow.devote_at(&id, &owner, key)
+        })?;
+        Ok(id)
+    }
+    /// devotes obj right after `after` among the children of owner, or
+    /// first if None, under a stable position key; all or nothing.
+    pub fn entity_devote_after(
+        &mut self,
+        obj: EntityId,
+        owner: EntityId,
+        after: Option<EntityId>,
+    ) -> Result<(), FlowError<EntityId>> {
+        self.flow.transact(|flow| {
+            let key = flow.position_after(&owner, after.as_ref())?;
+            flow.devote_at(&obj, &owner, key)
+        })
     }
     /// final product function: entity_add, duplicates its dude
-    /// and devotes to its owner.
+    /// and devotes to its owner; all or nothing.
     pub fn entity_add(
         &mut self,
         dude: EntityId,
         owner: EntityId,
         idx: usize,
-    ) -> Result<EntityId, FlowError> {
-        let obj = self.entity_grow_devote(owner, idx)?;
-        self.entity_duplicate(obj, dude);
-        Ok(obj)
+    ) -> Result<EntityId, FlowError<EntityId>> {
+        let dude = self.entity(&dude).cloned().unwrap_or_default();
+        let mut entity = Entity::new_rotate(&mut self.factory);
+        entity.duplicate_from(&dude);
+        self.entity_flow_grow_devote(entity, owner, idx)
+    }
+    /// duplicates obj along with all it owns under fresh ids, and puts
+    /// the copy right after obj; returns the copy of obj. All or nothing.
+    ///
+    /// The links within the subtree point to the copies, while the ones
+    /// leaving it are kept or dropped as `outer` says.
+    pub fn entity_duplicate_subtree(
+        &mut self,
+        obj: EntityId,
+        outer: OuterLinks,
+    ) -> Result<EntityId, FlowError<EntityId>> {
+        let factory = &mut self.factory;
+        let kept = |id: &EntityId| match outer {
+            OuterLinks::Keep => Some(*id),
+            OuterLinks::Drop => None,
+        };
+        let mut fresh = HashMap::new();
+        let (copy, id) = self.flow.snap_fresh(
+            &obj,
+            |node| {
+                let entity = node.entity.duplicate_rotate(factory);
+                fresh.insert(*node.id(), *entity.id());
+                FlowNode::from_id(*entity.id(), entity)
+            },
+            outer,
+        )?;
+        let nodes: Vec<EntityNode> = copy
+            .node_ownership_set(&id)
+            .iter()
+            .filter_map(|id| copy.node(id).cloned())
+            .map(|mut node| {
+                // the labels follow the links into the copies
+                node.entity
+                    .relations
+                    .remap(|id| fresh.get(id).cloned().or_else(|| kept(id)));
+                node
+            })
+            .collect();
+        let owner = self.flow.parent(&obj);
+        let nth = self.flow.nth_friend(&obj).map_or(0, |nth| nth + 1);
+        self.flow.transact(|flow| {
+            for node in nodes {
+                flow.grow(node)?;
+            }
+            match owner {
+                Some(owner) => flow.devote(&id, &owner, nth),
+                None => Ok(()),
+            }
+        })?;
+        Ok(id)
     }
-    pub fn entity_decay(&mut self, obj: EntityId) -> Result<(), FlowError> {
+    pub fn entity_decay(
+        &mut self,
+        obj: EntityId,
+    ) -> Result<(), FlowError<EntityId>> {
         self.flow.decay(&obj)?;
         Ok(())
     }
-    pub fn entity_erase(&mut self, obj: EntityId) -> Result<(), FlowError> {
+    /// erases obj and all it owns; refused if any of them is a stub.
+    pub fn entity_erase(
+        &mut self,
+        obj: EntityId,
+    ) -> Result<(), FlowError<EntityId>> {
+        self.stub_check(&obj)?;
         self.flow.erase(&obj)?;
         Ok(())
     }
-    /// removes entity from a flow_arena
-    pub fn entity_remove(&mut self, obj: EntityId) -> Result<(), FlowError> {
-        self.flow.decay(&obj)?;
-        self.flow.erase(&obj)?;
+    /// removes entity from a flow_arena; all or nothing, and refused if
+    /// it owns a stub.
+    pub fn entity_remove(
+        &mut self,
+        obj: EntityId,
+    ) -> Result<(), FlowError<EntityId>> {
+        self.stub_check(&obj)?;
+        self.flow.transact(|flow| {
+            flow.decay(&obj)?;
+            flow.erase(&obj)
+        })?;
         self.glass_refresh();
         Ok(())
     }
 }
 
+/// relations
+impl Vessel {
+    /// the relation of the link from owner to obj; None if a plain link or
+    /// not linked at all.
+    pub fn entity_relation(
+        &self,
+        owner: &EntityId,
+        obj: &EntityId,
+    ) -> Option<Relation> {
+        let node = self.node(owner)?;
+        if !node.children().contains(obj) {
+            return None;
+        }
+        node.entity.relations.get(obj)
+    }
+    /// the children of obj linked as relation, in order.
+    pub fn entity_related(
+        &self,
+        obj: &EntityId,
+        relation: Relation,
+    ) -> Vec<EntityId> {
+        self.node(obj).map_or(Vec::new(), |node| {
+            node.children()
+                .iter()
+                .filter(|id| node.entity.relations.get(id) == Some(relation))
+                .cloned()
+                .collect()
+        })
+    }
+    /// the entities linking to obj as relation, sorted by id.
+    pub fn entity_related_from(
+        &self,
+        obj: &EntityId,
+        relation: Relation,
+    ) -> Vec<EntityId> {
+        let mut linkers: Vec<EntityId> = self
+            .flow
+            .linked_from(obj)
+            .into_iter()
+            .filter(|linker| {
+                self.entity_relation(linker, obj) == Some(relation)
+            })
+            .collect();
+        linkers.sort();
+        linkers
+    }
+    /// all the links labeled as relation, as (linker, linked); sorted.
+    pub fn entity_relation_all(
+        &self,
+        relation: Relation,
+    ) -> Vec<(EntityId, EntityId)> {
+        let mut links: Vec<(EntityId, EntityId)> = self
+            .entity_id_all()
+            .into_iter()
+            .flat_map(|linker| {
+                self.entity_related(&linker, relation)
+                    .into_iter()
+                    .map(move |linked| (linker, linked))
+            })
+            .collect();
+        links.sort();
+        links
+    }
+    /// links obj to owner at nth as relation; all or nothing.
+    pub fn entity_link_as(
+        &mut self,
+        obj: EntityId,
+        owner: EntityId,
+        nth: usize,
+        relation: Relation,
+    ) -> Result<(), FlowError<EntityId>> {
+        self.flow.transact(|flow| {
+            flow.link(&obj, &owner, nth)?;
+            let node = flow
+                .node_mut(&owner)
+                .ok_or(FlowError::NotExistOwner { owner })?;
+            node.entity.relations.set(obj, Some(relation));
+            Ok(())
+        })
+    }
+    /// drops the labels of the links gone from ids, e.g. the nodes touched
+    /// by a tube.
+    pub(crate) fn relations_tidy<'a>(
+        &mut self,
+        ids: impl IntoIterator<Item = &'a EntityId>,
+    ) {
+        for id in ids {
+            let stale = self.node(id).is_some_and(|node| {
+                let children = node.children();
+                node.entity
+                    .relations
+                    .iter()
+                    .any(|(x, _)| !children.contains(x))
+            });
+            if let Some(node) = self.flow.node_mut(id).filter(|_| stale) {
+                let children = node.children().to_vec();
+                node.entity.relations.retain(|x| children.contains(x));
+            }
+        }
+    }
+    /// labels the link from owner to obj as relation, or makes it a plain
+    /// link if None; the labels of the children gone are dropped meanwhile.
+    pub fn entity_relabel(
+        &mut self,
+        obj: EntityId,
+        owner: EntityId,
+        relation: Option<Relation>,
+    ) -> Result<(), FlowError<EntityId>> {
+        let node = self
+            .flow
+            .node_mut(&owner)
+            .ok_or(FlowError::NotExistOwner { owner })?;
+        let children = node.children().to_vec();
+        if !children.contains(&obj) {
+            return Err(FlowError::NotExistChild { obj, owner });
+        }
+        let relations = &mut node.entity.relations;
+        relations.retain(|id| children.contains(id));
+        relations.set(obj, relation);
+        Ok(())
+    }
+}
+
 /// indent & move
 impl Vessel {
     pub fn entity_shuttle(
         &mut self,
         obj: &EntityId,
         dir: Direction,
-    ) -> Result<EntityId, FlowError> {
+    ) -> Result<EntityId, FlowError<EntityId>> {
         self.flow.shuttle(obj, dir)
     }
 
@@ -201,7 +512,7 @@ impl Vessel {
         &mut self,
         obj: &EntityId,
         dir: Direction,
-    ) -> Result<(), FlowError> {
+    ) -> Result<(), FlowError<EntityId>> {
         self.flow.migrate_iter(obj, dir)
     }
 }
@@ -217,30 +528,21 @@ impl Vessel {
 /// flow debug prints
 impl Vessel {
     pub fn concise_debug_string(&self) -> String {
-        let objs = self.flow.orphan();
-        objs.into_iter().fold("".to_owned(), |debug_info, obj| {
-            format!("{}\n{}", debug_info, concise_debug_impl(obj, self, 1))
-        })
+        self.entity_traverse_all(TraverseOrder::PreOrder, TraverseMode::Linked)
+            .fold("".to_owned(), |debug_info, visit| {
+                let id_debug = self.entity(&visit.id).map_or("".into(), |x| {
+                    format!("{:?}: {:?}", x.id(), x.face)
+                });
+                let mut prefix_debug = " ".repeat((visit.depth + 1) * 2);
+                prefix_debug.push_str("|--");
+                format!("{}\n{}{}", debug_info, prefix_debug, id_debug)
+            })
     }
     pub fn concise_debug(&self) {
         println!("{}", self.concise_debug_string())
     }
 }
 
-fn concise_debug_impl(obj: EntityId, vessel: &Vessel, prefix: usize) -> String {
-    let id_debug = vessel
-        .entity(&obj)
-        .map_or("".into(), |x| format!("{:?}: {:?}", x.id(), x.face));
-    let children = vessel.node(&obj).map_or(Vec::new(), |node| node.children());
-    let children_debug = children
-        .iter()
-        .map(|x| concise_debug_impl(x.clone(), vessel, prefix + 1))
-        .fold(String::new(), |x, y| format!("{}\n{}", x, y));
-    let mut prefix_debug = " ".repeat(prefix * 2);
-    prefix_debug.push_str("|--");
-    format! {"{}{}{}", prefix_debug, id_debug, children_debug}
-}
-
 #[cfg(test)]
 mod tests {
     use flow_arena::{FlowError, FlowLink};
@@ -271,14 +573,14 @@ mod tests {
         let id = (0..num).filter_map(|_| vessel.entity_grow().ok()).collect();
         (id, vessel)
     }
-    fn has_err(place: &str, fe: Option<FlowError>) {
+    fn has_err(place: &str, fe: Option<FlowError<EntityId>>) {
         if let Some(e) = fe {
             println!("error at {}: {:?}", place, e)
         }
     }
 
     #[test]
-    fn entity_remove() -> Result<(), FlowError> {
+    fn entity_remove() -> Result<(), FlowError<EntityId>> {
         let (id, mut vessel) = make_vessel(5);
         has_err(
             "devote 1 -> 0",
@@ -330,6 +632,17 @@ mod tests {
         println!("{:?}", vessel.flow.node_offspring_set(&id[0]));
     }
     #[test]
+    fn entity_linked_from() {
+        let (id, mut vessel) = make_vessel(3);
+        vessel.flow.devote_push(&id[1], &id[0]).expect("devote");
+        vessel.flow.link_push(&id[1], &id[2]).expect("link");
+        let mut expected = vec![id[0], id[2]];
+        expected.sort();
+        assert_eq!(vessel.entity_linked_from(&id[1]), expected);
+        vessel.entity_remove(id[2]).expect("remove");
+        assert_eq!(vessel.entity_linked_from(&id[1]), vec![id[0]]);
+    }
+    #[test]
     fn serde() {
         let (id, mut vessel) = make_vessel(5);
         vessel.flow.devote_push(&id[1], &id[0]).ok();
@@ -378,12 +691,218 @@ mod tests {
         }
     }
     #[test]
-    fn random_demon_tests() -> Result<(), FlowError> {
+    fn entity_add_atomic() {
+        let (id, mut vessel) = make_vessel(2);
+        vessel.flow.devote_push(&id[1], &id[0]).expect("devote");
+        let flow = vessel.flow.clone();
+        // owner out of the bound; nothing grown
+        assert!(vessel.entity_add(id[1], id[0], 5).is_err());
+        assert_eq!(vessel.flow, flow);
+        // owner not exist; nothing grown
+        let ghost = vessel.factory.incr_id();
+        assert!(vessel.entity_add(id[1], ghost, 0).is_err());
+        assert_eq!(vessel.flow, flow);
+        assert!(vessel.entity_remove(ghost).is_err());
+        assert_eq!(vessel.flow, flow);
+        let obj = vessel.entity_add(id[1], id[0], 1).expect("add");
+        assert_eq!(vessel.flow.children(&id[0]), vec![id[1], obj]);
+    }
+    #[test]
+    fn entity_duplicate_subtree() {
+        let (id, mut vessel) = make_vessel(5);
+        // 0 -> {1 -> {2, 3 -> (4), (2)}}
+        for (obj, owner) in [(1, 0), (2, 1), (3, 1)] {
+            vessel
+                .flow
+                .devote_push(&id[obj], &id[owner])
+                .expect("devote");
+        }
+        vessel.flow.link_push(&id[4], &id[3]).expect("link");
+        vessel.flow.link_push(&id[2], &id[3]).expect("link");
+        vessel.entity_mut(&id[2]).unwrap().face = "two".into();
+
+        let obj = vessel
+            .entity_duplicate_subtree(id[1], OuterLinks::Keep)
+            .expect("duplicate");
+        assert_eq!(vessel.flow.children(&id[0]), vec![id[1], obj]);
+        let copied = vessel.flow.children(&obj);
+        assert_eq!(copied.len(), 2);
+        assert!(copied.iter().all(|x| !id.contains(x)));
+        assert_eq!(vessel.entity(&copied[0]).unwrap().face, "two");
+        assert_eq!(vessel.flow.children(&copied[1]), vec![id[4], copied[0]]);
+
+        let obj = vessel
+            .entity_duplicate_subtree(id[1], OuterLinks::Drop)
+            .expect("duplicate");
+        assert_eq!(vessel.flow.children(&id[0])[1], obj);
+        let copied = vessel.flow.children(&obj);
+        assert_eq!(vessel.flow.children(&copied[1]), vec![copied[0]]);
+        assert_eq!(vessel.entity_id_all().len(), 11);
+        // orphans stay orphans
+        let obj = vessel
+            .entity_duplicate_subtree(id[0], OuterLinks::Drop)
+            .expect("duplicate");
+        assert_eq!(vessel.flow.parent(&obj), None);
+        assert_eq!(vessel.entity_id_all().len(), 21);
+    }
+    #[test]
+    fn entity_relation() {
+        use crate::{Echo, Tube};
+        let (id, mut vessel) = make_vessel(4);
+        // 0 -> {1, 2, (3)}; 1 -> (2)
+        vessel.flow.devote_push(&id[1], &id[0]).expect("devote");
+        vessel.flow.devote_push(&id[2], &id[0]).expect("devote");
+        vessel.flow.link_push(&id[2], &id[1]).expect("link");
+        vessel
+            .entity_link_as(id[3], id[0], 2, Relation::SeeAlso)
+            .expect("link");
+        assert_eq!(
+            vessel.entity_relation(&id[0], &id[3]),
+            Some(Relation::SeeAlso)
+        );
+        assert_eq!(vessel.entity_relation(&id[0], &id[1]), None);
+        // labeling fails all or nothing
+        let flow = vessel.flow.clone();
+        assert!(vessel
+            .entity_link_as(id[3], id[1], 5, Relation::Blocks)
+            .is_err());
+        assert_eq!(vessel.flow, flow);
+
+        let echo = vessel.update_tube(Tube::EntityRelabel {
+            obj: id[2],
+            owner: id[1],
+            relation: Some(Relation::Blocks),
+        });
+        assert!(!matches!(echo, Echo::FlowError(_)));
+        // the label goes along with the link, and doesn't come back
+        vessel
+            .entity_relabel(id[1], id[0], Some(Relation::DuplicateOf))
+            .expect("relabel");
+        vessel.update_tube(Tube::EntityDecay { obj: id[1] });
+        assert!(vessel
+            .entity(&id[0])
+            .unwrap()
+            .relations
+            .get(&id[1])
+            .is_none());
+        vessel.update_tube(Tube::Undo);
+        assert_eq!(
+            vessel.entity_relation(&id[0], &id[1]),
+            Some(Relation::DuplicateOf)
+        );
+        vessel.update_tube(Tube::EntityDecay { obj: id[1] });
+        vessel.update_tube(Tube::EntityLink {
+            obj: id[1],
+            owner: id[0],
+            nth: 0,
+        });
+        assert_eq!(vessel.entity_relation(&id[0], &id[1]), None);
+        // linking a child linked already keeps its label
+        vessel.update_tube(Tube::EntityLink {
+            obj: id[2],
+            owner: id[1],
+            nth: 0,
+        });
+        vessel.entity_link(id[2], id[1], 0).expect("link");
+        assert_eq!(
+            vessel.entity_relation(&id[1], &id[2]),
+            Some(Relation::Blocks)
+        );
+        let echo = vessel.update_tube(Tube::EntityRelabel {
+            obj: id[3],
+            owner: id[1],
+            relation: Some(Relation::Blocks),
+        });
+        assert!(matches!(
+            echo,
+            Echo::FlowError(FlowError::NotExistChild { .. })
+        ));
+        assert_eq!(
+            vessel.entity_related(&id[1], Relation::Blocks),
+            vec![id[2]]
+        );
+        assert_eq!(
+            vessel.entity_related_from(&id[2], Relation::Blocks),
+            vec![id[1]]
+        );
+        assert_eq!(
+            vessel.entity_relation_all(Relation::SeeAlso),
+            vec![(id[0], id[3])]
+        );
+        vessel.update_tube(Tube::EntityRelabel {
+            obj: id[3],
+            owner: id[0],
+            relation: None,
+        });
+        assert!(vessel.entity_relation_all(Relation::SeeAlso).is_empty());
+        vessel.update_tube(Tube::Undo);
+        assert_eq!(
+            vessel.entity_related(&id[0], Relation::SeeAlso),
+            vec![id[3]]
+        );
+
+        // the labels follow the copies
+        let obj = vessel
+            .entity_duplicate_subtree(id[1], OuterLinks::Keep)
+            .expect("duplicate");
+        assert_eq!(vessel.entity_related(&obj, Relation::Blocks), vec![id[2]]);
+        // but not the links dropped
+        let obj = vessel
+            .entity_duplicate_subtree(id[1], OuterLinks::Drop)
+            .expect("duplicate");
+        assert!(vessel.entity(&obj).unwrap().relations.is_empty());
+
+        // survives serialization, and plain old data loads as plain links
+        let json = serde_json::to_string(&vessel).expect("serialize");
+        let loaded: Vessel = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(loaded.flow, vessel.flow);
+        let plain = serde_json::to_string(&vessel.entity(&id[3])).unwrap();
+        assert!(!plain.contains("relations"));
+        let entity: Entity = serde_json::from_str(&plain).expect("load");
+        assert!(entity.relations.is_empty());
+    }
+    #[test]
+    fn entity_after() {
+        use crate::{Echo, Tube};
+        let (id, mut vessel) = make_vessel(1);
+        let add = |vessel: &mut Vessel, after: Option<EntityId>| match vessel
+            .update_tube(Tube::EntityAddAfter {
+                owner: id[0],
+                after,
+            }) {
+            Echo::SendObj(obj) => obj,
+            echo => panic!("{:?}", echo),
+        };
+        let a = add(&mut vessel, None);
+        let c = add(&mut vessel, Some(a));
+        let b = add(&mut vessel, Some(a));
+        assert_eq!(vessel.entity_id_direct(&id[0]), vec![a, b, c]);
+        // the index-based ones still work alongside
+        let d = vessel.entity_grow_devote(id[0], 0).expect("grow");
+        vessel.update_tube(Tube::EntityDevoteAfter {
+            obj: d,
+            owner: id[0],
+            after: Some(b),
+        });
+        assert_eq!(vessel.entity_id_direct(&id[0]), vec![a, b, d, c]);
+        let echo = vessel.update_tube(Tube::EntityAddAfter {
+            owner: id[0],
+            after: Some(id[0]),
+        });
+        assert!(matches!(echo, Echo::FlowError(_)));
+        assert_eq!(vessel.entity_id_all().len(), 5);
+        // the keys survive serialization
+        let json = serde_json::to_string(&vessel).expect("serialize");
+        let loaded: Vessel = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(loaded.flow, vessel.flow);
+    }
+    #[test]
+    fn random_demon_tests() -> Result<(), FlowError<EntityId>> {
         let length = 4096;
         let quiet = true;
         let func_set = [
             // grow
-            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError> {
+            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError<EntityId>> {
                 print!("Grow. ");
                 let obj = vessel.entity_grow()?;
                 println!("{:?}", obj);
@@ -391,7 +910,7 @@ mod tests {
                 Ok(())
             },
             // devote
-            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError> {
+            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError<EntityId>> {
                 let obj_owner = retrive_random_2(&id).map(|(i, j)| (id[i], id[j]));
                 match obj_owner {
                     Some((obj, owner)) => {
@@ -403,7 +922,7 @@ mod tests {
                 Ok(())
             },
             // remove
-            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError> {
+            |(id, vessel): (&mut Vec<EntityId>, &mut Vessel)| -> Result<(), FlowError<EntityId>> {
                 let idx = retrive_random(&id);
                 let obj = idx.map(|i| id[i]);
                 match obj {